@@ -0,0 +1,119 @@
+#![feature(test)]
+
+extern crate boolean_search;
+extern crate rand;
+extern crate test;
+
+use std::mem;
+
+use rand::Rng;
+use test::{Bencher, black_box};
+
+use boolean_search::{Codec, CompressedPosting};
+
+/// Generate a postings list with random gaps in [1, max_gap].
+fn random_posting(len: usize, max_gap: u64) -> Vec<u64> {
+    let mut rng = rand::thread_rng();
+    let mut doc = 0;
+
+    (0..len)
+        .map(|_| {
+            doc += rng.gen_range(1, max_gap + 1);
+            doc
+        })
+        .collect()
+}
+
+fn encode_bench(b: &mut Bencher, codec: Codec, max_gap: u64) {
+    let docs = random_posting(100000, max_gap);
+    b.bytes = (docs.len() * mem::size_of::<u64>()) as u64;
+
+    b.iter(|| CompressedPosting::encode(&docs, codec))
+}
+
+fn decode_bench(b: &mut Bencher, codec: Codec, max_gap: u64) {
+    let docs = random_posting(100000, max_gap);
+    let compressed = CompressedPosting::encode(&docs, codec);
+
+    // Use the uncompressed size, so that throughput can be compared to
+    // raw_iter.
+    b.bytes = (docs.len() * mem::size_of::<u64>()) as u64;
+
+    b.iter(|| {
+        for doc in compressed.iter() {
+            black_box(doc);
+        }
+    })
+}
+
+#[bench]
+fn raw_iter(b: &mut Bencher) {
+    let docs = random_posting(100000, 16);
+    b.bytes = (docs.len() * mem::size_of::<u64>()) as u64;
+
+    b.iter(|| {
+        for doc in &docs {
+            black_box(doc);
+        }
+    })
+}
+
+#[bench]
+fn vbyte_encode_dense(b: &mut Bencher) {
+    encode_bench(b, Codec::VByte, 16)
+}
+
+#[bench]
+fn vbyte_encode_sparse(b: &mut Bencher) {
+    encode_bench(b, Codec::VByte, 100000)
+}
+
+#[bench]
+fn vbyte_decode_dense(b: &mut Bencher) {
+    decode_bench(b, Codec::VByte, 16)
+}
+
+#[bench]
+fn vbyte_decode_sparse(b: &mut Bencher) {
+    decode_bench(b, Codec::VByte, 100000)
+}
+
+#[bench]
+fn gamma_encode_dense(b: &mut Bencher) {
+    encode_bench(b, Codec::Gamma, 16)
+}
+
+#[bench]
+fn gamma_encode_sparse(b: &mut Bencher) {
+    encode_bench(b, Codec::Gamma, 100000)
+}
+
+#[bench]
+fn gamma_decode_dense(b: &mut Bencher) {
+    decode_bench(b, Codec::Gamma, 16)
+}
+
+#[bench]
+fn gamma_decode_sparse(b: &mut Bencher) {
+    decode_bench(b, Codec::Gamma, 100000)
+}
+
+#[bench]
+fn delta_encode_dense(b: &mut Bencher) {
+    encode_bench(b, Codec::Delta, 16)
+}
+
+#[bench]
+fn delta_encode_sparse(b: &mut Bencher) {
+    encode_bench(b, Codec::Delta, 100000)
+}
+
+#[bench]
+fn delta_decode_dense(b: &mut Bencher) {
+    decode_bench(b, Codec::Delta, 16)
+}
+
+#[bench]
+fn delta_decode_sparse(b: &mut Bencher) {
+    decode_bench(b, Codec::Delta, 100000)
+}
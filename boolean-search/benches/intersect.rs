@@ -0,0 +1,94 @@
+#![feature(test)]
+
+extern crate boolean_search;
+extern crate rand;
+extern crate test;
+
+use rand::Rng;
+use test::Bencher;
+
+use boolean_search::{Codec, CompressedPosting, IntersectStrategy, InvertedIndex,
+                     InvertedIndexMut, MemoryIndex};
+
+/// Generate a postings list with random gaps in [1, max_gap].
+fn random_docs(len: usize, max_gap: u64) -> Vec<u64> {
+    let mut rng = rand::thread_rng();
+    let mut doc = 0;
+
+    (0..len)
+        .map(|_| {
+            doc += rng.gen_range(1, max_gap + 1);
+            doc
+        })
+        .collect()
+}
+
+fn intersect_bench(b: &mut Bencher, strategy: IntersectStrategy, short_len: usize) {
+    // Both lists cover roughly the same range of documents.
+    let mut index = MemoryIndex::new();
+    index.add_postings_list("long", random_docs(100000, 10));
+    index.add_postings_list("short", random_docs(short_len, 1000000 / short_len as u64));
+
+    let long = index.posting("long").unwrap();
+    let short = index.posting("short").unwrap();
+
+    b.iter(|| short.intersect_with(&long, strategy))
+}
+
+fn compressed_intersect_bench(b: &mut Bencher, codec: Codec, short_len: usize) {
+    let long = CompressedPosting::encode(&random_docs(100000, 10), codec);
+    let short = CompressedPosting::encode(&random_docs(short_len, 1000000 / short_len as u64),
+                                          codec);
+
+    b.iter(|| short.intersect(&long))
+}
+
+#[bench]
+fn intersect_linear_100(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::Linear, 100)
+}
+
+#[bench]
+fn intersect_linear_10000(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::Linear, 10000)
+}
+
+#[bench]
+fn intersect_binsearch_100(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::BinarySearch, 100)
+}
+
+#[bench]
+fn intersect_binsearch_10000(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::BinarySearch, 10000)
+}
+
+#[bench]
+fn intersect_galloping_100(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::Galloping, 100)
+}
+
+#[bench]
+fn intersect_galloping_10000(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::Galloping, 10000)
+}
+
+#[bench]
+fn intersect_skip_100(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::SkipPointers(None), 100)
+}
+
+#[bench]
+fn intersect_skip_10000(b: &mut Bencher) {
+    intersect_bench(b, IntersectStrategy::SkipPointers(None), 10000)
+}
+
+#[bench]
+fn intersect_vbyte_skip_100(b: &mut Bencher) {
+    compressed_intersect_bench(b, Codec::VByte, 100)
+}
+
+#[bench]
+fn intersect_vbyte_skip_10000(b: &mut Bencher) {
+    compressed_intersect_bench(b, Codec::VByte, 10000)
+}
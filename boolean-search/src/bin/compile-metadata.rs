@@ -0,0 +1,67 @@
+#[macro_use]
+extern crate boolean_search;
+extern crate getopts;
+extern crate stdinout;
+
+use std::env::args;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process;
+
+use getopts::Options;
+use stdinout::*;
+
+use boolean_search::{DocLengths, MemoryMetadata, or_exit};
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] [METADATA_FILE] [OUTPUT_FILE]\n\nConvert document \
+                         metadata in the text format to the binary format, with random access.",
+                        program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("l",
+                "lengths",
+                "add the document lengths in FILE, as written by create-index, as the length \
+                 field",
+                "FILE");
+    let matches = or_exit(opts.parse(&args[1..]));
+
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    if matches.free.len() > 2 {
+        print_usage(&program, opts);
+        process::exit(1);
+    }
+
+    let input = Input::from(matches.free.first().map(String::as_str));
+    let mut metadata = or_exit(MemoryMetadata::from_buf_read(or_exit(input.buf_read())));
+
+    if let Some(filename) = matches.opt_str("l") {
+        let lengths_file = or_exit(File::open(filename));
+        let lengths: DocLengths<u64> =
+            or_exit(DocLengths::from_buf_read(BufReader::new(lengths_file)));
+
+        for doc in metadata.docs() {
+            if let Some(len) = lengths.get(&doc) {
+                metadata.set(doc, "length", &len.to_string());
+            }
+        }
+    }
+
+    let output = Output::from(matches.free.get(1).map(String::as_str));
+    let mut writer = BufWriter::new(or_exit(output.write()));
+    or_exit(metadata.write_binary(&mut writer));
+    or_exit(writer.flush());
+
+    stderr!("Wrote metadata of {} documents", metadata.len());
+}
@@ -4,15 +4,32 @@ extern crate stdinout;
 extern crate conllx;
 extern crate getopts;
 
+use std::collections::HashMap;
+use std::env;
 use std::env::args;
-use std::io::BufWriter;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
 
-use conllx::Features;
+use conllx::Sentence;
 use stdinout::*;
-use getopts::Options;
+use getopts::{Matches, Options};
 
-use boolean_search::{InvertedIndexMut, InvertedIndexToText, MemoryIndex, or_exit};
+use boolean_search::{BinaryIndexWriter, CollectionError, DocIdAssigner, DocIdExtractor,
+                     DocIdPolicy, DocLengths, Document, DocumentFormat, Fields,
+                     FrequencyMemoryIndex, IndexingReport, InvertedIndexMut, InvertedIndexToBinary,
+                     InvertedIndexToText, JsonlReader, MemoryIndex, MemoryMetadata,
+                     MmapIndexWriter, Normalizer, Pipeline, PositionalIndexMut,
+                     PositionalMemoryIndex, SpimiIndexer, TextReader, TrecReader,
+                     add_sentences, index_parallel, or_exit, write_mmap_index};
+
+/// The maximum number of sentences without a document identifier that
+/// are listed in the report.
+const MAX_REPORTED_ERRORS: usize = 10;
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options] EXPR [INPUT_FILE] [OUTPUT_FILE]",
@@ -25,7 +42,76 @@ fn main() {
     let program = args[0].clone();
 
     let mut opts = Options::new();
+    opts.optflag("b", "binary", "write the index in the binary format");
+    opts.optopt("d",
+                "doc-key",
+                "read document identifiers from the KEY=VALUE feature of the first token, \
+                 rather than the whole features column",
+                "KEY");
+    opts.optopt("e",
+                "on-error",
+                "handle sentences without a valid document identifier using POLICY: skip \
+                 (default), fail, or sequential",
+                "POLICY");
+    opts.optopt("F",
+                "fields",
+                "index the comma-separated FIELDS (form, lemma, cpos, pos; default: lemma), \
+                 terms are qualified as in lemma:run when multiple fields are indexed",
+                "FIELDS");
     opts.optflag("h", "help", "print this help menu");
+    opts.optopt("i",
+                "input-format",
+                "read documents in FORMAT: conllx (default), text (documents separated by \
+                 empty lines, titles on the first line), jsonl, or trec",
+                "FORMAT");
+    opts.optopt("",
+                "id-field",
+                "read JSON Lines document identifiers from FIELD (default: id)",
+                "FIELD");
+    opts.optopt("",
+                "text-field",
+                "read JSON Lines document texts from FIELD (default: text)",
+                "FIELD");
+    opts.optopt("",
+                "title-field",
+                "read JSON Lines document titles from FIELD (default: title)",
+                "FIELD");
+    opts.optopt("j",
+                "threads",
+                "index the input files and directories given as arguments using N threads",
+                "N");
+    opts.optflag("f",
+                 "frequencies",
+                 "create an index with term frequencies, for ranked retrieval");
+    opts.optopt("l",
+                "lengths",
+                "write document lengths to FILE, for ranked retrieval",
+                "FILE");
+    opts.optflag("m",
+                 "mmap",
+                 "write the index in the memory-mappable format");
+    opts.optopt("M",
+                "memory",
+                "index in runs of at most MB megabytes that are merged on disk",
+                "MB");
+    opts.optopt("N",
+                "normalize",
+                "normalize form and lemma terms using the comma-separated PIPELINE of \
+                 lowercase, nfc, nfkc, ptb-stopwords=FILE, stopwords=FILE, and \
+                 stem=LANGUAGE",
+                "PIPELINE");
+    opts.optopt("o",
+                "output",
+                "write the index to FILE when indexing in parallel",
+                "FILE");
+    opts.optflag("p",
+                 "positional",
+                 "create a positional index, for phrase and proximity queries");
+    opts.optopt("T",
+                "titles",
+                "write the titles of text, jsonl, or trec documents to FILE, as metadata for \
+                 query-index; identifiers that are not numbers are kept in the docno field",
+                "FILE");
     let matches = or_exit(opts.parse(&args[1..]));
 
     if matches.opt_present("h") {
@@ -33,35 +119,354 @@ fn main() {
         return;
     }
 
-    if matches.free.len() > 2 {
+    if !matches.opt_present("j") && matches.free.len() > 2 {
         print_usage(&program, opts);
         process::exit(1);
     }
 
-    let input = Input::from(matches.free.get(0).map(String::as_str));
-    let reader = conllx::Reader::new(or_exit(input.buf_read()));
+    if (matches.opt_present("b") || matches.opt_present("m")) &&
+       (matches.opt_present("f") || matches.opt_present("p")) {
+        stderr!("The binary formats do not support term frequencies or positions");
+        process::exit(1);
+    }
+
+    if matches.opt_present("M") && (matches.opt_present("f") || matches.opt_present("p")) {
+        stderr!("External memory indexing does not support term frequencies or positions");
+        process::exit(1);
+    }
+
+    if matches.opt_present("j") &&
+       (matches.opt_present("f") || matches.opt_present("p") || matches.opt_present("M")) {
+        stderr!("Parallel indexing does not support term frequencies, positions, or runs");
+        process::exit(1);
+    }
+
+    let policy: DocIdPolicy =
+        matches.opt_str("e").map(|policy| or_exit(policy.parse())).unwrap_or_default();
+    if matches.opt_present("j") && policy == DocIdPolicy::Sequential {
+        stderr!("Parallel indexing does not support sequential document identifiers");
+        process::exit(1);
+    }
+
+    let format: DocumentFormat =
+        matches.opt_str("i").map(|format| or_exit(format.parse())).unwrap_or_default();
+    if format == DocumentFormat::Conllx && matches.opt_present("T") {
+        stderr!("CoNLL-X documents do not have titles");
+        process::exit(1);
+    }
+
+    if format != DocumentFormat::Conllx && matches.opt_present("j") {
+        stderr!("Parallel indexing only supports CoNLL-X documents");
+        process::exit(1);
+    }
+
+    let n_threads = matches.opt_str("j").map(|n| or_exit(n.parse::<usize>()));
+    let output_filename = matches.opt_str("o");
+    let fields: Fields =
+        matches.opt_str("F").map(|fields| or_exit(fields.parse())).unwrap_or_default();
+    if format != DocumentFormat::Conllx && !fields.fields().iter().all(|field| field.is_word()) {
+        stderr!("Documents in the {} format only have the form and lemma fields",
+                format);
+        process::exit(1);
+    }
+
+    let pipeline: Arc<Pipeline> = Arc::new(matches.opt_str("N")
+        .map(|spec| or_exit(Pipeline::from_indexing_spec(&spec)))
+        .unwrap_or_default());
 
-    let output = Output::from(matches.free.get(1).map(String::as_str));
+    // In parallel mode, all free arguments are inputs.
+    let output = if n_threads.is_some() {
+        Output::from(output_filename.as_deref())
+    } else {
+        Output::from(matches.free.get(1).map(String::as_str))
+    };
     let mut writer = BufWriter::new(or_exit(output.write()));
 
-    let mut index = MemoryIndex::new();
+    let input = Input::from(matches.free.first().map(String::as_str));
+
+    let mut lengths = DocLengths::new();
+    let mut extractor = DocIdExtractor::new(matches.opt_str("d"), policy);
+    let mut titles = MemoryMetadata::new();
+
+    if let Some(n_threads) = n_threads {
+        let inputs = or_exit(input_files(&matches.free));
+        if inputs.is_empty() {
+            stderr!("No input files to index");
+            process::exit(1);
+        }
+
+        let (index, reports) = or_exit(index_parallel(inputs,
+                                                      &fields,
+                                                      pipeline.clone(),
+                                                      matches.opt_str("d"),
+                                                      policy,
+                                                      n_threads,
+                                                      &mut lengths));
+        for (path, report) in reports {
+            print_report(&report, policy, Some(&path));
+        }
+
+        write_index(&index, &matches, &mut writer);
+    } else {
+        let sentences = read_sentences(or_exit(input.buf_read()),
+                                       format,
+                                       &matches,
+                                       &mut extractor,
+                                       &mut titles);
+
+        if let Some(budget) = matches.opt_str("M") {
+            let budget: usize = or_exit(budget.parse());
+            let indexer = create_index_spimi(sentences,
+                                             &fields,
+                                             &pipeline,
+                                             &mut lengths,
+                                             budget * 1024 * 1024);
+
+            // The merged postings lists are written as they come out of the
+            // merge, so that the index is never fully in memory.
+            if matches.opt_present("b") {
+                let mut index_writer = or_exit(BinaryIndexWriter::new(env::temp_dir()));
+                or_exit(indexer.finish(|term, docs| index_writer.add_postings_list(term, &docs)));
+                or_exit(index_writer.finish(&mut writer));
+            } else if matches.opt_present("m") {
+                let mut index_writer = or_exit(MmapIndexWriter::new(env::temp_dir()));
+                or_exit(indexer.finish(|term, docs| index_writer.add_postings_list(term, &docs)));
+                or_exit(index_writer.finish(&mut writer));
+            } else {
+                or_exit(indexer.finish(|term, docs| {
+                    let docs_str: Vec<_> = docs.iter().map(ToString::to_string).collect();
+                    writeln!(writer, "{}\t{}", term, docs_str.join(" "))
+                }));
+            }
+        } else if matches.opt_present("p") {
+            let index = create_positional_index(sentences, &fields, &pipeline, &mut lengths);
+            or_exit(index.to_text(&mut writer));
+        } else if matches.opt_present("f") {
+            let index: FrequencyMemoryIndex<_> =
+                create_index(sentences, &fields, &pipeline, &mut lengths);
+            or_exit(index.to_text(&mut writer));
+        } else {
+            let index: MemoryIndex<_> = create_index(sentences, &fields, &pipeline, &mut lengths);
+            write_index(&index, &matches, &mut writer);
+        }
+    }
+
+    if n_threads.is_none() {
+        print_report(extractor.report(), policy, None);
+    }
+
+    // Save the stopwords that ptb-stopwords removed, for normalizing queries.
+    or_exit(pipeline.save());
+
+    if let Some(lengths_filename) = matches.opt_str("l") {
+        let mut lengths_writer = BufWriter::new(or_exit(File::create(lengths_filename)));
+        or_exit(lengths.to_text(&mut lengths_writer));
+    }
+
+    if let Some(titles_filename) = matches.opt_str("T") {
+        let mut titles_writer = BufWriter::new(or_exit(File::create(titles_filename)));
+        or_exit(titles.write_text(&mut titles_writer));
+    }
+}
+
+/// Read the sentences of the input with their document identifiers.
+/// Documents in the other formats are tokenized as a single sentence,
+/// their titles and identifiers are added to `titles`.
+fn read_sentences<'a, R>(reader: R,
+                         format: DocumentFormat,
+                         matches: &Matches,
+                         extractor: &'a mut DocIdExtractor,
+                         titles: &'a mut MemoryMetadata)
+                         -> Box<dyn Iterator<Item = (u64, Sentence)> + 'a>
+    where R: BufRead + 'a
+{
+    let field = |name: &str, default: &str| {
+        matches.opt_str(name).unwrap_or_else(|| default.to_owned())
+    };
+    let documents: Box<dyn Iterator<Item = Result<Document, CollectionError>>> = match format {
+        DocumentFormat::Conllx => return conllx_sentences(reader, extractor),
+        DocumentFormat::Text => Box::new(TextReader::new(reader)),
+        DocumentFormat::Jsonl => {
+            Box::new(JsonlReader::new(reader,
+                                      &field("id-field", "id"),
+                                      &field("text-field", "text"),
+                                      &field("title-field", "title")))
+        }
+        DocumentFormat::Trec => Box::new(TrecReader::new(reader)),
+    };
 
-    for sentence in reader {
+    let mut assigner = DocIdAssigner::new();
+    Box::new(documents.map(move |document| {
+        let document = or_exit(document);
+        let doc = or_exit(assigner.assign(&document, titles));
+        (doc, document.to_sentence())
+    }))
+}
+
+/// Read CoNLL-X sentences with the document identifiers of `extractor`.
+/// Sentences without a document identifier are skipped, unless the policy
+/// is to fail.
+fn conllx_sentences<'a, R>(reader: R,
+                           extractor: &'a mut DocIdExtractor)
+                           -> Box<dyn Iterator<Item = (u64, Sentence)> + 'a>
+    where R: BufRead + 'a
+{
+    Box::new(conllx::Reader::new(reader).into_iter().filter_map(move |sentence| {
         let sentence = or_exit(sentence);
 
         // Get the document identifier. We can safely assume that all the
         // tokens in a sentence belong to the same document.
-        let token = ok_or_continue!(sentence.as_tokens().get(0));
-        let doc_str = ok_or_continue!(token.features().map(Features::as_str));
-        let doc: usize = or_exit(doc_str.parse());
+        sentence_doc(extractor, &sentence).map(|doc| (doc, sentence))
+    }))
+}
+
+fn create_index<I, S>(sentences: S,
+                      fields: &Fields,
+                      pipeline: &Pipeline,
+                      lengths: &mut DocLengths<u64>)
+                      -> I
+    where I: Default + InvertedIndexMut<u64>,
+          S: IntoIterator<Item = (u64, Sentence)>
+{
+    let mut index = I::default();
+    add_sentences(sentences, fields, pipeline, &mut index, lengths);
+    index
+}
+
+/// Get the document identifier of a sentence. Returns `None` if the
+/// sentence should be skipped, exits if the policy is to fail.
+fn sentence_doc(extractor: &mut DocIdExtractor, sentence: &Sentence) -> Option<u64> {
+    or_exit(extractor.doc_id(sentence))
+}
+
+/// Print a summary of the sentences without a valid document identifier.
+fn print_report(report: &IndexingReport, policy: DocIdPolicy, path: Option<&Path>) {
+    if report.errors().is_empty() {
+        return;
+    }
+
+    let prefix = path.map(|path| format!("{}: ", path.display())).unwrap_or_default();
 
-        // Get the lemmas and add to the inverted index.
+    for err in report.errors().iter().take(MAX_REPORTED_ERRORS) {
+        stderr!("{}{}", prefix, err);
+    }
+
+    if report.errors().len() > MAX_REPORTED_ERRORS {
+        stderr!("{}... and {} more",
+                prefix,
+                report.errors().len() - MAX_REPORTED_ERRORS);
+    }
+
+    let action = match policy {
+        DocIdPolicy::Sequential => "Assigned new document identifiers to",
+        _ => "Skipped",
+    };
+
+    stderr!("{}{} {} of {} sentences without a valid document identifier",
+            prefix,
+            action,
+            report.errors().len(),
+            report.sentences());
+}
+
+/// Get the input files. Directories are expanded to the files that they
+/// contain.
+fn input_files(args: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+
+    for arg in args {
+        let path = Path::new(arg);
+        if path.is_dir() {
+            let mut dir_inputs = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_file() {
+                    dir_inputs.push(entry_path);
+                }
+            }
+
+            dir_inputs.sort();
+            inputs.extend(dir_inputs);
+        } else {
+            inputs.push(path.to_owned());
+        }
+    }
+
+    Ok(inputs)
+}
+
+fn write_index<W>(index: &MemoryIndex<u64>, matches: &Matches, writer: &mut W)
+    where W: Write
+{
+    if matches.opt_present("b") {
+        or_exit(index.to_binary(writer));
+    } else if matches.opt_present("m") {
+        or_exit(write_mmap_index(index, writer));
+    } else {
+        or_exit(index.to_text(writer));
+    }
+}
+
+fn create_index_spimi<S>(sentences: S,
+                        fields: &Fields,
+                        pipeline: &Pipeline,
+                        lengths: &mut DocLengths<u64>,
+                        budget: usize)
+                        -> SpimiIndexer<u64>
+    where S: IntoIterator<Item = (u64, Sentence)>
+{
+    let mut indexer = SpimiIndexer::new(env::temp_dir(), budget);
+
+    for (doc, sentence) in sentences {
+        for token in &sentence {
+            let terms = ok_or_continue!(fields.terms(token, pipeline));
+            for term in &terms {
+                or_exit(indexer.add_term(term, doc));
+            }
+
+            if !terms.is_empty() {
+                lengths.add(doc, 1);
+            }
+        }
+    }
+
+    indexer
+}
+
+fn create_positional_index<S>(sentences: S,
+                              fields: &Fields,
+                              pipeline: &Pipeline,
+                              lengths: &mut DocLengths<u64>)
+                              -> PositionalMemoryIndex<u64>
+    where S: IntoIterator<Item = (u64, Sentence)>
+{
+    let mut index = PositionalMemoryIndex::new();
+
+    // The next token position for every document. A document can consist
+    // of multiple sentences, so positions continue across sentences.
+    let mut doc_positions: HashMap<u64, u32> = HashMap::new();
+
+    for (doc, sentence) in sentences {
+        let position = doc_positions.entry(doc).or_insert(0);
+
+        // Tokens without indexed fields still take a position, so that
+        // phrases cannot match across them. Tokens that are removed by
+        // normalization, such as stopwords, do not take a position, so
+        // that phrase queries match after removing stopwords. The terms
+        // of the fields of a token share its position.
         for token in &sentence {
-            let lemma = ok_or_continue!(token.lemma());
-            index.add_term(lemma, doc);
+            let terms = ok_or_continue!(fields.terms(token, pipeline));
+            for term in &terms {
+                index.add_term_position(term, doc, *position);
+            }
+
+            if !terms.is_empty() {
+                lengths.add(doc, 1);
+            }
+
+            *position += 1;
         }
     }
 
-    // Write out the inverted index.
-    or_exit(index.to_text(&mut writer));
+    index
 }
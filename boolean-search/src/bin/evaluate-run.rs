@@ -0,0 +1,99 @@
+extern crate boolean_search;
+extern crate getopts;
+
+use std::env::args;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+
+use boolean_search::{Metric, Qrels, Run, evaluate, or_exit};
+
+const DEFAULT_METRICS: &str = "P@5,P@10,recall,map,mrr,ndcg@10";
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] QRELS_FILE RUN_FILE...\n\nEvaluate TREC runs, \
+                         such as the runs of query-index -R, against relevance judgments. \
+                         Multiple runs are shown side by side.",
+                        program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("m",
+                "metrics",
+                &format!("comma-separated list of METRICS: P@k, recall, map, mrr, and ndcg@k \
+                          (default: {})",
+                         DEFAULT_METRICS),
+                "METRICS");
+    opts.optflag("q", "per-query", "also print the metrics of every query");
+    let matches = or_exit(opts.parse(&args[1..]));
+
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    if matches.free.len() < 2 {
+        print_usage(&program, opts);
+        process::exit(1);
+    }
+
+    let metrics: Vec<Metric> = matches.opt_str("m")
+        .unwrap_or_else(|| DEFAULT_METRICS.to_owned())
+        .split(',')
+        .map(|metric| or_exit(metric.trim().parse()))
+        .collect();
+
+    let qrels_file = or_exit(File::open(&matches.free[0]));
+    let qrels = or_exit(Qrels::from_buf_read(BufReader::new(qrels_file)));
+
+    let run_names: Vec<_> = matches.free[1..]
+        .iter()
+        .map(|filename| {
+            Path::new(filename)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| filename.clone())
+        })
+        .collect();
+
+    // The values of every run, indexed by query and metric.
+    let results: Vec<_> = matches.free[1..]
+        .iter()
+        .map(|filename| {
+            let run = or_exit(Run::from_buf_read(BufReader::new(or_exit(File::open(filename)))));
+            evaluate(&qrels, &run, &metrics)
+        })
+        .collect();
+
+    println!("metric\tquery\t{}", run_names.join("\t"));
+
+    if matches.opt_present("q") {
+        for (query_idx, query) in qrels.queries().into_iter().enumerate() {
+            for (metric_idx, metric) in metrics.iter().enumerate() {
+                let values: Vec<_> = results.iter()
+                    .map(|result| format!("{:.4}", result[query_idx].1[metric_idx]))
+                    .collect();
+                println!("{}\t{}\t{}", metric, query, values.join("\t"));
+            }
+        }
+    }
+
+    for (metric_idx, metric) in metrics.iter().enumerate() {
+        let means: Vec<_> = results.iter()
+            .map(|result| {
+                let sum: f64 = result.iter().map(|(_, values)| values[metric_idx]).sum();
+                format!("{:.4}", sum / result.len().max(1) as f64)
+            })
+            .collect();
+        println!("{}\tall\t{}", metric, means.join("\t"));
+    }
+}
@@ -1,19 +1,39 @@
 #[macro_use]
 extern crate boolean_search;
 extern crate getopts;
+#[macro_use]
+extern crate serde_json;
 
-use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::env::args;
 use std::fs::File;
-use std::io::{BufRead, BufReader, stdin};
+use std::io;
+use std::io::{BufRead, BufReader, Write, stdin};
 use std::process;
+use std::time::{Duration, Instant};
 
 use getopts::Options;
 
-use boolean_search::{DocIdentifiers, InvertedIndex, InvertedIndexFromText, MemoryIndex, or_exit};
+use boolean_search::{DocIdExtractor, DocIdPolicy, DocumentStore, Explain, Fields, Filter,
+                     FrequencyIndex, IntersectStrategy, InvertedIndex, Metadata, Pipeline,
+                     Posting, Query, QueryPlan, Scorer, Snippet, SpellingCorrector,
+                     add_index_options, add_ranking_options, index_term_options,
+                     intersect_strategy, open_index, open_metadata, or_exit, read_lengths,
+                     result_json, scorer, split_filters, top_k, trec_doc_id};
+
+/// The maximum number of spelling suggestions that is shown for an
+/// unknown term.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The maximum number of snippets that is shown for a document.
+const MAX_SNIPPETS: usize = 3;
 
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} [options] TITLE_FILE INDEX_FILE", program);
+    let brief = format!("Usage: {} [options] METADATA_FILE INDEX_FILE\n\nQueries can be \
+                         restricted using metadata filters, such as date>2016-01-01. The \
+                         operators are =, !=, <, <=, >, and >=. In Boolean queries, filters \
+                         cannot be used in disjunctions, negations, or parentheses.",
+                        program);
     print!("{}", opts.usage(&brief));
 }
 
@@ -22,7 +42,60 @@ fn main() {
     let program = args[0].clone();
 
     let mut opts = Options::new();
+    add_index_options(&mut opts);
+    add_ranking_options(&mut opts);
+    opts.optflag("a",
+                 "autocorrect",
+                 "replace unknown query terms by their best spelling suggestion");
+    opts.optopt("B",
+                "batch",
+                "evaluate the queries in FILE and report their latencies, rather than results",
+                "FILE");
+    opts.optflag("C", "count", "only print the number of matching documents");
+    opts.optopt("",
+                "context",
+                "number of tokens of context in snippets (default: 5)",
+                "N");
+    opts.optopt("d",
+                "doc-key",
+                "read document identifiers of the snippet corpus from the KEY feature, as \
+                 given to create-index",
+                "KEY");
+    opts.optopt("e",
+                "edits",
+                "maximum edit distance of spelling suggestions (default: 2)",
+                "N");
     opts.optflag("h", "help", "print this help menu");
+    opts.optflag("i",
+                 "interactive",
+                 "read queries interactively, with commands to inspect the index");
+    opts.optflag("j", "json", "print results in the JSON Lines format");
+    opts.optopt("K",
+                "kwic",
+                "show matched terms in context, using the source documents in CORPUS",
+                "CORPUS");
+    opts.optopt("k",
+                "top",
+                "number of ranked results, if no --limit is given (default: 10)",
+                "N");
+    opts.optopt("n", "limit", "print at most N results per query", "N");
+    opts.optopt("o", "offset", "skip the first N results of a query (default: 0)", "N");
+    opts.optopt("R",
+                "run",
+                "print results as a TREC run with TAG, for evaluate-run; queries can be \
+                 preceded by their identifier and a tab",
+                "TAG");
+    opts.optopt("r",
+                "rank",
+                "rank documents using MODEL (bm25 or tfidf), rather than Boolean retrieval",
+                "MODEL");
+    opts.optmulti("W",
+                  "where",
+                  "only return documents that match the metadata FILTER",
+                  "FILTER");
+    opts.optflag("x",
+                 "explain",
+                 "print how Boolean queries are evaluated, with intermediate result sizes");
     let matches = or_exit(opts.parse(&args[1..]));
 
     if matches.opt_present("h") {
@@ -35,49 +108,601 @@ fn main() {
         process::exit(1);
     }
 
-    // Read the titles file.
-    let title_file = or_exit(File::open(&matches.free[0]));
-    let doc_ids = or_exit(DocIdentifiers::from_buf_read(BufReader::new(title_file)));
+    let (fields, pipeline) = or_exit(index_term_options(&matches));
+    let index_term = |term: &str| fields.index_term(term, &pipeline);
+
+    // Read the document metadata, in the text format or the binary format
+    // of compile-metadata.
+    let metadata = or_exit(open_metadata(&matches.free[0]));
+
+    let index = or_exit(open_index(&matches.free[1], &matches));
 
-    // Read the inverted index.
-    let index_file = or_exit(File::open(&matches.free[1]));
-    let index: MemoryIndex<u64> = or_exit(MemoryIndex::from_text(BufReader::new(index_file)));
+    // Read the source documents for snippets.
+    let store = matches.opt_str("K").map(|filename| {
+        let reader = BufReader::new(or_exit(File::open(filename)));
+        let mut extractor = DocIdExtractor::new(matches.opt_str("d"), DocIdPolicy::Skip);
+        or_exit(DocumentStore::from_conllx(reader, &mut extractor))
+    });
+
+    let printer = Printer {
+        metadata: &*metadata,
+        filters: matches.opt_strs("W").iter().map(|filter| or_exit(filter.parse())).collect(),
+        count: matches.opt_present("C"),
+        json: matches.opt_present("j"),
+        run: matches.opt_str("R"),
+        offset: matches.opt_str("o").map(|n| or_exit(n.parse())).unwrap_or(0),
+        limit: matches.opt_str("n").map(|n| or_exit(n.parse())),
+        store: store.as_ref(),
+        fields: &fields,
+        pipeline: &pipeline,
+        window: matches.opt_str("context").map(|n| or_exit(n.parse())).unwrap_or(5),
+    };
+
+    let lengths = or_exit(read_lengths(&matches));
+    let ranking = matches.opt_str("r").map(|model| {
+        let scorer = or_exit(scorer(&model, &*index, lengths.as_ref(), &matches));
+        Ranking {
+            index: index.frequencies().expect("Scorer without term frequencies"),
+            scorer,
+        }
+    });
+
+    let mut searcher = Searcher {
+        index: &*index,
+        ranking,
+        index_term: &index_term,
+        printer,
+        strategy: or_exit(intersect_strategy(&matches)),
+        top: matches.opt_str("k").map(|k| or_exit(k.parse())).unwrap_or(10),
+        max_edits: matches.opt_str("e").map(|n| or_exit(n.parse())).unwrap_or(2),
+        autocorrect: matches.opt_present("a"),
+        explain: matches.opt_present("x"),
+        quiet: false,
+        corrector: None,
+        n_queries: 0,
+        history: None,
+        latencies: None,
+    };
+
+    if let Some(filename) = matches.opt_str("B") {
+        run_batch(&mut searcher, &filename, matches.opt_present("j"));
+    } else if matches.opt_present("i") {
+        run_interactive(&mut searcher);
+    } else {
+        let input = stdin();
+        for line in input.lock().lines() {
+            searcher.search(&or_exit(line));
+        }
+    }
+}
+
+/// An index with term frequencies and the scorer of a ranking model.
+struct Ranking<'a> {
+    index: &'a dyn FrequencyIndex<u64>,
+    scorer: Box<dyn Scorer<u64> + 'a>,
+}
+
+/// Read queries from standard input, with a prompt and commands to
+/// inspect the index.
+fn run_interactive(searcher: &mut Searcher) {
+    searcher.history = Some(Vec::new());
+    searcher.latencies = Some(Vec::new());
+
+    stderr!("Type a query, or :help for a list of commands.");
 
     let input = stdin();
-    for line in input.lock().lines() {
+    let mut lines = input.lock().lines();
+
+    loop {
+        print!("query> ");
+        or_exit(io::stdout().flush());
+
+        let line = match lines.next() {
+            Some(line) => or_exit(line),
+            None => {
+                println!();
+                break;
+            }
+        };
+        let line = line.trim();
+
+        let command = match line.strip_prefix(':') {
+            Some(command) => command,
+            None => {
+                if let Some(stats) = searcher.search(line) {
+                    stderr!("{} results in {:.3} ms, {} postings touched",
+                            stats.results,
+                            millis(stats.elapsed),
+                            stats.postings);
+                }
+                continue;
+            }
+        };
+
+        let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+        match command {
+            "explain" => searcher.explain(arg.trim()),
+            "help" => print_commands(),
+            "history" => {
+                for (idx, query) in searcher.history.iter().flatten().enumerate() {
+                    println!("{:5}  {}", idx + 1, query);
+                }
+            }
+            "quit" | "q" => break,
+            "stats" => searcher.print_stats(),
+            "terms" => searcher.print_terms(arg.trim()),
+            _ => stderr!("Unknown command ':{}', type :help for a list of commands", command),
+        }
+    }
+}
+
+fn print_commands() {
+    println!(":explain QUERY   show how a query is evaluated");
+    println!(":help            print this list of commands");
+    println!(":history         list the queries of this session");
+    println!(":quit            exit");
+    println!(":stats           show index and query latency statistics");
+    println!(":terms PREFIX    list the index terms that start with PREFIX");
+}
+
+/// Evaluate the queries in a file without printing their results, and
+/// report the latency of every query and aggregate statistics.
+fn run_batch(searcher: &mut Searcher, filename: &str, json: bool) {
+    let reader = BufReader::new(or_exit(File::open(filename)));
+    searcher.quiet = true;
+    searcher.latencies = Some(Vec::new());
+
+    if !json {
+        println!("query\tresults\tpostings\tms");
+    }
+
+    let mut n_failed = 0;
+    let mut n_postings = 0;
+    let start = Instant::now();
+
+    for line in reader.lines() {
         let line = or_exit(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let stats = match searcher.search(&line) {
+            Some(stats) => stats,
+            None => {
+                n_failed += 1;
+                continue;
+            }
+        };
 
-        // Store the terms in a vector.
-        let terms: Vec<_> = line.split_whitespace().collect();
+        n_postings += stats.postings;
 
-        // Get the posting lists for each term for terms that are in
-        // the inverted index.
-        let mut posting_lists: Vec<_> = terms.iter()
-            .filter_map(|t| index.posting(t))
+        if json {
+            println!("{}",
+                     json!({
+                         "query": line,
+                         "results": stats.results,
+                         "postings": stats.postings,
+                         "ms": millis(stats.elapsed),
+                     }));
+        } else {
+            println!("{}\t{}\t{}\t{:.3}",
+                     line,
+                     stats.results,
+                     stats.postings,
+                     millis(stats.elapsed));
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let latencies = searcher.latencies.as_deref().unwrap_or_default();
+    let n_queries = latencies.len();
+
+    stderr!("Queries: {} evaluated, {} failed", n_queries, n_failed);
+    stderr!("Intersection: {}",
+            searcher.strategy
+                .map(|strategy| strategy.to_string())
+                .unwrap_or_else(|| "all lists at once".to_owned()));
+    stderr!("Total time: {:.3} ms, throughput: {:.1} queries/s",
+            millis(elapsed),
+            n_queries as f64 / elapsed.as_secs_f64());
+    print_latencies(latencies);
+    stderr!("Postings touched: {} documents, {:.1} per query",
+            n_postings,
+            n_postings as f64 / n_queries.max(1) as f64);
+}
+
+/// Print the mean, median, 95th percentile, and maximum latency.
+fn print_latencies(latencies: &[Duration]) {
+    if latencies.is_empty() {
+        return;
+    }
+
+    let mut sorted = latencies.to_vec();
+    sorted.sort();
+
+    let percentile = |p: usize| sorted[((sorted.len() - 1) * p) / 100];
+    let total: Duration = sorted.iter().sum();
+
+    stderr!("Latency: mean {:.3} ms, median {:.3} ms, p95 {:.3} ms, max {:.3} ms",
+            millis(total) / sorted.len() as f64,
+            millis(percentile(50)),
+            millis(percentile(95)),
+            millis(sorted[sorted.len() - 1]));
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+/// Statistics of the evaluation of a query.
+struct QueryStats {
+    results: usize,
+
+    /// The number of postings that were read from the index.
+    postings: usize,
+    elapsed: Duration,
+}
+
+/// Evaluates queries and prints their results.
+struct Searcher<'a> {
+    index: &'a dyn InvertedIndex<u64>,
+    ranking: Option<Ranking<'a>>,
+    index_term: &'a dyn Fn(&str) -> Option<String>,
+    printer: Printer<'a>,
+    strategy: Option<IntersectStrategy>,
+    top: usize,
+    max_edits: usize,
+    autocorrect: bool,
+    explain: bool,
+    quiet: bool,
+
+    // The spelling corrector is constructed when it is first needed.
+    corrector: Option<SpellingCorrector>,
+
+    // Queries are only recorded in interactive mode, latencies only in
+    // interactive and batch mode.
+    n_queries: usize,
+    history: Option<Vec<String>>,
+    latencies: Option<Vec<Duration>>,
+}
+
+impl<'a> Searcher<'a> {
+    /// Evaluate a query and print its results, unless the searcher is
+    /// quiet. Returns `None` if the query could not be evaluated.
+    fn search(&mut self, line: &str) -> Option<QueryStats> {
+        // Skip empty lines.
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        self.n_queries += 1;
+        if let Some(ref mut history) = self.history {
+            history.push(line.to_owned());
+        }
+
+        // In TREC run mode, a query can be preceded by its identifier and a
+        // tab. Otherwise, queries are numbered from 1.
+        let (id, line) = match line.split_once('\t') {
+            Some((id, query)) if self.printer.run.is_some() => (id.to_owned(), query),
+            _ => (self.n_queries.to_string(), line),
+        };
+
+        let stats = if self.ranking.is_some() {
+            self.search_ranked(&id, line)
+        } else {
+            self.search_boolean(&id, line)
+        };
+
+        if let (Some(stats), Some(latencies)) = (&stats, &mut self.latencies) {
+            latencies.push(stats.elapsed);
+        }
+
+        stats
+    }
+
+    fn search_boolean(&mut self, id: &str, line: &str) -> Option<QueryStats> {
+        let start = Instant::now();
+
+        let (mut query, filters) = self.parse(line)?;
+
+        // Give spelling suggestions for terms that are not in the index.
+        let corrections =
+            suggest_corrections(self.index, &query, &mut self.corrector, self.max_edits);
+        if self.autocorrect && !corrections.is_empty() {
+            query = query.filter_map_terms(&|term| {
+                    Some(corrections.get(term).cloned().unwrap_or_else(|| term.to_owned()))
+                })
+                .expect("Term removed by spelling correction");
+            stderr!("Showing results for: {}", query);
+        }
+
+        let (result, explain) = self.execute(line, &query)?;
+        if self.explain {
+            stderr!("{}", explain);
+        }
+
+        let result: Vec<_> = result.iter()
+            .filter(|&&doc| self.printer.matches(doc, &filters))
+            .map(|&doc| (doc, None))
             .collect();
 
-        // If the number of postings lists is smaller than the number of
-        // terms, one of the terms was not found. This means that there
-        // are no documents satisfying the query. Read the next line.
-        if posting_lists.len() != terms.len() || posting_lists.is_empty() {
+        let elapsed = start.elapsed();
+
+        // Highlight the query terms and the expansions of wildcards.
+        let mut terms: HashSet<String> = query.terms().into_iter().map(str::to_owned).collect();
+        for pattern in query.wildcards() {
+            terms.extend(self.index.expand_wildcard(pattern));
+        }
+
+        if !self.quiet {
+            self.printer.print(id, line, &result, result.len(), &terms);
+        }
+
+        Some(QueryStats {
+            results: result.len(),
+            postings: explain.postings(),
+            elapsed,
+        })
+    }
+
+    fn search_ranked(&mut self, id: &str, line: &str) -> Option<QueryStats> {
+        let start = Instant::now();
+
+        let (query, filters) = split_filters(line);
+        let terms: Vec<_> = query.split_whitespace().filter_map(self.index_term).collect();
+        let terms: Vec<_> = terms.iter().map(String::as_str).collect();
+        if terms.is_empty() {
+            return None;
+        }
+
+        let ranking = self.ranking.as_ref().expect("Searcher without ranking model");
+        let mut scores = ranking.scorer.score(ranking.index, &terms);
+        scores.retain(|&doc, _| self.printer.matches(doc, &filters));
+
+        // Print the best documents with their scores. Only the documents
+        // up to the end of the page are ranked.
+        let total = scores.len();
+        let k = self.printer.limit.unwrap_or(self.top).saturating_add(self.printer.offset);
+        let result: Vec<_> = top_k(scores, k)
+            .into_iter()
+            .map(|(doc, score)| (doc, Some(score)))
+            .collect();
+
+        let elapsed = start.elapsed();
+
+        if !self.quiet {
+            let terms = terms.iter().cloned().map(str::to_owned).collect();
+            self.printer.print(id, line, &result, total, &terms);
+        }
+
+        Some(QueryStats {
+            results: total,
+
+            // The scorer reads the postings lists of all query terms.
+            postings: terms.iter().map(|term| self.index.doc_freq(term)).sum(),
+            elapsed,
+        })
+    }
+
+    /// Parse a query and its metadata filters, and qualify and normalize
+    /// its terms as during indexing. Errors are reported and result in
+    /// `None`.
+    fn parse(&self, line: &str) -> Option<(Query, Vec<Filter>)> {
+        let (query, filters) = match Query::parse_with_filters(line) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                stderr!("Invalid query '{}': {}", line, err);
+                return None;
+            }
+        };
+
+        match query.filter_map_terms(&self.index_term) {
+            Some(query) => Some((query, filters)),
+            None => {
+                stderr!("Query '{}' only consists of stopwords", line);
+                None
+            }
+        }
+    }
+
+    /// Plan and execute a query. Errors are reported and result in `None`.
+    fn execute(&self, line: &str, query: &Query) -> Option<(Posting<'a, u64>, Explain)> {
+        let result = QueryPlan::new(query, self.index)
+            .and_then(|plan| plan.execute(self.index, self.strategy));
+
+        match result {
+            Ok(result) => Some(result),
+            Err(err) => {
+                stderr!("Cannot evaluate query '{}': {}", line, err);
+                None
+            }
+        }
+    }
+
+    /// Print the normalized query, its filters, and how the query is
+    /// evaluated.
+    fn explain(&self, line: &str) {
+        let (query, filters) = match self.parse(line) {
+            Some(parsed) => parsed,
+            None => return,
+        };
+
+        println!("Query: {}", query);
+        for filter in self.printer.filters.iter().chain(&filters) {
+            println!("Filter: {}", filter);
+        }
+
+        if let Some((_, explain)) = self.execute(line, &query) {
+            println!("{}", explain);
+        }
+    }
+
+    /// Print the index terms that start with `prefix`, with their document
+    /// frequencies.
+    fn print_terms(&self, prefix: &str) {
+        for (term, posting) in self.index.prefix(prefix) {
+            println!("{}\t{}", term, posting.len());
+        }
+    }
+
+    /// Print index statistics and the latencies of the queries so far.
+    fn print_stats(&self) {
+        let n_postings: usize = self.index.iter().map(|(_, posting)| posting.len()).sum();
+        stderr!("Terms: {}, postings: {} documents", self.index.len(), n_postings);
+        let latencies = self.latencies.as_deref().unwrap_or_default();
+        stderr!("Queries: {}", latencies.len());
+        print_latencies(latencies);
+    }
+}
+
+/// Print spelling suggestions for the query terms that are not in the
+/// index. Returns the best suggestion for every unknown term that has
+/// suggestions.
+fn suggest_corrections(index: &dyn InvertedIndex<u64>,
+                       query: &Query,
+                       corrector: &mut Option<SpellingCorrector>,
+                       max_edits: usize)
+                       -> HashMap<String, String> {
+    let mut corrections = HashMap::new();
+
+    for term in query.terms() {
+        if index.doc_freq(term) > 0 || corrections.contains_key(term) {
             continue;
         }
 
-        // Sort the postings lists from smallest to largest.
-        posting_lists.sort_by(|p1, p2| p1.len().cmp(&p2.len()));
+        let corrector = corrector.get_or_insert_with(|| SpellingCorrector::from_index(index));
+        let suggestions = corrector.suggest(term, max_edits);
+        if suggestions.is_empty() {
+            stderr!("Unknown term '{}'", term);
+            continue;
+        }
 
-        // Get the first postings list.
-        let (first, rest) = posting_lists.split_first().unwrap();
+        let terms: Vec<_> = suggestions.iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|suggestion| suggestion.term())
+            .collect();
+        stderr!("Unknown term '{}', did you mean: {}?", term, terms.join(", "));
 
-        // Apply intersection to the postings lists.
-        let result = rest.iter().fold(Cow::Borrowed(first), |acc, p| Cow::Owned(acc.intersect(p)));
+        corrections.insert(term.to_owned(), suggestions[0].term().to_owned());
+    }
+
+    corrections
+}
+
+/// Prints the results of queries, with paging and optional snippets.
+struct Printer<'a> {
+    metadata: &'a dyn Metadata,
+    filters: Vec<Filter>,
+    count: bool,
+    json: bool,
+    run: Option<String>,
+    offset: usize,
+    limit: Option<usize>,
+    store: Option<&'a DocumentStore<BufReader<File>>>,
+    fields: &'a Fields,
+    pipeline: &'a Pipeline,
+    window: usize,
+}
+
+impl<'a> Printer<'a> {
+    /// Check whether a document matches the filters of the query and the
+    /// filters that apply to all queries.
+    fn matches(&self, doc: u64, filters: &[Filter]) -> bool {
+        self.filters.iter().chain(filters).all(|filter| filter.matches(self.metadata, doc))
+    }
+
+    /// Print the results of a query. Documents have a score in ranked
+    /// retrieval. `total` is the number of matching documents, of which
+    /// `result` may only contain the best ranked documents. `terms` are the
+    /// index terms that are highlighted in snippets, `id` is the query
+    /// identifier in TREC runs.
+    fn print(&self,
+             id: &str,
+             query: &str,
+             result: &[(u64, Option<f64>)],
+             total: usize,
+             terms: &HashSet<String>) {
+        if self.count {
+            if self.json {
+                println!("{}", json!({"query": query, "total": total}));
+            } else {
+                println!("{}", total);
+            }
+
+            return;
+        }
+
+        let page = result.iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX));
+
+        if let Some(ref tag) = self.run {
+            for (rank, &(doc, score)) in page.enumerate().map(|(idx, r)| (self.offset + idx, r)) {
+                // Boolean results are not ranked, use scores that retain
+                // their order.
+                let score = score.unwrap_or((total - rank) as f64);
+                println!("{} Q0 {} {} {} {}",
+                         id,
+                         trec_doc_id(self.metadata, doc),
+                         rank + 1,
+                         score,
+                         tag);
+            }
+
+            return;
+        }
+
+        if self.json {
+            let results: Vec<_> = page.map(|&(doc, score)| {
+                    let snippets: Vec<_> = self.snippets(doc, terms)
+                        .iter()
+                        .map(|snippet| {
+                            json!({
+                                "left": snippet.left(),
+                                "keyword": snippet.keyword(),
+                                "right": snippet.right(),
+                            })
+                        })
+                        .collect();
+
+                    let mut result = result_json(self.metadata, doc, score);
+                    if self.store.is_some() {
+                        result["snippets"] = json!(snippets);
+                    }
+
+                    result
+                })
+                .collect();
+
+            println!("{}",
+                     json!({"query": query, "total": total, "results": results}));
+            return;
+        }
+
+        for &(doc, score) in page {
+            let title = self.metadata.title(doc).unwrap_or("title unknown");
+            match score {
+                Some(score) => println!("{}: {} ({:.4})", doc, title, score),
+                None => println!("{}: {}", doc, title),
+            }
+
+            for snippet in self.snippets(doc, terms) {
+                println!("    {}", snippet);
+            }
+        }
+    }
 
-        // Print the document ids and titles.
-        for doc in result.iter() {
-            match doc_ids.get(*doc as usize) {
-                Some(title) => println!("{}: {}", doc, title),
-                None => println!("{}: title unknown", doc),
+    fn snippets(&self, doc: u64, terms: &HashSet<String>) -> Vec<Snippet> {
+        match self.store {
+            Some(store) => {
+                or_exit(store.snippets(doc,
+                                       terms,
+                                       self.fields,
+                                       self.pipeline,
+                                       self.window,
+                                       MAX_SNIPPETS))
             }
+            None => Vec::new(),
         }
     }
 }
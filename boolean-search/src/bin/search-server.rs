@@ -0,0 +1,108 @@
+#[macro_use]
+extern crate boolean_search;
+extern crate getopts;
+
+use std::collections::BTreeMap;
+use std::env::args;
+use std::io::Write;
+use std::net::TcpListener;
+use std::process;
+
+use getopts::Options;
+
+use boolean_search::{FrontendError, MAX_WILDCARD_TERMS, READ_TIMEOUT, Server,
+                     add_index_options, add_ranking_options, index_term_options,
+                     intersect_strategy, open_index, open_metadata, or_exit, read_lengths,
+                     scorer};
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] METADATA_FILE INDEX_FILE\n\nServe search requests \
+                         over HTTP with JSON responses. Requests are handled one at a time, so \
+                         a client that is slow to send its request blocks all other clients \
+                         for up to {} seconds. Wildcards can match at most {} terms.\n\n\
+                         Endpoints:\n\n\
+                         GET /search?q=QUERY  Boolean search (offset, limit, where, explain)\n\
+                         GET /rank?q=QUERY    ranked search (model, offset, limit, where)\n\
+                         GET /terms?term=TERM statistics of a query term, or of the index \
+                         terms\n                     \
+                         with a prefix (prefix, limit)\n\
+                         GET /stats           index statistics",
+                        program,
+                        READ_TIMEOUT.as_secs(),
+                        MAX_WILDCARD_TERMS);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    add_index_options(&mut opts);
+    add_ranking_options(&mut opts);
+    opts.optopt("A",
+                "address",
+                "listen on ADDRESS (default: 127.0.0.1:8080), use port 0 for any free port",
+                "ADDRESS");
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("n",
+                "limit",
+                "default number of results per response (default: 10)",
+                "N");
+    let matches = or_exit(opts.parse(&args[1..]));
+
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    if matches.free.len() != 2 {
+        print_usage(&program, opts);
+        process::exit(1);
+    }
+
+    let (fields, pipeline) = or_exit(index_term_options(&matches));
+    let index_term = |term: &str| fields.index_term(term, &pipeline);
+
+    let metadata = or_exit(open_metadata(&matches.free[0]));
+    let index = or_exit(open_index(&matches.free[1], &matches));
+    let lengths = or_exit(read_lengths(&matches));
+
+    // Ranked retrieval is available for indexes with term frequencies,
+    // BM25 also requires document lengths.
+    let mut scorers = BTreeMap::new();
+    for &model in &["bm25", "tfidf"] {
+        match scorer(model, &*index, lengths.as_ref(), &matches) {
+            Ok(scorer) => {
+                scorers.insert(model, scorer);
+            }
+            Err(FrontendError::Unsupported(_)) => (),
+            Err(err) => or_exit(Err(err)),
+        }
+    }
+
+    let server = Server::new(&*index,
+                             &*metadata,
+                             &index_term,
+                             scorers,
+                             or_exit(intersect_strategy(&matches)),
+                             matches.opt_str("n").map(|n| or_exit(n.parse())).unwrap_or(10));
+
+    let address = matches.opt_str("A").unwrap_or_else(|| "127.0.0.1:8080".to_owned());
+    let listener = or_exit(TcpListener::bind(&address));
+    stderr!("Listening on http://{}", or_exit(listener.local_addr()));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                stderr!("Could not accept connection: {}", err);
+                continue;
+            }
+        };
+
+        if let Err(err) = server.serve(&stream) {
+            stderr!("Could not serve request: {}", err);
+        }
+    }
+}
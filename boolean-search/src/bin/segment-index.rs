@@ -0,0 +1,108 @@
+#[macro_use]
+extern crate boolean_search;
+extern crate getopts;
+extern crate stdinout;
+
+use std::env::args;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::process;
+
+use getopts::Options;
+use stdinout::*;
+
+use boolean_search::{InvertedIndexFromBinary, InvertedIndexFromText, MemoryIndex,
+                     SegmentedIndex, or_exit};
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] add DIR [INDEX_FILE]\n       {} [options] delete \
+                         DIR [DOC...]\n       {} [options] merge DIR",
+                        program,
+                        program,
+                        program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = args().collect();
+    let program = args[0].clone();
+
+    let mut opts = Options::new();
+    opts.optflag("b", "binary", "read the index to add in the binary format");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = or_exit(opts.parse(&args[1..]));
+
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    if matches.free.len() < 2 {
+        print_usage(&program, opts);
+        process::exit(1);
+    }
+
+    let dir = &matches.free[1];
+    let args = &matches.free[2..];
+
+    match matches.free[0].as_str() {
+        "add" if args.len() <= 1 => {
+            // Read the index that should be added as a segment, created
+            // using create-index.
+            let input = Input::from(args.first().map(String::as_str));
+            let reader = or_exit(input.buf_read());
+            let segment: MemoryIndex<u64> = if matches.opt_present("b") {
+                or_exit(MemoryIndex::from_binary(reader))
+            } else {
+                or_exit(MemoryIndex::from_text(reader))
+            };
+
+            let mut index = if Path::new(dir).exists() {
+                or_exit(SegmentedIndex::open(dir))
+            } else {
+                or_exit(SegmentedIndex::create(dir))
+            };
+
+            or_exit(index.add_segment(segment));
+        }
+        "delete" => {
+            let mut index: SegmentedIndex<u64> = or_exit(SegmentedIndex::open(dir));
+            let docs = if args.is_empty() {
+                read_docs()
+            } else {
+                args.iter().map(|doc| or_exit(doc.parse())).collect()
+            };
+
+            or_exit(index.delete(docs));
+        }
+        "merge" if args.is_empty() => {
+            let mut index: SegmentedIndex<u64> = or_exit(SegmentedIndex::open(dir));
+            or_exit(index.merge());
+        }
+        _ => {
+            print_usage(&program, opts);
+            process::exit(1);
+        }
+    }
+}
+
+/// Read document identifiers from stdin, one per line.
+fn read_docs() -> Vec<u64> {
+    let input = Input::from(None);
+    let reader = or_exit(input.buf_read());
+
+    let mut docs = Vec::new();
+    for line in reader.lines() {
+        let line = or_exit(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match line.trim().parse() {
+            Ok(doc) => docs.push(doc),
+            Err(_) => stderr!("Skipping invalid document identifier: {}", line),
+        }
+    }
+
+    docs
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::str;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use super::codec::{read_vbyte, write_vbyte};
+use super::tempfile::TempFile;
+
+/// Magic number at the start of a binary index file.
+pub const MAGIC: [u8; 4] = *b"BSIX";
+
+/// The version of the binary index format that is written.
+pub const VERSION: u32 = 1;
+
+/// Errors for reading binary indexes.
+#[derive(Debug)]
+pub enum BinaryReadError {
+    Io(io::Error),
+    BadMagic,
+    Corrupt(String),
+    InvalidTerm,
+    Truncated,
+    UnsupportedVersion(u32),
+}
+
+impl From<io::Error> for BinaryReadError {
+    fn from(err: io::Error) -> BinaryReadError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => BinaryReadError::Truncated,
+            _ => BinaryReadError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for BinaryReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BinaryReadError::Io(ref err) => write!(f, "{}", err),
+            BinaryReadError::BadMagic => write!(f, "Not a binary index file"),
+            BinaryReadError::Corrupt(ref msg) => write!(f, "Corrupt index file: {}", msg),
+            BinaryReadError::InvalidTerm => write!(f, "Term is not valid UTF-8"),
+            BinaryReadError::Truncated => write!(f, "Index file is truncated"),
+            BinaryReadError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported index format version: {}", version)
+            }
+        }
+    }
+}
+
+pub trait InvertedIndexFromBinary<N>
+    where Self: Sized
+{
+    /// Read an inverted index in the binary format. See `to_binary` for
+    /// a description of the format.
+    fn from_binary<R>(reader: R) -> Result<Self, BinaryReadError> where R: Read;
+}
+
+pub trait InvertedIndexToBinary<N> {
+    /// Write an inverted index in the binary format. The format is
+    /// (all integers are little-endian):
+    ///
+    /// * Header: the magic number `BSIX`, the format version (u32) and
+    ///   the number of terms (u64).
+    /// * Term dictionary, sorted by term. Each entry has the length of the
+    ///   term in bytes (u32), the UTF-8 encoded term, the number of
+    ///   documents (u64), and the offset (u64) and length (u64) in bytes
+    ///   of its postings list in the postings block.
+    /// * Postings block: the length of the block in bytes (u64), followed
+    ///   by the postings lists. A postings list is stored as the first
+    ///   document identifier followed by the gaps between consecutive
+    ///   identifiers, using variable-byte encoding.
+    fn to_binary<W>(&self, writer: &mut W) -> io::Result<()> where W: Write;
+}
+
+/// Write terms and their postings lists in the binary format.
+pub fn write_binary<N, W>(mut terms: Vec<(&str, &[N])>, writer: &mut W) -> io::Result<()>
+    where N: ToPrimitive,
+          W: Write
+{
+    terms.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut dictionary = Vec::new();
+    let mut postings = Vec::new();
+
+    for &(term, docs) in &terms {
+        let offset = postings.len();
+        encode_postings(docs, &mut postings)?;
+
+        dictionary.write_u32::<LittleEndian>(term.len() as u32)?;
+        dictionary.write_all(term.as_bytes())?;
+        dictionary.write_u64::<LittleEndian>(docs.len() as u64)?;
+        dictionary.write_u64::<LittleEndian>(offset as u64)?;
+        dictionary.write_u64::<LittleEndian>((postings.len() - offset) as u64)?;
+    }
+
+    write_header(writer, terms.len() as u64, &dictionary, postings.len() as u64)?;
+    writer.write_all(&postings)
+}
+
+/// Writes an index in the binary format term by term, for indexes that do
+/// not fit in memory. Only the term dictionary is kept in memory, the
+/// postings lists are buffered in a temporary file.
+pub struct BinaryIndexWriter {
+    dictionary: Vec<u8>,
+    n_terms: u64,
+    last_term: Option<String>,
+    postings: TempFile,
+    postings_len: u64,
+    buf: Vec<u8>,
+}
+
+impl BinaryIndexWriter {
+    /// Construct a writer that buffers postings lists in `dir`.
+    pub fn new<P>(dir: P) -> io::Result<Self>
+        where P: AsRef<Path>
+    {
+        Ok(BinaryIndexWriter {
+            dictionary: Vec::new(),
+            n_terms: 0,
+            last_term: None,
+            postings: TempFile::create(dir)?,
+            postings_len: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Add the postings list of a term. Terms must be added in ascending
+    /// order.
+    pub fn add_postings_list<N>(&mut self, term: &str, docs: &[N]) -> io::Result<()>
+        where N: ToPrimitive
+    {
+        check_term_order(&mut self.last_term, term)?;
+
+        self.buf.clear();
+        encode_postings(docs, &mut self.buf)?;
+        self.postings.write_all(&self.buf)?;
+
+        self.dictionary.write_u32::<LittleEndian>(term.len() as u32)?;
+        self.dictionary.write_all(term.as_bytes())?;
+        self.dictionary.write_u64::<LittleEndian>(docs.len() as u64)?;
+        self.dictionary.write_u64::<LittleEndian>(self.postings_len)?;
+        self.dictionary.write_u64::<LittleEndian>(self.buf.len() as u64)?;
+
+        self.n_terms += 1;
+        self.postings_len += self.buf.len() as u64;
+
+        Ok(())
+    }
+
+    /// Write the index.
+    pub fn finish<W>(mut self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        write_header(writer, self.n_terms, &self.dictionary, self.postings_len)?;
+        self.postings.copy_to(writer)?;
+        Ok(())
+    }
+}
+
+/// Check that terms are added in ascending order.
+pub fn check_term_order(last_term: &mut Option<String>, term: &str) -> io::Result<()> {
+    if last_term.as_ref().is_some_and(|last| last.as_str() >= term) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Term added out of order: {}", term)));
+    }
+
+    *last_term = Some(term.to_owned());
+
+    Ok(())
+}
+
+/// Write the header and the term dictionary, up to the postings lists.
+fn write_header<W>(writer: &mut W,
+                   n_terms: u64,
+                   dictionary: &[u8],
+                   postings_len: u64)
+                   -> io::Result<()>
+    where W: Write
+{
+    writer.write_all(&MAGIC)?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    writer.write_u64::<LittleEndian>(n_terms)?;
+    writer.write_all(dictionary)?;
+    writer.write_u64::<LittleEndian>(postings_len)
+}
+
+/// Read terms and their postings lists in the binary format. The function
+/// `add` is called for every term with its postings list.
+pub fn read_binary<N, R, F>(mut reader: R, mut add: F) -> Result<(), BinaryReadError>
+    where N: FromPrimitive,
+          R: Read,
+          F: FnMut(&str, Vec<N>)
+{
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let mut cursor = Cursor::new(data.as_slice());
+
+    let mut magic = [0u8; 4];
+    cursor.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(BinaryReadError::BadMagic);
+    }
+
+    let version = cursor.read_u32::<LittleEndian>()?;
+    if version != VERSION {
+        return Err(BinaryReadError::UnsupportedVersion(version));
+    }
+
+    let n_terms = cursor.read_u64::<LittleEndian>()?;
+
+    let mut dictionary = Vec::new();
+    for _ in 0..n_terms {
+        let term_len = cursor.read_u32::<LittleEndian>()? as usize;
+        let term_start = cursor.position() as usize;
+        let term_end = term_start.checked_add(term_len).ok_or(BinaryReadError::Truncated)?;
+        let term_bytes = data.get(term_start..term_end).ok_or(BinaryReadError::Truncated)?;
+        let term = str::from_utf8(term_bytes).map_err(|_| BinaryReadError::InvalidTerm)?;
+        cursor.set_position(term_end as u64);
+
+        let n_docs = cursor.read_u64::<LittleEndian>()?;
+        let offset = cursor.read_u64::<LittleEndian>()?;
+        let len = cursor.read_u64::<LittleEndian>()?;
+
+        dictionary.push((term, n_docs, offset, len));
+    }
+
+    let block_len = cursor.read_u64::<LittleEndian>()?;
+    let block_start = cursor.position() as usize;
+    let block = match (block_len as usize).checked_add(block_start) {
+        Some(block_end) => data.get(block_start..block_end).ok_or(BinaryReadError::Truncated)?,
+        None => return Err(BinaryReadError::Truncated),
+    };
+
+    for (term, n_docs, offset, len) in dictionary {
+        let bytes = (offset as usize)
+            .checked_add(len as usize)
+            .and_then(|end| block.get(offset as usize..end))
+            .ok_or_else(|| {
+                BinaryReadError::Corrupt(format!("postings of '{}' out of bounds", term))
+            })?;
+
+        add(term, decode_postings(bytes, n_docs)?);
+    }
+
+    Ok(())
+}
+
+/// Encode a postings list as the first document identifier, followed by
+/// gaps, using variable-byte encoding.
+fn encode_postings<N>(docs: &[N], buf: &mut Vec<u8>) -> io::Result<()>
+    where N: ToPrimitive
+{
+    let mut prev = 0;
+
+    for doc in docs {
+        let doc = doc.to_u64().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           "Document identifier does not fit in u64")
+        })?;
+        write_vbyte(doc - prev, buf);
+        prev = doc;
+    }
+
+    Ok(())
+}
+
+fn decode_postings<N>(mut bytes: &[u8], n_docs: u64) -> Result<Vec<N>, BinaryReadError>
+    where N: FromPrimitive
+{
+    // Every document takes at least one byte, do not trust n_docs for
+    // allocation beyond that.
+    let mut docs = Vec::with_capacity((n_docs as usize).min(bytes.len()));
+
+    let mut prev = 0u64;
+    for idx in 0..n_docs {
+        let gap = read_vbyte(&mut bytes)
+            .ok_or_else(|| BinaryReadError::Corrupt("invalid variable-byte number".to_owned()))?;
+        if idx != 0 && gap == 0 {
+            return Err(BinaryReadError::Corrupt("postings list not sorted or unique".to_owned()));
+        }
+
+        prev = prev.checked_add(gap)
+            .ok_or_else(|| BinaryReadError::Corrupt("document identifier overflow".to_owned()))?;
+        docs.push(N::from_u64(prev).ok_or_else(|| {
+            BinaryReadError::Corrupt(format!("document identifier out of range: {}", prev))
+        })?);
+    }
+
+    if !bytes.is_empty() {
+        return Err(BinaryReadError::Corrupt("trailing bytes in postings list".to_owned()));
+    }
+
+    Ok(docs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{BinaryIndexWriter, BinaryReadError, read_binary, write_binary};
+
+    fn test_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        let docs1 = [0u64, 5, 200, 1 << 40];
+        let docs2 = [3u64];
+        write_binary(vec![("york", &docs1[..]), ("new", &docs2[..])], &mut data).unwrap();
+        data
+    }
+
+    fn read(data: &[u8]) -> Result<Vec<(String, Vec<u64>)>, BinaryReadError> {
+        let mut terms = Vec::new();
+        read_binary(data, |term, docs| terms.push((term.to_owned(), docs)))?;
+        Ok(terms)
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        assert_eq!(read(&test_data()).unwrap(),
+                   vec![("new".to_owned(), vec![3]), ("york".to_owned(), vec![0, 5, 200, 1 << 40])]);
+    }
+
+    #[test]
+    fn binary_writer() {
+        let mut writer = BinaryIndexWriter::new(env::temp_dir()).unwrap();
+        writer.add_postings_list("new", &[3u64]).unwrap();
+        writer.add_postings_list("york", &[0u64, 5, 200, 1 << 40]).unwrap();
+        assert!(writer.add_postings_list("city", &[1u64]).is_err());
+
+        let mut data = Vec::new();
+        writer.finish(&mut data).unwrap();
+        assert_eq!(data, test_data());
+    }
+
+    #[test]
+    fn truncated_is_error() {
+        let data = test_data();
+
+        for len in 0..data.len() {
+            assert!(read(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn bad_header_is_error() {
+        let mut data = test_data();
+        data[4] = 2;
+        match read(&data) {
+            Err(BinaryReadError::UnsupportedVersion(2)) => (),
+            r => panic!("Expected unsupported version, got: {:?}", r),
+        }
+
+        data[0] = b'X';
+        match read(&data) {
+            Err(BinaryReadError::BadMagic) => (),
+            r => panic!("Expected bad magic, got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn doc_out_of_range_is_error() {
+        let mut terms = Vec::new();
+        let r = read_binary(test_data().as_slice(),
+                            |term, docs: Vec<u32>| terms.push((term.to_owned(), docs)));
+        match r {
+            Err(BinaryReadError::Corrupt(_)) => (),
+            r => panic!("Expected corrupt index, got: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+use num_traits::ToPrimitive;
+
+use super::index::default_skip_interval;
+
+/// Compression methods for postings lists. In all methods, a postings
+/// list is stored as the first document identifier followed by the gaps
+/// between consecutive document identifiers. Since most gaps are small
+/// numbers, they can be stored compactly.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Codec {
+    /// Variable-byte encoding: 7 bits of a number are stored per byte.
+    VByte,
+
+    /// Elias gamma encoding: the length of a number in unary code,
+    /// followed by the number in binary code.
+    Gamma,
+
+    /// Elias delta encoding: the length of a number in gamma code,
+    /// followed by the number in binary code.
+    Delta,
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vbyte" => Ok(Codec::VByte),
+            "gamma" => Ok(Codec::Gamma),
+            "delta" => Ok(Codec::Delta),
+            _ => Err(format!("Unknown codec: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Codec::VByte => write!(f, "vbyte"),
+            Codec::Gamma => write!(f, "gamma"),
+            Codec::Delta => write!(f, "delta"),
+        }
+    }
+}
+
+/// A compressed postings list.
+#[derive(Clone, Debug)]
+pub struct CompressedPosting {
+    codec: Codec,
+    len: usize,
+    data: Vec<u8>,
+    skips: Vec<SkipPointer>,
+}
+
+/// A skip pointer to a document in a compressed postings list. Decoding
+/// can be resumed from a skip pointer.
+#[derive(Clone, Copy, Debug)]
+struct SkipPointer {
+    /// The document that the skip pointer points to.
+    doc: u64,
+
+    /// The index of the document in the postings list.
+    idx: usize,
+
+    /// The offset just after the encoded document, in bytes for
+    /// variable-byte encoding and in bits for the Elias codes.
+    offset: usize,
+}
+
+impl CompressedPosting {
+    /// Compress a postings list. The document identifiers must be sorted
+    /// and unique. Skip pointers are stored every *sqrt(n)* documents.
+    ///
+    /// Panics when a document identifier cannot be represented as `u64`.
+    /// The Elias codes store gaps plus one, so they also panic on a gap of
+    /// `u64::MAX`: when document `u64::MAX` is the first document or
+    /// follows document 0.
+    pub fn encode<N>(docs: &[N], codec: Codec) -> Self
+        where N: ToPrimitive
+    {
+        Self::encode_with_skip_interval(docs, codec, default_skip_interval(docs.len()))
+    }
+
+    /// Compress a postings list, storing a skip pointer every `interval`
+    /// documents. No skip pointers are stored when `interval` is zero.
+    /// Panics in the same cases as `encode`.
+    pub fn encode_with_skip_interval<N>(docs: &[N], codec: Codec, interval: usize) -> Self
+        where N: ToPrimitive
+    {
+        let mut data = Vec::new();
+        let mut skips = Vec::new();
+
+        let mut bit_writer = BitWriter::new();
+        let mut prev = 0;
+
+        for (idx, doc) in docs.iter().enumerate() {
+            let doc = doc.to_u64().expect("Document identifier does not fit in u64");
+            let gap = doc - prev;
+            prev = doc;
+
+            let offset = match codec {
+                Codec::VByte => {
+                    write_vbyte(gap, &mut data);
+                    data.len()
+                }
+                // Elias codes cannot represent zero.
+                Codec::Gamma => bit_writer.write_gamma(&mut data, elias_gap(gap)),
+                Codec::Delta => bit_writer.write_delta(&mut data, elias_gap(gap)),
+            };
+
+            if interval != 0 && idx != 0 && idx.is_multiple_of(interval) {
+                skips.push(SkipPointer { doc, idx, offset });
+            }
+        }
+
+        data.shrink_to_fit();
+        skips.shrink_to_fit();
+
+        CompressedPosting {
+            codec,
+            len: docs.len(),
+            data,
+            skips,
+        }
+    }
+
+    /// Get the codec that was used to compress the postings list.
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Returns `true` if the postings list does not contain any documents.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Intersect the postings list with another compressed postings list.
+    /// Documents of the smaller list are looked up in the larger list,
+    /// following the skip pointers of the larger list.
+    pub fn intersect(&self, other: &CompressedPosting) -> Vec<u64> {
+        let (smaller, larger) = if self.len < other.len {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        larger.intersect_iter(smaller.iter())
+    }
+
+    /// Intersect the postings list with a sorted list of documents. The
+    /// documents are looked up following the skip pointers, so that only
+    /// parts of the postings list are decoded.
+    pub fn intersect_sorted(&self, docs: &[u64]) -> Vec<u64> {
+        self.intersect_iter(docs.iter().cloned())
+    }
+
+    fn intersect_iter<I>(&self, docs: I) -> Vec<u64>
+        where I: IntoIterator<Item = u64>
+    {
+        let mut inter = Vec::new();
+        let mut iter = self.iter();
+
+        for doc in docs {
+            match iter.skip_to(doc) {
+                Some(found) => {
+                    if found == doc {
+                        inter.push(doc);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        inter
+    }
+
+    /// Get an iterator that decodes the document identifiers.
+    pub fn iter(&self) -> DecodeIter<'_> {
+        DecodeIter {
+            posting: self,
+            offset: 0,
+            prev: 0,
+            idx: 0,
+            current: None,
+        }
+    }
+
+    /// Get the number of documents in the postings list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Get the size of the compressed data in bytes, including skip
+    /// pointers.
+    pub fn size_in_bytes(&self) -> usize {
+        self.data.len() + self.skips.len() * mem::size_of::<SkipPointer>()
+    }
+}
+
+/// Iterator that decodes the document identifiers of a compressed
+/// postings list.
+pub struct DecodeIter<'a> {
+    posting: &'a CompressedPosting,
+    offset: usize,
+    prev: u64,
+    idx: usize,
+    current: Option<u64>,
+}
+
+impl<'a> DecodeIter<'a> {
+    /// Advance the iterator to the first document that is equal to or
+    /// larger than `doc` and return it. Skip pointers are followed where
+    /// possible. The document that was returned last is also considered,
+    /// so that repeated calls with the same document give the same result.
+    pub fn skip_to(&mut self, doc: u64) -> Option<u64> {
+        if let Some(current) = self.current {
+            if current >= doc {
+                return Some(current);
+            }
+        }
+
+        // Find the last skip pointer ahead of the iterator that does not
+        // go past the target document.
+        let skips = &self.posting.skips;
+        let start = match skips.binary_search_by(|skip| skip.idx.cmp(&self.idx)) {
+            Ok(idx) | Err(idx) => idx,
+        };
+        let end = match skips[start..].binary_search_by(|skip| skip.doc.cmp(&doc)) {
+            Ok(idx) => start + idx + 1,
+            Err(idx) => start + idx,
+        };
+
+        if end > start {
+            let skip = skips[end - 1];
+            self.offset = skip.offset;
+            self.prev = skip.doc;
+            self.idx = skip.idx + 1;
+            self.current = Some(skip.doc);
+
+            if skip.doc == doc {
+                return Some(doc);
+            }
+        }
+
+        self.find(|&d| d >= doc)
+    }
+}
+
+impl<'a> Iterator for DecodeIter<'a> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.idx == self.posting.len {
+            self.current = None;
+            return None;
+        }
+
+        // The data was encoded by CompressedPosting, so it is well-formed.
+        let data = &self.posting.data;
+        let gap = match self.posting.codec {
+            Codec::VByte => {
+                let mut bytes = &data[self.offset..];
+                let gap = read_vbyte(&mut bytes);
+                self.offset = data.len() - bytes.len();
+                gap
+            }
+            Codec::Gamma | Codec::Delta => {
+                let mut reader = BitReader::new(data, self.offset);
+                let gap = if self.posting.codec == Codec::Gamma {
+                    reader.read_gamma()
+                } else {
+                    reader.read_delta()
+                };
+                self.offset = reader.pos;
+                gap.map(|gap| gap - 1)
+            }
+        }
+        .expect("Invalid compressed postings list");
+
+        self.prev += gap;
+        self.idx += 1;
+        self.current = Some(self.prev);
+
+        self.current
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.posting.len - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for DecodeIter<'a> {}
+
+/// Write a number using variable-byte encoding. The number is split in
+/// 7-bit groups, most significant group first. The high bit is set on the
+/// last byte.
+pub fn write_vbyte(mut n: u64, buf: &mut Vec<u8>) {
+    let mut groups = [0u8; 10];
+    let mut len = 0;
+
+    loop {
+        groups[len] = (n & 0x7f) as u8;
+        len += 1;
+        n >>= 7;
+
+        if n == 0 {
+            break;
+        }
+    }
+
+    groups[0] |= 0x80;
+
+    for &group in groups[..len].iter().rev() {
+        buf.push(group);
+    }
+}
+
+/// Read a variable-byte encoded number, advancing the slice. Returns
+/// `None` if the number is not terminated or does not fit in `u64`.
+pub fn read_vbyte(bytes: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+
+    for (idx, &byte) in bytes.iter().enumerate() {
+        if n.leading_zeros() < 7 {
+            return None;
+        }
+
+        n = (n << 7) | (byte & 0x7f) as u64;
+
+        if byte & 0x80 != 0 {
+            *bytes = &bytes[idx + 1..];
+            return Some(n);
+        }
+    }
+
+    None
+}
+
+/// Writer for bit-level codes. Bits are written from the most
+/// significant to the least significant bit of each byte. The write
+/// methods return the number of bits written so far.
+struct BitWriter {
+    pos: usize,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter { pos: 0 }
+    }
+
+    fn write_bit(&mut self, buf: &mut Vec<u8>, bit: bool) {
+        if self.pos.is_multiple_of(8) {
+            buf.push(0);
+        }
+
+        if bit {
+            *buf.last_mut().unwrap() |= 0x80 >> (self.pos % 8);
+        }
+
+        self.pos += 1;
+    }
+
+    /// Write the `n_bits` least significant bits of `n`.
+    fn write_bits(&mut self, buf: &mut Vec<u8>, n: u64, n_bits: u32) -> usize {
+        for bit in (0..n_bits).rev() {
+            self.write_bit(buf, (n >> bit) & 1 == 1);
+        }
+
+        self.pos
+    }
+
+    /// Write a number (> 0) in Elias gamma code.
+    fn write_gamma(&mut self, buf: &mut Vec<u8>, n: u64) -> usize {
+        let n_bits = 64 - n.leading_zeros();
+
+        for _ in 1..n_bits {
+            self.write_bit(buf, false);
+        }
+
+        self.write_bits(buf, n, n_bits)
+    }
+
+    /// Write a number (> 0) in Elias delta code.
+    fn write_delta(&mut self, buf: &mut Vec<u8>, n: u64) -> usize {
+        let n_bits = 64 - n.leading_zeros();
+        self.write_gamma(buf, n_bits as u64);
+
+        // The most significant bit is implied by the length.
+        self.write_bits(buf, n, n_bits - 1)
+    }
+}
+
+/// Get the number that represents a gap in the Elias codes.
+fn elias_gap(gap: u64) -> u64 {
+    gap.checked_add(1).expect("Gap of u64::MAX cannot be stored in an Elias code")
+}
+
+/// Reader for bit-level codes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Construct a reader that starts at bit `pos`.
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        BitReader { data, pos }
+    }
+
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, n_bits: u32) -> Option<u64> {
+        let mut n = 0;
+
+        for _ in 0..n_bits {
+            n = (n << 1) | self.read_bit()? as u64;
+        }
+
+        Some(n)
+    }
+
+    fn read_gamma(&mut self) -> Option<u64> {
+        let mut n_zeros = 0;
+        while !self.read_bit()? {
+            n_zeros += 1;
+        }
+
+        if n_zeros > 63 {
+            return None;
+        }
+
+        Some((1 << n_zeros) | self.read_bits(n_zeros)?)
+    }
+
+    fn read_delta(&mut self) -> Option<u64> {
+        let n_bits = self.read_gamma()?;
+
+        if n_bits > 64 {
+            return None;
+        }
+
+        let n_bits = n_bits as u32;
+        Some((1 << (n_bits - 1)) | self.read_bits(n_bits - 1)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::panic;
+
+    use super::{Codec, CompressedPosting, read_vbyte, write_vbyte};
+
+    fn roundtrip(docs: &[u64], codec: Codec) -> bool {
+        let compressed = CompressedPosting::encode(docs, codec);
+        compressed.len() == docs.len() && compressed.iter().collect::<Vec<_>>() == docs
+    }
+
+    #[test]
+    fn vbyte_roundtrip() {
+        for &n in &[0, 1, 127, 128, 16383, 16384, u64::MAX] {
+            let mut buf = Vec::new();
+            write_vbyte(n, &mut buf);
+            let mut bytes = buf.as_slice();
+            assert_eq!(read_vbyte(&mut bytes), Some(n));
+            assert!(bytes.is_empty());
+        }
+    }
+
+    #[test]
+    fn extreme_values() {
+        let docs = [0, 1, 2, 1000, 1 << 40, u64::MAX - 1];
+        for &codec in &[Codec::VByte, Codec::Gamma, Codec::Delta] {
+            assert!(roundtrip(&docs, codec));
+        }
+
+        assert!(roundtrip(&[0, u64::MAX], Codec::VByte));
+        assert!(roundtrip(&[1, u64::MAX], Codec::Gamma));
+        assert!(panic::catch_unwind(|| CompressedPosting::encode(&[u64::MAX], Codec::Delta))
+            .is_err());
+    }
+
+    #[test]
+    fn gamma_is_compact() {
+        // The first document is stored as 1 (1 bit), the remaining gaps
+        // as 2 (3 bits each): 1 + 63 * 3 = 190 bits.
+        let docs: Vec<u64> = (0..64).collect();
+        let compressed = CompressedPosting::encode_with_skip_interval(&docs, Codec::Gamma, 0);
+        assert_eq!(compressed.size_in_bytes(), 24);
+    }
+
+    #[test]
+    fn skip_to() {
+        let docs: Vec<u64> = (0..100).map(|doc| doc * 3).collect();
+        for &codec in &[Codec::VByte, Codec::Gamma, Codec::Delta] {
+            let compressed = CompressedPosting::encode_with_skip_interval(&docs, codec, 7);
+            let mut iter = compressed.iter();
+            assert_eq!(iter.skip_to(0), Some(0));
+            assert_eq!(iter.skip_to(22), Some(24));
+            assert_eq!(iter.skip_to(24), Some(24));
+            assert_eq!(iter.next(), Some(27));
+            assert_eq!(iter.skip_to(210), Some(210));
+            assert_eq!(iter.next(), Some(213));
+            assert_eq!(iter.skip_to(298), None);
+        }
+    }
+
+    quickcheck! {
+        fn prop_intersect(xs: BTreeSet<u64>, ys: BTreeSet<u64>, interval: usize) -> bool {
+            let check: Vec<_> = xs.intersection(&ys).cloned().collect();
+            let (docs1, docs2): (Vec<_>, Vec<_>) =
+                (xs.into_iter().collect(), ys.into_iter().collect());
+
+            [Codec::VByte, Codec::Gamma, Codec::Delta].iter().all(|&codec| {
+                let p1 = CompressedPosting::encode(&docs1, codec);
+                let p2 = CompressedPosting::encode_with_skip_interval(&docs2, codec, interval % 8);
+                p1.intersect(&p2) == check && p2.intersect(&p1) == check &&
+                p2.intersect_sorted(&docs1) == check
+            })
+        }
+    }
+
+    quickcheck! {
+        fn prop_roundtrip(xs: BTreeSet<u64>) -> bool {
+            let docs: Vec<_> = xs.into_iter().collect();
+            roundtrip(&docs, Codec::VByte) && roundtrip(&docs, Codec::Gamma) &&
+            roundtrip(&docs, Codec::Delta)
+        }
+    }
+}
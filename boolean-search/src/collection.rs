@@ -0,0 +1,478 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::iter::Enumerate;
+use std::ops::Range;
+use std::str::FromStr;
+
+use conllx::{Sentence, TokenBuilder};
+use serde_json;
+use serde_json::Value;
+
+use super::MemoryMetadata;
+
+/// Errors for reading document collections. Lines are numbered from 1.
+#[derive(Debug)]
+pub enum CollectionError {
+    DuplicateId(u64, Option<String>),
+    Io(io::Error),
+    Json(usize, serde_json::Error),
+    MissingField(usize, String),
+    Trec(usize, String),
+}
+
+impl From<io::Error> for CollectionError {
+    fn from(err: io::Error) -> CollectionError {
+        CollectionError::Io(err)
+    }
+}
+
+impl fmt::Display for CollectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CollectionError::DuplicateId(doc, ref id) => {
+                write!(f,
+                       "Duplicate document identifier {} of document {}",
+                       doc,
+                       id.as_deref().unwrap_or("without identifier"))
+            }
+            CollectionError::Io(ref err) => write!(f, "{}", err),
+            CollectionError::Json(line, ref err) => {
+                write!(f, "Line {}: invalid JSON: {}", line, err)
+            }
+            CollectionError::MissingField(line, ref field) => {
+                write!(f, "Line {}: no string field '{}'", line, field)
+            }
+            CollectionError::Trec(line, ref err) => write!(f, "Line {}: {}", line, err),
+        }
+    }
+}
+
+/// Formats of the documents that are indexed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DocumentFormat {
+    /// Tokenized and annotated sentences in the CoNLL-X format, with the
+    /// document identifier in the features of the first token.
+    #[default]
+    Conllx,
+
+    /// Plain text, see `TextReader`.
+    Text,
+
+    /// JSON Lines, see `JsonlReader`.
+    Jsonl,
+
+    /// TREC SGML, see `TrecReader`.
+    Trec,
+}
+
+impl FromStr for DocumentFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "conllx" => Ok(DocumentFormat::Conllx),
+            "text" => Ok(DocumentFormat::Text),
+            "jsonl" => Ok(DocumentFormat::Jsonl),
+            "trec" => Ok(DocumentFormat::Trec),
+            _ => Err(format!("Unknown document format: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DocumentFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DocumentFormat::Conllx => write!(f, "conllx"),
+            DocumentFormat::Text => write!(f, "text"),
+            DocumentFormat::Jsonl => write!(f, "jsonl"),
+            DocumentFormat::Trec => write!(f, "trec"),
+        }
+    }
+}
+
+/// An untokenized document, with its identifier and title in the
+/// collection, if any.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Document {
+    id: Option<String>,
+    title: Option<String>,
+    text: String,
+}
+
+impl Document {
+    pub fn new(id: Option<String>, title: Option<String>, text: String) -> Self {
+        Document { id, title, text }
+    }
+
+    /// Get the identifier of the document in the collection.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Get the title of the document.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Get the text of the document.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Tokenize the text of the document as a single sentence, which can
+    /// be indexed like CoNLL-X sentences. Since the tokens are not
+    /// lemmatized, the words are used as both form and lemma.
+    pub fn to_sentence(&self) -> Sentence {
+        let tokens = tokenize(&self.text)
+            .into_iter()
+            .map(|word| TokenBuilder::new().form(word).lemma(word).token())
+            .collect();
+        Sentence::new(tokens)
+    }
+}
+
+/// Assigns document identifiers to the documents of a collection.
+///
+/// Identifiers that are numbers are used as document identifiers. Other
+/// documents get the identifier that follows the highest identifier seen
+/// so far, which assumes that the identifiers are ascending. An identifier
+/// that was already assigned is an error, since the postings of the
+/// documents would be merged.
+#[derive(Debug, Default)]
+pub struct DocIdAssigner {
+    next_doc: u64,
+    assigned: HashSet<u64>,
+}
+
+impl DocIdAssigner {
+    pub fn new() -> Self {
+        DocIdAssigner::default()
+    }
+
+    /// Assign an identifier to a document. The title of the document is
+    /// added to `metadata`, as well as its identifier in the collection if
+    /// that is not the assigned identifier. The latter is stored in the
+    /// `docno` field, which is used in TREC runs.
+    pub fn assign(&mut self,
+                  document: &Document,
+                  metadata: &mut MemoryMetadata)
+                  -> Result<u64, CollectionError> {
+        let doc = document.id().and_then(|id| id.parse().ok()).unwrap_or(self.next_doc);
+        self.next_doc = self.next_doc.max(doc.saturating_add(1));
+
+        if !self.assigned.insert(doc) {
+            return Err(CollectionError::DuplicateId(doc, document.id().map(str::to_owned)));
+        }
+
+        if let Some(title) = document.title() {
+            metadata.set(doc, "title", title);
+        }
+        if let Some(id) = document.id().filter(|&id| id != doc.to_string()) {
+            metadata.set(doc, "docno", id);
+        }
+
+        Ok(doc)
+    }
+}
+
+/// Split text into tokens, which are sequences of alphanumeric characters.
+/// Apostrophes and hyphens between alphanumeric characters are part of a
+/// token, as in `don't` and `e-mail`. Other characters are removed.
+pub fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    let mut chars = text.char_indices().peekable();
+    while let Some((idx, ch)) = chars.next() {
+        let next_alphanumeric = chars.peek().map(|&(_, next)| next.is_alphanumeric());
+
+        if ch.is_alphanumeric() {
+            start.get_or_insert(idx);
+        } else if start.is_some() && (ch == '\'' || ch == '-') &&
+                  next_alphanumeric == Some(true) {
+            continue;
+        } else if let Some(start) = start.take() {
+            tokens.push(&text[start..idx]);
+        }
+    }
+
+    if let Some(start) = start {
+        tokens.push(&text[start..]);
+    }
+
+    tokens
+}
+
+/// Reads plain-text documents. Documents are separated by one or more
+/// empty lines, the first line of a document is its title. Documents do
+/// not have identifiers.
+pub struct TextReader<R> {
+    lines: io::Lines<R>,
+}
+
+impl<R> TextReader<R>
+    where R: BufRead
+{
+    pub fn new(reader: R) -> Self {
+        TextReader { lines: reader.lines() }
+    }
+}
+
+impl<R> Iterator for TextReader<R>
+    where R: BufRead
+{
+    type Item = Result<Document, CollectionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut lines = Vec::new();
+
+        for line in &mut self.lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+
+            if !line.trim().is_empty() {
+                lines.push(line);
+            } else if !lines.is_empty() {
+                break;
+            }
+        }
+
+        let title = lines.first()?.trim().to_owned();
+        Some(Ok(Document::new(None, Some(title), lines.join("\n"))))
+    }
+}
+
+/// Reads documents in the JSON Lines format. Every non-empty line is a
+/// JSON object with a document. The text field is required, the
+/// identifier field can be a string or a number.
+pub struct JsonlReader<R> {
+    lines: Enumerate<io::Lines<R>>,
+    id_field: String,
+    text_field: String,
+    title_field: String,
+}
+
+impl<R> JsonlReader<R>
+    where R: BufRead
+{
+    /// Construct a reader that gets the identifier, text, and title of
+    /// documents from the given fields.
+    pub fn new(reader: R, id_field: &str, text_field: &str, title_field: &str) -> Self {
+        JsonlReader {
+            lines: reader.lines().enumerate(),
+            id_field: id_field.to_owned(),
+            text_field: text_field.to_owned(),
+            title_field: title_field.to_owned(),
+        }
+    }
+
+    fn parse(&self, line_no: usize, line: &str) -> Result<Document, CollectionError> {
+        let value: Value =
+            serde_json::from_str(line).map_err(|err| CollectionError::Json(line_no, err))?;
+
+        let text = value.get(&self.text_field)
+            .and_then(Value::as_str)
+            .ok_or_else(|| CollectionError::MissingField(line_no, self.text_field.clone()))?;
+
+        let id = match value.get(&self.id_field) {
+            Some(Value::String(id)) => Some(id.clone()),
+            Some(Value::Number(id)) => Some(id.to_string()),
+            _ => None,
+        };
+
+        let title = value.get(&self.title_field).and_then(Value::as_str).map(str::to_owned);
+
+        Ok(Document::new(id, title, text.to_owned()))
+    }
+}
+
+impl<R> Iterator for JsonlReader<R>
+    where R: BufRead
+{
+    type Item = Result<Document, CollectionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (idx, line) = self.lines.next()?;
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+
+            if !line.trim().is_empty() {
+                return Some(self.parse(idx + 1, &line));
+            }
+        }
+    }
+}
+
+/// Reads documents of TREC SGML collections. Every document is a `<DOC>`
+/// element, where `<DOC>` and `</DOC>` are on lines of their own. The
+/// identifier of a document is the content of its `<DOCNO>` element and
+/// the title is the content of its `<TITLE>` or `<HEADLINE>` element. The
+/// text of a document is the content of its other elements, without
+/// markup. Text outside `<DOC>` elements is ignored.
+pub struct TrecReader<R> {
+    lines: Enumerate<io::Lines<R>>,
+}
+
+impl<R> TrecReader<R>
+    where R: BufRead
+{
+    pub fn new(reader: R) -> Self {
+        TrecReader { lines: reader.lines().enumerate() }
+    }
+}
+
+impl<R> Iterator for TrecReader<R>
+    where R: BufRead
+{
+    type Item = Result<Document, CollectionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut start = None;
+        let mut content = String::new();
+
+        for (idx, line) in &mut self.lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Some(Err(err.into())),
+            };
+
+            match (line.trim(), start) {
+                ("<DOC>", None) => start = Some(idx + 1),
+                ("<DOC>", Some(_)) => {
+                    return Some(Err(CollectionError::Trec(idx + 1, "nested <DOC>".to_owned())))
+                }
+                ("</DOC>", Some(_)) => return Some(Ok(trec_document(&content))),
+                ("</DOC>", None) => {
+                    return Some(Err(CollectionError::Trec(idx + 1, "unopened </DOC>".to_owned())))
+                }
+                (_, Some(_)) => {
+                    content.push_str(&line);
+                    content.push('\n');
+                }
+                (_, None) => (),
+            }
+        }
+
+        start.map(|start| Err(CollectionError::Trec(start, "unterminated <DOC>".to_owned())))
+    }
+}
+
+/// Construct a document from the content of a TREC `<DOC>` element.
+fn trec_document(content: &str) -> Document {
+    let normalize = |text: &str| {
+        strip_markup(text).split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+
+    let (id, text) = match find_element(content, "DOCNO") {
+        Some((element, inner)) => {
+            let id = normalize(&content[inner]);
+            let text = format!("{}{}", &content[..element.start], &content[element.end..]);
+            (Some(id).filter(|id| !id.is_empty()), text)
+        }
+        None => (None, content.to_owned()),
+    };
+
+    let title = find_element(&text, "TITLE")
+        .or_else(|| find_element(&text, "HEADLINE"))
+        .map(|(_, inner)| normalize(&text[inner]))
+        .filter(|title| !title.is_empty());
+
+    Document::new(id, title, strip_markup(&text))
+}
+
+/// Find the first element with the given tag. Returns the byte ranges of
+/// the element and of its content.
+fn find_element(text: &str, tag: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+
+    let start = text.find(&open)?;
+    let inner_start = start + open.len();
+    let inner_end = inner_start + text[inner_start..].find(&close)?;
+
+    Some((start..inner_end + close.len(), inner_start..inner_end))
+}
+
+/// Replace tags by spaces and decode the predefined entities.
+fn strip_markup(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        stripped.push_str(&rest[..start]);
+        stripped.push(' ');
+        rest = match rest[start..].find('>') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+
+    stripped.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Document, JsonlReader, TextReader, TrecReader, tokenize};
+
+    static TREC: &str = "<DOC>\n\
+                         <DOCNO> LA010189-0001 </DOCNO>\n\
+                         <HEADLINE>\n<P>Rain in New York</P>\n</HEADLINE>\n\
+                         <TEXT>\nIt rains &amp; pours.\n</TEXT>\n\
+                         </DOC>\n\
+                         <DOC>\n<TEXT>No number</TEXT>\n</DOC>\n";
+
+    #[test]
+    fn collection_readers() {
+        assert_eq!(tokenize("Don't e-mail (the) U.N.  -- 'quotes' 3.5ms!"),
+                   vec!["Don't", "e-mail", "the", "U", "N", "quotes", "3", "5ms"]);
+
+        let docs: Vec<_> = TextReader::new(Cursor::new("\n\nTitle one\nis here\n\n\nTwo\n"))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(docs,
+                   vec![Document::new(None, Some("Title one".to_owned()), "Title one\nis here"
+                            .to_owned()),
+                        Document::new(None, Some("Two".to_owned()), "Two".to_owned())]);
+        let sentence = docs[0].to_sentence();
+        let lemmas: Vec<_> = sentence.iter().map(|token| token.lemma()).collect();
+        assert_eq!(lemmas, vec![Some("Title"), Some("one"), Some("is"), Some("here")]);
+
+        let jsonl = "{\"id\": 7, \"body\": \"a b\", \"title\": \"A\"}\n\n\
+                     {\"id\": \"x\", \"body\": \"c\"}\n\
+                     {\"id\": 8}\n\
+                     [";
+        let docs: Vec<_> = JsonlReader::new(Cursor::new(jsonl), "id", "body", "title").collect();
+        assert_eq!(docs[0].as_ref().unwrap(),
+                   &Document::new(Some("7".to_owned()), Some("A".to_owned()), "a b".to_owned()));
+        assert_eq!(docs[1].as_ref().unwrap(),
+                   &Document::new(Some("x".to_owned()), None, "c".to_owned()));
+        assert_eq!(docs[2].as_ref().unwrap_err().to_string(),
+                   "Line 4: no string field 'body'");
+        assert!(docs[3].is_err());
+
+        let docs: Vec<_> = TrecReader::new(Cursor::new(TREC)).map(Result::unwrap).collect();
+        assert_eq!(docs[0].id(), Some("LA010189-0001"));
+        assert_eq!(docs[0].title(), Some("Rain in New York"));
+        assert_eq!(tokenize(docs[0].text()),
+                   vec!["Rain", "in", "New", "York", "It", "rains", "pours"]);
+        assert_eq!(docs[1], Document::new(None, None, " No number \n".to_owned()));
+        assert_eq!(docs.len(), 2);
+
+        let docs: Vec<_> = TrecReader::new(Cursor::new("<DOC>\ntext\n")).collect();
+        assert_eq!(docs[0].as_ref().unwrap_err().to_string(), "Line 1: unterminated <DOC>");
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::btree_map::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::Bound;
+
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use super::{InvertedIndex, Posting};
+use super::codec::{Codec, CompressedPosting};
+use super::index::{dictionary_range, posting_from_vec};
+
+/// In-memory inverted index with compressed postings lists. Postings
+/// lists are decompressed when they are retrieved. Conjunctions of terms
+/// are intersected on the compressed lists, see `intersect_terms`.
+pub struct CompressedMemoryIndex<N> {
+    codec: Codec,
+    terms: BTreeMap<String, CompressedPosting>,
+    _phantom: PhantomData<N>,
+}
+
+impl<N> CompressedMemoryIndex<N>
+    where N: 'static + Clone + Ord + ToPrimitive
+{
+    /// Construct a compressed index from another inverted index, using
+    /// the given codec.
+    pub fn from_index(index: &dyn InvertedIndex<N>, codec: Codec) -> Self {
+        let terms = index.iter()
+            .map(|(term, posting)| {
+                (term.to_owned(), CompressedPosting::encode(posting.as_slice(), codec))
+            })
+            .collect();
+
+        CompressedMemoryIndex {
+            codec,
+            terms,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<N> CompressedMemoryIndex<N> {
+    /// Get the codec that is used to compress postings lists.
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Get the compressed postings list of a term.
+    pub fn compressed_posting(&self, term: &str) -> Option<&CompressedPosting> {
+        self.terms.get(term)
+    }
+
+    /// Get the total size of the compressed postings lists in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.terms.values().map(CompressedPosting::size_in_bytes).sum()
+    }
+}
+
+impl<N> InvertedIndex<N> for CompressedMemoryIndex<N>
+    where N: 'static + Clone + FromPrimitive + Ord
+{
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(self.terms.iter().map(|(term, compressed)| (term.as_str(), decode(compressed))))
+    }
+
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(dictionary_range(&self.terms, from, to)
+            .map(|(term, compressed)| (term.as_str(), decode(compressed))))
+    }
+
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
+        self.terms.get(term).map(decode)
+    }
+
+    fn doc_freq(&self, term: &str) -> usize {
+        self.terms.get(term).map(CompressedPosting::len).unwrap_or(0)
+    }
+
+    fn intersect_terms(&self, terms: &[&str]) -> Option<Posting<'_, N>> {
+        let mut postings = Vec::with_capacity(terms.len());
+        for term in terms {
+            match self.terms.get(*term) {
+                Some(posting) => postings.push(posting),
+                None => return Some(posting_from_vec(Vec::new())),
+            }
+        }
+
+        // Start with the smallest lists, only the result is decoded
+        // completely.
+        postings.sort_by_key(|posting| posting.len());
+        let mut docs = match postings[..] {
+            [] => return None,
+            [posting] => return Some(decode(posting)),
+            [first, second, ..] => first.intersect(second),
+        };
+
+        for posting in &postings[2..] {
+            if docs.is_empty() {
+                break;
+            }
+
+            docs = posting.intersect_sorted(&docs);
+        }
+
+        Some(posting_from_vec(docs.into_iter().map(from_u64).collect()))
+    }
+
+    fn len(&self) -> usize {
+        self.terms.len()
+    }
+}
+
+fn decode<N>(compressed: &CompressedPosting) -> Posting<'static, N>
+    where N: 'static + Clone + FromPrimitive + Ord
+{
+    posting_from_vec(compressed.iter().map(from_u64).collect())
+}
+
+fn from_u64<N>(doc: u64) -> N
+    where N: FromPrimitive
+{
+    // Document identifiers were converted from N when compressing.
+    N::from_u64(doc).expect("Document identifier out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompressedMemoryIndex;
+    use super::super::{InvertedIndex, InvertedIndexMut, MemoryIndex};
+    use super::super::codec::Codec;
+
+    #[test]
+    fn compressed_index_postings() {
+        let mut index = MemoryIndex::new();
+        index.add_postings_list("new", vec![1u32, 5, 200, 70000]);
+        index.add_postings_list("york", vec![5u32]);
+
+        for &codec in &[Codec::VByte, Codec::Gamma, Codec::Delta] {
+            let compressed = CompressedMemoryIndex::from_index(&index, codec);
+            assert_eq!(compressed.len(), 2);
+            assert_eq!(compressed.posting("new").unwrap().as_slice(), &[1, 5, 200, 70000]);
+            assert_eq!(compressed.posting("york").unwrap().as_slice(), &[5]);
+            assert!(compressed.posting("jersey").is_none());
+            assert_eq!(compressed.doc_freq("new"), 4);
+            assert_eq!(compressed.doc_freq("jersey"), 0);
+
+            assert_eq!(compressed.intersect_terms(&["new", "york"]).unwrap().as_slice(), &[5]);
+            assert_eq!(compressed.intersect_terms(&["york"]).unwrap().as_slice(), &[5]);
+            assert!(compressed.intersect_terms(&["new", "jersey"]).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+/// Errors for reading document lengths.
+#[derive(Debug)]
+pub enum DocLengthError {
+    Io(io::Error),
+    NoId,
+    NoLength,
+    Parse,
+}
+
+impl From<io::Error> for DocLengthError {
+    fn from(err: io::Error) -> DocLengthError {
+        DocLengthError::Io(err)
+    }
+}
+
+impl fmt::Display for DocLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DocLengthError::Io(ref err) => write!(f, "{}", err),
+            DocLengthError::NoId => write!(f, "No identifier found"),
+            DocLengthError::NoLength => write!(f, "No document length found"),
+            DocLengthError::Parse => write!(f, "Could not parse document length"),
+        }
+    }
+}
+
+/// Document lengths (in tokens), as used for length normalization in
+/// ranked retrieval.
+pub struct DocLengths<N> {
+    lengths: HashMap<N, u32>,
+    total: u64,
+}
+
+impl<N> DocLengths<N>
+    where N: Eq + Hash
+{
+    /// Construct an empty document length table.
+    pub fn new() -> Self {
+        DocLengths {
+            lengths: HashMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Add `n` tokens to the length of a document.
+    pub fn add(&mut self, doc: N, n: u32) {
+        *self.lengths.entry(doc).or_insert(0) += n;
+        self.total += n as u64;
+    }
+
+    /// Merge the lengths of another table. The lengths of documents that
+    /// occur in both tables are added.
+    pub fn merge(&mut self, other: DocLengths<N>) {
+        for (doc, n) in other.lengths {
+            self.add(doc, n);
+        }
+    }
+
+    /// Get the average document length.
+    pub fn avg_len(&self) -> f64 {
+        if self.lengths.is_empty() {
+            0.
+        } else {
+            self.total as f64 / self.lengths.len() as f64
+        }
+    }
+
+    /// Get the length of a document.
+    pub fn get(&self, doc: &N) -> Option<u32> {
+        self.lengths.get(doc).cloned()
+    }
+
+    /// Returns `true` if there are no documents.
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+
+    /// Get the number of documents.
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+}
+
+impl<N> Default for DocLengths<N>
+    where N: Eq + Hash
+{
+    fn default() -> Self {
+        DocLengths::new()
+    }
+}
+
+impl<N> DocLengths<N>
+    where N: Eq + FromStr + Hash
+{
+    /// Read document lengths from a buffered reader. The expected format
+    /// is: one (document id, length) pair per line, separated by a tab.
+    pub fn from_buf_read<R>(reader: R) -> Result<Self, DocLengthError>
+        where R: BufRead
+    {
+        let mut lengths = DocLengths::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut iter = line.split('\t');
+
+            let doc = match iter.next() {
+                Some(doc) => doc.parse().map_err(|_| DocLengthError::Parse)?,
+                None => return Err(DocLengthError::NoId),
+            };
+
+            let len = match iter.next() {
+                Some(len) => len.parse().map_err(|_| DocLengthError::Parse)?,
+                None => return Err(DocLengthError::NoLength),
+            };
+
+            lengths.add(doc, len);
+        }
+
+        Ok(lengths)
+    }
+}
+
+impl<N> DocLengths<N>
+    where N: Eq + fmt::Display + Hash + Ord
+{
+    /// Write the document lengths in the format read by `from_buf_read`.
+    /// Documents are written in ascending order.
+    pub fn to_text<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        let mut lengths: Vec<_> = self.lengths.iter().collect();
+        lengths.sort();
+
+        for (doc, len) in lengths {
+            writeln!(writer, "{}\t{}", doc, len)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use super::Metadata;
+
+/// Errors for reading relevance judgments and runs.
+#[derive(Debug)]
+pub enum EvaluationError {
+    Io(io::Error),
+    MissingColumn(usize),
+    Parse(usize, String),
+}
+
+impl From<io::Error> for EvaluationError {
+    fn from(err: io::Error) -> EvaluationError {
+        EvaluationError::Io(err)
+    }
+}
+
+impl fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvaluationError::Io(ref err) => write!(f, "{}", err),
+            EvaluationError::MissingColumn(line) => write!(f, "Line {}: missing column", line),
+            EvaluationError::Parse(line, ref value) => {
+                write!(f, "Line {}: could not parse number: {}", line, value)
+            }
+        }
+    }
+}
+
+/// Relevance judgments in the TREC qrels format. Each line contains a
+/// query identifier, an iteration (which is ignored), a document
+/// identifier, and a relevance grade, separated by whitespace. Documents
+/// with a grade above zero are relevant.
+pub struct Qrels {
+    judgments: BTreeMap<String, HashMap<String, u32>>,
+}
+
+impl Qrels {
+    /// Read relevance judgments from a buffered reader.
+    pub fn from_buf_read<R>(reader: R) -> Result<Self, EvaluationError>
+        where R: BufRead
+    {
+        let mut judgments: BTreeMap<String, HashMap<String, u32>> = BTreeMap::new();
+
+        for_each_record(reader, 4, |line_no, columns| {
+            let grade = parse_column(line_no, columns[3])?;
+            judgments.entry(columns[0].to_owned())
+                .or_default()
+                .insert(columns[2].to_owned(), grade);
+            Ok(())
+        })?;
+
+        Ok(Qrels { judgments })
+    }
+
+    /// Get the identifiers of the judged queries, in lexicographic order.
+    pub fn queries(&self) -> Vec<&str> {
+        self.judgments.keys().map(String::as_str).collect()
+    }
+
+    /// Get the relevance grades of the judged documents of a query.
+    pub fn judgments(&self, query: &str) -> Option<&HashMap<String, u32>> {
+        self.judgments.get(query)
+    }
+}
+
+/// A run in the TREC format. Each line contains a query identifier, the
+/// literal `Q0`, a document identifier, a rank, a score, and a run tag,
+/// separated by whitespace. Documents are ordered by decreasing score,
+/// documents with the same score keep their order in the file.
+pub struct Run {
+    rankings: HashMap<String, Vec<(String, f64)>>,
+}
+
+impl Run {
+    /// Read a run from a buffered reader.
+    pub fn from_buf_read<R>(reader: R) -> Result<Self, EvaluationError>
+        where R: BufRead
+    {
+        let mut rankings: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+
+        for_each_record(reader, 5, |line_no, columns| {
+            let score: f64 = parse_column(line_no, columns[4])?;
+            if !score.is_finite() {
+                return Err(EvaluationError::Parse(line_no, columns[4].to_owned()));
+            }
+
+            rankings.entry(columns[0].to_owned())
+                .or_default()
+                .push((columns[2].to_owned(), score));
+            Ok(())
+        })?;
+
+        for ranking in rankings.values_mut() {
+            ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+        }
+
+        Ok(Run { rankings })
+    }
+
+    /// Get the ranked document identifiers of a query. Returns an empty
+    /// ranking if the run does not contain the query.
+    pub fn ranking(&self, query: &str) -> Vec<&str> {
+        self.rankings
+            .get(query)
+            .map(|ranking| ranking.iter().map(|(doc, _)| doc.as_str()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Retrieval quality metrics.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Metric {
+    /// The fraction of the first k documents that is relevant.
+    PrecisionAt(usize),
+
+    /// The fraction of the relevant documents that is retrieved.
+    Recall,
+
+    /// Average precision, the mean over queries is MAP.
+    AveragePrecision,
+
+    /// The reciprocal rank of the first relevant document, the mean over
+    /// queries is MRR.
+    ReciprocalRank,
+
+    /// Normalized discounted cumulative gain of the first k documents,
+    /// using the relevance grades as gains.
+    NdcgAt(usize),
+}
+
+impl Metric {
+    /// Compute the metric for a ranking, given the relevance grades of
+    /// the judged documents. Unjudged documents are not relevant.
+    pub fn compute(&self, ranking: &[&str], judgments: &HashMap<String, u32>) -> f64 {
+        let grade = |doc: &&str| judgments.get(*doc).cloned().unwrap_or(0);
+        let n_relevant = judgments.values().filter(|&&grade| grade > 0).count();
+
+        match *self {
+            Metric::PrecisionAt(k) => {
+                let hits = ranking.iter().take(k).filter(|doc| grade(doc) > 0).count();
+                hits as f64 / k as f64
+            }
+            Metric::Recall => {
+                if n_relevant == 0 {
+                    return 0.;
+                }
+
+                let hits = ranking.iter().filter(|doc| grade(doc) > 0).count();
+                hits as f64 / n_relevant as f64
+            }
+            Metric::AveragePrecision => {
+                if n_relevant == 0 {
+                    return 0.;
+                }
+
+                let mut hits = 0;
+                let mut sum = 0.;
+                for (rank, doc) in ranking.iter().enumerate() {
+                    if grade(doc) > 0 {
+                        hits += 1;
+                        sum += hits as f64 / (rank + 1) as f64;
+                    }
+                }
+
+                sum / n_relevant as f64
+            }
+            Metric::ReciprocalRank => {
+                ranking.iter()
+                    .position(|doc| grade(doc) > 0)
+                    .map(|rank| 1. / (rank + 1) as f64)
+                    .unwrap_or(0.)
+            }
+            Metric::NdcgAt(k) => {
+                let mut ideal: Vec<_> = judgments.values().cloned().collect();
+                ideal.sort_by(|a, b| b.cmp(a));
+
+                let ideal_dcg = dcg(ideal.into_iter().take(k));
+                if ideal_dcg == 0. {
+                    return 0.;
+                }
+
+                dcg(ranking.iter().take(k).map(grade)) / ideal_dcg
+            }
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_k = |k: &str| {
+            k.parse()
+                .ok()
+                .filter(|&k| k > 0)
+                .ok_or_else(|| format!("Invalid cutoff in metric: {}", s))
+        };
+
+        match s {
+            "recall" => Ok(Metric::Recall),
+            "map" => Ok(Metric::AveragePrecision),
+            "mrr" => Ok(Metric::ReciprocalRank),
+            _ => {
+                if let Some(k) = s.strip_prefix("P@") {
+                    Ok(Metric::PrecisionAt(parse_k(k)?))
+                } else if let Some(k) = s.strip_prefix("ndcg@") {
+                    Ok(Metric::NdcgAt(parse_k(k)?))
+                } else {
+                    Err(format!("Unknown metric: {}", s))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Metric::PrecisionAt(k) => write!(f, "P@{}", k),
+            Metric::Recall => write!(f, "recall"),
+            Metric::AveragePrecision => write!(f, "map"),
+            Metric::ReciprocalRank => write!(f, "mrr"),
+            Metric::NdcgAt(k) => write!(f, "ndcg@{}", k),
+        }
+    }
+}
+
+/// Evaluate a run. Returns the per-query values of each metric for the
+/// judged queries, in the order of `Qrels::queries`. Judged queries that
+/// are not in the run have an empty ranking.
+pub fn evaluate(qrels: &Qrels, run: &Run, metrics: &[Metric]) -> Vec<(String, Vec<f64>)> {
+    qrels.judgments
+        .iter()
+        .map(|(query, judgments)| {
+            let ranking = run.ranking(query);
+            let values = metrics.iter().map(|metric| metric.compute(&ranking, judgments)).collect();
+            (query.clone(), values)
+        })
+        .collect()
+}
+
+/// Get the identifier of a document in TREC runs. This is the `docno`
+/// field of the metadata, which holds the collection identifiers of TREC
+/// and JSON Lines documents that are not numbers. Other documents are
+/// identified by their number.
+pub fn trec_doc_id(metadata: &dyn Metadata, doc: u64) -> String {
+    metadata.get(doc, "docno").map(str::to_owned).unwrap_or_else(|| doc.to_string())
+}
+
+/// Discounted cumulative gain of a sequence of gains.
+fn dcg<I>(gains: I) -> f64
+    where I: Iterator<Item = u32>
+{
+    gains.enumerate()
+        .map(|(rank, gain)| gain as f64 / ((rank + 2) as f64).log2())
+        .fold(0., |sum, gain| sum + gain)
+}
+
+/// Call `f` with the line number and the whitespace-separated columns of
+/// every non-empty line, requiring at least `n_columns` columns.
+fn for_each_record<R, F>(reader: R, n_columns: usize, mut f: F) -> Result<(), EvaluationError>
+    where R: BufRead,
+          F: FnMut(usize, &[&str]) -> Result<(), EvaluationError>
+{
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let columns: Vec<_> = line.split_whitespace().collect();
+
+        if columns.is_empty() {
+            continue;
+        }
+
+        if columns.len() < n_columns {
+            return Err(EvaluationError::MissingColumn(idx + 1));
+        }
+
+        f(idx + 1, &columns)?;
+    }
+
+    Ok(())
+}
+
+fn parse_column<T>(line_no: usize, column: &str) -> Result<T, EvaluationError>
+    where T: FromStr
+{
+    column.parse().map_err(|_| EvaluationError::Parse(line_no, column.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Metric, Qrels, Run, evaluate, trec_doc_id};
+    use super::super::{DocIdAssigner, InvertedIndexMut, MemoryIndex, MemoryMetadata, Query,
+                       TrecReader, tokenize};
+
+    static QRELS: &str = "q1 0 d1 1\nq1 0 d2 2\nq1 0 d3 0\nq1 0 d4 1\nq2 0 d5 1\n";
+
+    static RUN: &str = "q1 Q0 d3 1 0.9 test\n\
+                        q1 Q0 d2 2 0.8 test\n\
+                        q1 Q0 d9 3 0.5 test\n\
+                        q1 Q0 d1 4 0.7 test\n";
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn evaluation_metrics() {
+        let qrels = Qrels::from_buf_read(Cursor::new(QRELS)).unwrap();
+        let run = Run::from_buf_read(Cursor::new(RUN)).unwrap();
+        assert_eq!(run.ranking("q1"), vec!["d3", "d2", "d1", "d9"]);
+
+        let metrics: Vec<Metric> = ["P@2", "recall", "map", "mrr", "ndcg@3"]
+            .iter()
+            .map(|metric| metric.parse().unwrap())
+            .collect();
+        assert_eq!(metrics[4].to_string(), "ndcg@3");
+
+        let results = evaluate(&qrels, &run, &metrics);
+        assert_eq!(results[0].0, "q1");
+
+        let q1 = &results[0].1;
+        assert_close(q1[0], 0.5);
+        assert_close(q1[1], 2. / 3.);
+        assert_close(q1[2], (1. / 2. + 2. / 3.) / 3.);
+        assert_close(q1[3], 0.5);
+        let dcg = 2. / 3f64.log2() + 1. / 2.;
+        let ideal_dcg = 2. + 1. / 3f64.log2() + 1. / 2.;
+        assert_close(q1[4], dcg / ideal_dcg);
+
+        // Judged queries without results score zero.
+        assert_eq!(results[1], ("q2".to_owned(), vec![0.; 5]));
+
+        assert!("P@0".parse::<Metric>().is_err());
+        assert!("bpref".parse::<Metric>().is_err());
+        assert!(Qrels::from_buf_read(Cursor::new("q1 0 d1\n")).is_err());
+        assert!(Run::from_buf_read(Cursor::new("q1 Q0 d1 1 NaN test\n")).is_err());
+        assert!(Run::from_buf_read(Cursor::new("q1 Q0 d1 1 inf test\n")).is_err());
+    }
+
+    #[test]
+    fn trec_run_docnos() {
+        let trec = "<DOC>\n<DOCNO> FT911-1 </DOCNO>\n<TEXT>rain in york</TEXT>\n</DOC>\n\
+                    <DOC>\n<DOCNO> 7 </DOCNO>\n<TEXT>new york</TEXT>\n</DOC>\n\
+                    <DOC>\n<DOCNO> FT911-3 </DOCNO>\n<TEXT>new york city</TEXT>\n</DOC>\n";
+
+        // Index like create-index.
+        let mut assigner = DocIdAssigner::new();
+        let mut metadata = MemoryMetadata::new();
+        let mut index = MemoryIndex::new();
+        for document in TrecReader::new(Cursor::new(trec)) {
+            let document = document.unwrap();
+            let doc = assigner.assign(&document, &mut metadata).unwrap();
+            for term in tokenize(document.text()) {
+                index.add_term(term, doc);
+            }
+        }
+
+        let result = Query::parse("new york").unwrap().evaluate(&index).unwrap();
+        let run: String = result.iter()
+            .enumerate()
+            .map(|(rank, &doc)| {
+                format!("q1 Q0 {} {} {} test\n",
+                        trec_doc_id(&metadata, doc),
+                        rank + 1,
+                        result.len() - rank)
+            })
+            .collect();
+        assert_eq!(run, "q1 Q0 7 1 2 test\nq1 Q0 FT911-3 2 1 test\n");
+
+        let qrels = Qrels::from_buf_read(Cursor::new("q1 0 FT911-3 1\nq1 0 7 1\n")).unwrap();
+        let run = Run::from_buf_read(Cursor::new(run)).unwrap();
+        let results = evaluate(&qrels, &run, &["recall".parse().unwrap()]);
+        assert_close(results[0].1[0], 1.);
+
+        let duplicate = "<DOC>\n<DOCNO> 7 </DOCNO>\n</DOC>\n";
+        let document = TrecReader::new(Cursor::new(duplicate)).next().unwrap().unwrap();
+        assert!(assigner.assign(&document, &mut metadata).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+use std::str::FromStr;
+
+use conllx::Sentence;
+
+/// Errors for extracting the document identifier of a sentence. Sentences
+/// are numbered from 1.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IndexingError {
+    EmptySentence(usize),
+    NoFeatures(usize),
+    NoDocIdFeature(usize, String),
+    InvalidDocId(usize, String),
+}
+
+impl fmt::Display for IndexingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexingError::EmptySentence(sentence) => {
+                write!(f, "Sentence {}: sentence has no tokens", sentence)
+            }
+            IndexingError::NoFeatures(sentence) => {
+                write!(f, "Sentence {}: first token has no features", sentence)
+            }
+            IndexingError::NoDocIdFeature(sentence, ref key) => {
+                write!(f, "Sentence {}: first token has no '{}' feature", sentence, key)
+            }
+            IndexingError::InvalidDocId(sentence, ref doc) => {
+                write!(f, "Sentence {}: invalid document identifier: {}", sentence, doc)
+            }
+        }
+    }
+}
+
+/// Policies for sentences without a valid document identifier.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DocIdPolicy {
+    /// Skip the sentence, the error is recorded in the report.
+    #[default]
+    Skip,
+
+    /// Stop indexing with an error.
+    Fail,
+
+    /// Assign the sentence to a new document, with the identifier that
+    /// follows the highest identifier seen so far. This assumes that the
+    /// identifiers in the input are ascending.
+    Sequential,
+}
+
+impl FromStr for DocIdPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(DocIdPolicy::Skip),
+            "fail" => Ok(DocIdPolicy::Fail),
+            "sequential" => Ok(DocIdPolicy::Sequential),
+            _ => Err(format!("Unknown document identifier policy: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for DocIdPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DocIdPolicy::Skip => write!(f, "skip"),
+            DocIdPolicy::Fail => write!(f, "fail"),
+            DocIdPolicy::Sequential => write!(f, "sequential"),
+        }
+    }
+}
+
+/// Summary of the sentences that were processed and the sentences that
+/// did not have a valid document identifier.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IndexingReport {
+    sentences: usize,
+    errors: Vec<IndexingError>,
+}
+
+impl IndexingReport {
+    /// Get the number of processed sentences.
+    pub fn sentences(&self) -> usize {
+        self.sentences
+    }
+
+    /// Get the errors of sentences without a valid document identifier.
+    pub fn errors(&self) -> &[IndexingError] {
+        &self.errors
+    }
+}
+
+/// Extracts document identifiers from the features of the first token of
+/// a sentence. The identifier is either the complete features column or
+/// the value of a `key=value` feature.
+pub struct DocIdExtractor {
+    key: Option<String>,
+    policy: DocIdPolicy,
+    next_doc: u64,
+    report: IndexingReport,
+}
+
+impl DocIdExtractor {
+    /// Construct an extractor. If `key` is `None`, the complete features
+    /// column is used as the identifier.
+    pub fn new(key: Option<String>, policy: DocIdPolicy) -> Self {
+        DocIdExtractor {
+            key,
+            policy,
+            next_doc: 0,
+            report: IndexingReport::default(),
+        }
+    }
+
+    /// Get the document identifier of the next sentence. Returns `None`
+    /// if the sentence should be skipped. Errors are only returned with
+    /// the `Fail` policy.
+    pub fn doc_id(&mut self, sentence: &Sentence) -> Result<Option<u64>, IndexingError> {
+        self.report.sentences += 1;
+
+        match self.extract(sentence) {
+            Ok(doc) => {
+                self.next_doc = self.next_doc.max(doc.saturating_add(1));
+                Ok(Some(doc))
+            }
+            Err(err) => {
+                self.report.errors.push(err.clone());
+
+                match self.policy {
+                    DocIdPolicy::Skip => Ok(None),
+                    DocIdPolicy::Fail => Err(err),
+                    DocIdPolicy::Sequential => {
+                        self.next_doc += 1;
+                        Ok(Some(self.next_doc - 1))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the report of the sentences processed so far.
+    pub fn report(&self) -> &IndexingReport {
+        &self.report
+    }
+
+    fn extract(&self, sentence: &Sentence) -> Result<u64, IndexingError> {
+        let n = self.report.sentences;
+
+        let token = sentence.as_tokens().first().ok_or(IndexingError::EmptySentence(n))?;
+        let features = token.features().ok_or(IndexingError::NoFeatures(n))?;
+
+        let doc_str = match self.key {
+            Some(ref key) => {
+                feature_value(features.as_str(), key)
+                    .ok_or_else(|| IndexingError::NoDocIdFeature(n, key.clone()))?
+            }
+            None => features.as_str(),
+        };
+
+        doc_str.trim().parse().map_err(|_| IndexingError::InvalidDocId(n, doc_str.to_owned()))
+    }
+}
+
+/// Get the value of a feature in a features column of the form
+/// `key1=value1|key2=value2`.
+fn feature_value<'a>(features: &'a str, key: &str) -> Option<&'a str> {
+    features.split('|')
+        .filter_map(|feature| feature.split_once('='))
+        .find(|&(k, _)| k == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use conllx::{Sentence, TokenBuilder};
+
+    use super::{DocIdExtractor, DocIdPolicy, IndexingError};
+
+    fn sentence(features: Option<&str>) -> Sentence {
+        let mut builder = TokenBuilder::new().form("a");
+        if let Some(features) = features {
+            builder = builder.features(features);
+        }
+
+        Sentence::new(vec![builder.token()])
+    }
+
+    #[test]
+    fn doc_id_policies() {
+        let sentences = [sentence(Some("doc=3|lang=nl")),
+                         sentence(None),
+                         sentence(Some("doc=x")),
+                         sentence(Some("lang=nl")),
+                         Sentence::new(Vec::new())];
+
+        let mut skip = DocIdExtractor::new(Some("doc".to_owned()), DocIdPolicy::Skip);
+        let docs: Vec<_> = sentences.iter().map(|s| skip.doc_id(s).unwrap()).collect();
+        assert_eq!(docs, vec![Some(3), None, None, None, None]);
+        assert_eq!(skip.report().sentences(), 5);
+        assert_eq!(skip.report().errors(),
+                   &[IndexingError::NoFeatures(2),
+                     IndexingError::InvalidDocId(3, "x".to_owned()),
+                     IndexingError::NoDocIdFeature(4, "doc".to_owned()),
+                     IndexingError::EmptySentence(5)]);
+
+        let mut sequential = DocIdExtractor::new(Some("doc".to_owned()), DocIdPolicy::Sequential);
+        let docs: Vec<_> = sentences.iter().map(|s| sequential.doc_id(s).unwrap()).collect();
+        assert_eq!(docs, vec![Some(3), Some(4), Some(5), Some(6), Some(7)]);
+
+        let mut fail = DocIdExtractor::new(None, DocIdPolicy::Fail);
+        assert_eq!(fail.doc_id(&sentence(Some("42"))), Ok(Some(42)));
+        assert_eq!(fail.doc_id(&sentence(Some(&u64::MAX.to_string()))), Ok(Some(u64::MAX)));
+        assert_eq!(fail.doc_id(&sentence(Some("doc=3"))),
+                   Err(IndexingError::InvalidDocId(3, "doc=3".to_owned())));
+    }
+}
@@ -0,0 +1,235 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use conllx::Token;
+
+use super::Normalizer;
+
+/// CoNLL-X columns that can be indexed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    Form,
+    Lemma,
+    CPos,
+    Pos,
+}
+
+impl Field {
+    /// Get the value of the field for a token.
+    pub fn value<'a>(&self, token: &'a Token) -> Option<&'a str> {
+        match *self {
+            Field::Form => token.form(),
+            Field::Lemma => token.lemma(),
+            Field::CPos => token.cpos(),
+            Field::Pos => token.pos(),
+        }
+    }
+
+    /// Returns `true` if the field contains words, rather than tags. Only
+    /// word fields are normalized.
+    pub fn is_word(&self) -> bool {
+        matches!(*self, Field::Form | Field::Lemma)
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Field::Form => "form",
+            Field::Lemma => "lemma",
+            Field::CPos => "cpos",
+            Field::Pos => "pos",
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "form" => Ok(Field::Form),
+            "lemma" => Ok(Field::Lemma),
+            "cpos" => Ok(Field::CPos),
+            "pos" => Ok(Field::Pos),
+            _ => Err(format!("Unknown field: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The fields that are stored in an index. When a single field is
+/// indexed, its values are used as terms. When multiple fields are
+/// indexed, terms are qualified with the field name, as in `lemma:run` or
+/// `pos:VB`. The first field is the default field for unqualified terms in
+/// queries.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fields {
+    fields: Vec<Field>,
+}
+
+impl Fields {
+    /// Construct a set of fields. The set of fields should not be empty.
+    pub fn new(fields: Vec<Field>) -> Self {
+        assert!(!fields.is_empty(), "At least one field should be indexed");
+        Fields { fields }
+    }
+
+    /// Get the indexed fields.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Get the terms of a token, one for every indexed field that the
+    /// token has a value for. The values of word fields are normalized
+    /// using `normalizer`. Returns `None` if the normalizer removes the
+    /// token, e.g. because it is a stopword.
+    pub fn terms<'a>(&self,
+                     token: &'a Token,
+                     normalizer: &dyn Normalizer)
+                     -> Option<Vec<Cow<'a, str>>> {
+        let mut terms = Vec::with_capacity(self.fields.len());
+
+        for &field in &self.fields {
+            let value = match field.value(token) {
+                Some(value) => Cow::Borrowed(value),
+                None => continue,
+            };
+
+            let value = if field.is_word() {
+                normalizer.normalize(value, token.pos())?
+            } else {
+                value
+            };
+
+            terms.push(self.qualify(field, value));
+        }
+
+        Some(terms)
+    }
+
+    /// Rewrite a query term to the term in the index. Unqualified terms
+    /// are qualified with the default field when multiple fields are
+    /// indexed. The qualifier is removed when only its field is indexed.
+    /// Values of word fields are normalized with `normalizer`, which
+    /// should be the normalizer that was used for indexing. Returns `None`
+    /// if the normalizer removes the term.
+    ///
+    /// Wildcard patterns only get character-level normalization, such as
+    /// lowercasing, since stemmers and stopword filters do not apply to
+    /// partial words.
+    pub fn index_term(&self, term: &str, normalizer: &dyn Normalizer) -> Option<String> {
+        let (field, value) = split_qualified(term).unwrap_or((self.fields[0], term));
+
+        let value = if !field.is_word() {
+            Cow::Borrowed(value)
+        } else if value.contains('*') {
+            normalizer.normalize_pattern(Cow::Borrowed(value))
+        } else {
+            normalizer.normalize(Cow::Borrowed(value), None)?
+        };
+
+        if self.fields.len() == 1 && field != self.fields[0] {
+            // Keep the qualifier, the term does not occur in the index.
+            return Some(term.to_owned());
+        }
+
+        Some(self.qualify(field, value).into_owned())
+    }
+
+    fn qualify<'a>(&self, field: Field, value: Cow<'a, str>) -> Cow<'a, str> {
+        if self.fields.len() > 1 {
+            Cow::Owned(format!("{}:{}", field, value))
+        } else {
+            value
+        }
+    }
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields::new(vec![Field::Lemma])
+    }
+}
+
+impl FromStr for Fields {
+    type Err = String;
+
+    /// Parse a comma-separated list of fields, such as `lemma,pos`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split(',')
+            .map(|field| field.trim().parse())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Fields::new(fields))
+    }
+}
+
+/// Split a field-qualified term into its field and value.
+fn split_qualified(term: &str) -> Option<(Field, &str)> {
+    let idx = term.find(':')?;
+    let field = term[..idx].parse().ok()?;
+    Some((field, &term[idx + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use conllx::TokenBuilder;
+
+    use super::{Field, Fields};
+    use super::super::{Lowercase, Pipeline, TagStopwords};
+    use classify::PTBStopwordFilter;
+
+    #[test]
+    fn field_terms() {
+        let token = TokenBuilder::new().form("ran").lemma("run").pos("VBD").token();
+
+        let identity = Pipeline::new();
+
+        let lemma: Fields = "lemma".parse().unwrap();
+        assert_eq!(lemma.terms(&token, &identity), Some(vec!["run".into()]));
+
+        let fields: Fields = "form,lemma,cpos,pos".parse().unwrap();
+        assert_eq!(fields.terms(&token, &identity),
+                   Some(vec!["form:ran".into(), "lemma:run".into(), "pos:VBD".into()]));
+
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Lowercase);
+        pipeline.push(TagStopwords::new(PTBStopwordFilter));
+        let the = TokenBuilder::new().form("The").lemma("the").pos("DT").token();
+        assert_eq!(fields.terms(&the, &pipeline), None);
+        let token = TokenBuilder::new().form("Ran").lemma("run").pos("VBD").token();
+        assert_eq!(fields.terms(&token, &pipeline),
+                   Some(vec!["form:ran".into(), "lemma:run".into(), "pos:VBD".into()]));
+
+        assert!("lemma,tag".parse::<Fields>().is_err());
+        assert_eq!(Fields::default().fields(), &[Field::Lemma]);
+    }
+
+    #[test]
+    fn index_terms() {
+        let mut lowercase = Pipeline::new();
+        lowercase.push(Lowercase);
+
+        let lemma = Fields::default();
+        assert_eq!(lemma.index_term("Run", &lowercase), Some("run".to_owned()));
+        assert_eq!(lemma.index_term("lemma:run", &lowercase), Some("run".to_owned()));
+        assert_eq!(lemma.index_term("pos:VB", &lowercase), Some("pos:VB".to_owned()));
+
+        let fields: Fields = "lemma,pos".parse().unwrap();
+        assert_eq!(fields.index_term("run", &lowercase), Some("lemma:run".to_owned()));
+        assert_eq!(fields.index_term("pos:VB", &lowercase), Some("pos:VB".to_owned()));
+        assert_eq!(fields.index_term("12:30", &lowercase), Some("lemma:12:30".to_owned()));
+        assert_eq!(fields.index_term("Comp*", &lowercase), Some("lemma:comp*".to_owned()));
+        assert_eq!(fields.index_term("pos:VB*", &lowercase), Some("pos:VB*".to_owned()));
+
+        // Patterns are not stemmed, they are partial words.
+        let stem = Pipeline::from_spec("lowercase,stem=english").unwrap();
+        assert_eq!(fields.index_term("Running", &stem), Some("lemma:run".to_owned()));
+        assert_eq!(fields.index_term("Running*", &stem), Some("lemma:running*".to_owned()));
+    }
+}
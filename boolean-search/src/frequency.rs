@@ -0,0 +1,246 @@
+use std::borrow::Cow;
+use std::collections::btree_map;
+use std::collections::btree_map::BTreeMap;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+use std::ops::Bound;
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+use super::*;
+use super::index::{dictionary_range, posting_from_cow, posting_from_ref};
+
+/// An inverted index that stores the frequency of a term in each document
+/// of its postings list.
+pub trait FrequencyIndex<N: Clone + Ord>: InvertedIndex<N> {
+    /// Retrieve the postings list for a term with term frequencies.
+    fn term_frequencies(&self, term: &str) -> Option<FrequencyPosting<'_, N>>;
+}
+
+/// A postings list where each document has the frequency of the term in
+/// that document.
+#[derive(Clone, Debug)]
+pub struct FrequencyPosting<'a, N>
+    where N: Ord + Clone + 'a
+{
+    docs: Cow<'a, [N]>,
+    freqs: Cow<'a, [u32]>,
+}
+
+impl<'a, N> FrequencyPosting<'a, N>
+    where N: Clone + Ord
+{
+    /// Get an iterator over the documents and term frequencies.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&N, u32)> + '_> {
+        Box::new(self.docs.iter().zip(self.freqs.iter().cloned()))
+    }
+
+    /// Returns `true` if the postings list does not contain any documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Get the number of documents in the postings list, which is also
+    /// the document frequency of the term.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Convert to a postings list without frequencies.
+    pub fn into_posting(self) -> Posting<'a, N> {
+        posting_from_cow(self.docs)
+    }
+}
+
+pub fn frequency_posting_from_cows<'a, N>(docs: Cow<'a, [N]>,
+                                          freqs: Cow<'a, [u32]>)
+                                          -> FrequencyPosting<'a, N>
+    where N: Clone + Ord
+{
+    assert_eq!(docs.len(), freqs.len());
+
+    FrequencyPosting { docs, freqs }
+}
+
+struct FrequencyPostings<N> {
+    docs: Vec<N>,
+    freqs: Vec<u32>,
+}
+
+impl<N> FrequencyPostings<N> {
+    fn new() -> Self {
+        FrequencyPostings {
+            docs: Vec::new(),
+            freqs: Vec::new(),
+        }
+    }
+}
+
+/// In-memory inverted index with term frequencies.
+pub struct FrequencyMemoryIndex<N> {
+    terms: BTreeMap<String, FrequencyPostings<N>>,
+}
+
+impl<N> FrequencyMemoryIndex<N>
+    where N: Ord
+{
+    /// Construct an empty in-memory inverted index with term frequencies.
+    pub fn new() -> FrequencyMemoryIndex<N> {
+        FrequencyMemoryIndex { terms: BTreeMap::new() }
+    }
+}
+
+impl<N> Default for FrequencyMemoryIndex<N>
+    where N: Ord
+{
+    fn default() -> Self {
+        FrequencyMemoryIndex::new()
+    }
+}
+
+impl<N: Ord> InvertedIndexMut<N> for FrequencyMemoryIndex<N> {
+    /// Add a term-docid pair to the inverted index. Every addition of the
+    /// pair increments the frequency of the term in the document.
+    fn add_term(&mut self, term: &str, doc: N) {
+        let postings = self.terms.entry(term.to_owned()).or_insert_with(FrequencyPostings::new);
+
+        match postings.docs.binary_search(&doc) {
+            Ok(idx) => postings.freqs[idx] += 1,
+            Err(idx) => {
+                postings.docs.insert(idx, doc);
+                postings.freqs.insert(idx, 1);
+            }
+        }
+    }
+
+    /// Add a postings lists for a term. The term frequency of every
+    /// document is set to one.
+    fn add_postings_list<D>(&mut self, term: &str, docs: D)
+        where D: Into<Vec<N>>
+    {
+        let docs = docs.into();
+        let freqs = vec![1; docs.len()];
+        self.terms.insert(term.to_owned(), FrequencyPostings { docs, freqs });
+    }
+}
+
+impl<N> InvertedIndexFromText<N> for FrequencyMemoryIndex<N>
+    where N: FromStr + Ord
+{
+    /// Read an inverted index with term frequencies from a buffered
+    /// reader. The expected format is:
+    ///
+    /// * Each line contains a term and postings list.
+    /// * The term and postings list are separated by the tab character.
+    /// * The postings list consists of documents separated by the space
+    ///   character.
+    /// * A document is an unsigned integer, followed by a colon, followed
+    ///   by the frequency of the term in the document.
+    fn from_text<R>(reader: R) -> Result<Self, TextReadError>
+        where R: BufRead
+    {
+        let mut index = FrequencyMemoryIndex::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut iter = line.split_whitespace();
+
+            let term = iter.next().ok_or(TextReadError::NoTerm)?;
+
+            let mut postings = FrequencyPostings::new();
+            for doc_str in iter {
+                let mut doc_iter = doc_str.splitn(2, ':');
+                let doc = doc_iter.next().ok_or(TextReadError::Parse)?;
+                let freq = doc_iter.next().ok_or(TextReadError::Parse)?;
+
+                postings.docs.push(doc.parse().map_err(|_| TextReadError::Parse)?);
+                postings.freqs.push(freq.parse().map_err(|_| TextReadError::Parse)?);
+            }
+
+            if !is_sorted_uniq(&postings.docs) {
+                return Err(TextReadError::NotSortedOrUnique(line.to_owned()));
+            }
+
+            index.terms.insert(term.to_owned(), postings);
+        }
+
+        Ok(index)
+    }
+}
+
+impl<N> InvertedIndexToText<N> for FrequencyMemoryIndex<N>
+    where N: fmt::Display
+{
+    fn to_text<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        for (term, postings) in &self.terms {
+            write!(writer, "{}\t", term)?;
+            let docs_str = postings.docs
+                .iter()
+                .zip(&postings.freqs)
+                .map(|(doc, freq)| format!("{}:{}", doc, freq))
+                .join(" ");
+            writer.write_all(docs_str.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Iter<'a, N: 'a> {
+    term_postings_iter: btree_map::Range<'a, String, FrequencyPostings<N>>,
+}
+
+impl<'a, N> Iterator for Iter<'a, N>
+    where N: Clone + Ord
+{
+    type Item = (&'a str, Posting<'a, N>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.term_postings_iter.next().map(|(k, v)| (k.as_ref(), posting_from_ref(&v.docs)))
+    }
+}
+
+impl<N> InvertedIndex<N> for FrequencyMemoryIndex<N>
+    where N: Clone + Ord
+{
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(Iter { term_postings_iter: self.terms.range::<str, _>(..) })
+    }
+
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(Iter { term_postings_iter: dictionary_range(&self.terms, from, to) })
+    }
+
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
+        self.terms.get(term).map(|postings| posting_from_ref(&postings.docs))
+    }
+
+    fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    fn frequencies(&self) -> Option<&dyn FrequencyIndex<N>> {
+        Some(self)
+    }
+}
+
+impl<N> FrequencyIndex<N> for FrequencyMemoryIndex<N>
+    where N: Clone + Ord
+{
+    fn term_frequencies(&self, term: &str) -> Option<FrequencyPosting<'_, N>> {
+        self.terms.get(term).map(|postings| {
+            FrequencyPosting {
+                docs: Cow::Borrowed(&postings.docs),
+                freqs: Cow::Borrowed(&postings.freqs),
+            }
+        })
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Write};
+use std::mem;
+use std::str::FromStr;
+
+use getopts::{Matches, Options};
+use serde_json::{Map, Value};
+
+use super::{BinaryReadError, Bm25, Codec, CompressedMemoryIndex, DocLengthError, DocLengths,
+            Fields, FrequencyMemoryIndex, IntersectStrategy, InvertedIndex,
+            InvertedIndexFromBinary, InvertedIndexFromText, MemoryIndex, Metadata, MmapIndex,
+            NormalizeError, Pipeline, PositionalMemoryIndex, Scorer, SegmentError,
+            SegmentedIndex, TextReadError, TfIdf, WildcardIndex};
+
+/// Errors for setting up the search front-ends from their options.
+#[derive(Debug)]
+pub enum FrontendError {
+    Binary(BinaryReadError),
+    DocLength(DocLengthError),
+    InvalidOption(String, String),
+    Io(io::Error),
+    Normalize(NormalizeError),
+    Segment(SegmentError),
+    Text(TextReadError),
+    Unsupported(String),
+}
+
+impl From<BinaryReadError> for FrontendError {
+    fn from(err: BinaryReadError) -> FrontendError {
+        FrontendError::Binary(err)
+    }
+}
+
+impl From<DocLengthError> for FrontendError {
+    fn from(err: DocLengthError) -> FrontendError {
+        FrontendError::DocLength(err)
+    }
+}
+
+impl From<io::Error> for FrontendError {
+    fn from(err: io::Error) -> FrontendError {
+        FrontendError::Io(err)
+    }
+}
+
+impl From<NormalizeError> for FrontendError {
+    fn from(err: NormalizeError) -> FrontendError {
+        FrontendError::Normalize(err)
+    }
+}
+
+impl From<SegmentError> for FrontendError {
+    fn from(err: SegmentError) -> FrontendError {
+        FrontendError::Segment(err)
+    }
+}
+
+impl From<TextReadError> for FrontendError {
+    fn from(err: TextReadError) -> FrontendError {
+        FrontendError::Text(err)
+    }
+}
+
+impl fmt::Display for FrontendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrontendError::Binary(ref err) => write!(f, "{}", err),
+            FrontendError::DocLength(ref err) => write!(f, "{}", err),
+            FrontendError::InvalidOption(ref name, ref err) => {
+                write!(f, "Invalid value of --{}: {}", name, err)
+            }
+            FrontendError::Io(ref err) => write!(f, "{}", err),
+            FrontendError::Normalize(ref err) => write!(f, "{}", err),
+            FrontendError::Segment(ref err) => write!(f, "{}", err),
+            FrontendError::Text(ref err) => write!(f, "{}", err),
+            FrontendError::Unsupported(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Add the options for reading an index and rewriting query terms to
+/// index terms, which are shared by `query-index` and `search-server`.
+/// See `open_index` and `index_term_options`.
+pub fn add_index_options(opts: &mut Options) {
+    opts.optflag("b", "binary", "read an index in the binary format");
+    opts.optopt("c",
+                "compress",
+                "compress postings lists in memory using CODEC (vbyte, gamma, or delta)",
+                "CODEC");
+    opts.optopt("F",
+                "fields",
+                "the comma-separated FIELDS of the index, as given to create-index, the first \
+                 field is used for unqualified terms (default: lemma)",
+                "FIELDS");
+    opts.optflag("f",
+                 "frequencies",
+                 "read an index with term frequencies, for ranked retrieval");
+    opts.optopt("I",
+                "intersect",
+                "intersect postings lists pairwise using STRATEGY (adaptive, linear, \
+                 binsearch, galloping, skip, or skip=N), rather than all at once",
+                "STRATEGY");
+    opts.optflag("m",
+                 "mmap",
+                 "memory-map an index in the memory-mappable format");
+    opts.optflag("p",
+                 "positional",
+                 "read a positional index, for phrase and proximity queries");
+    opts.optopt("N",
+                "normalize",
+                "normalize query terms using PIPELINE, as given to create-index",
+                "PIPELINE");
+    opts.optflag("s",
+                 "segments",
+                 "INDEX_FILE is a segmented index directory, see segment-index");
+    opts.optflag("w",
+                 "wildcard",
+                 "store terms in tries, for faster wildcard queries");
+}
+
+/// Add the options of the ranking models, see `scorer`.
+pub fn add_ranking_options(opts: &mut Options) {
+    opts.optopt("",
+                "bm25-b",
+                "BM25 document length normalization (default: 0.75)",
+                "VALUE");
+    opts.optopt("",
+                "bm25-k1",
+                "BM25 term frequency saturation (default: 1.2)",
+                "VALUE");
+    opts.optopt("l",
+                "lengths",
+                "read document lengths from FILE, required for BM25",
+                "FILE");
+}
+
+/// Open the index in `path` in the format that is given by the options
+/// of `add_index_options`. Postings lists are compressed in memory with
+/// `-c`, in which case the compressed size is reported, and terms are
+/// stored in tries with `-w`. Options of different formats cannot be
+/// combined.
+pub fn open_index(path: &str,
+                  matches: &Matches)
+                  -> Result<Box<dyn InvertedIndex<u64>>, FrontendError> {
+    // A positional index also has term frequencies, so -p can be combined
+    // with -f.
+    let formats: Vec<_> = ["s", "m", "b", "p", "f"]
+        .iter()
+        .filter(|&&name| matches.opt_present(name))
+        .map(|name| format!("-{}", name))
+        .collect();
+    if formats.len() > 1 && formats != ["-p", "-f"] {
+        return Err(FrontendError::Unsupported(format!("Index format options cannot be \
+                                                       combined: {}",
+                                                      formats.join(" "))));
+    }
+
+    let codec: Option<Codec> = parse_option(matches, "compress")?;
+    if codec.is_some() && (matches.opt_present("f") || matches.opt_present("p")) {
+        return Err(FrontendError::Unsupported("Compressed indexes do not support term \
+                                               frequencies or positions"
+            .to_owned()));
+    }
+
+    let open = || File::open(path).map(BufReader::new);
+    let index: Box<dyn InvertedIndex<u64>> = if matches.opt_present("s") {
+        Box::new(SegmentedIndex::open(path)?)
+    } else if matches.opt_present("m") {
+        Box::new(MmapIndex::open(path)?)
+    } else if matches.opt_present("b") {
+        Box::new(MemoryIndex::from_binary(open()?)?)
+    } else if matches.opt_present("p") {
+        Box::new(PositionalMemoryIndex::from_text(open()?)?)
+    } else if matches.opt_present("f") {
+        Box::new(FrequencyMemoryIndex::from_text(open()?)?)
+    } else {
+        Box::new(MemoryIndex::from_text(open()?)?)
+    };
+
+    let index: Box<dyn InvertedIndex<u64>> = match codec {
+        Some(codec) => {
+            let compressed = CompressedMemoryIndex::from_index(&*index, codec);
+
+            let raw_size: usize =
+                index.iter().map(|(_, posting)| posting.len() * mem::size_of::<u64>()).sum();
+            stderr!("Postings lists: {} bytes compressed, {} bytes uncompressed",
+                    compressed.size_in_bytes(),
+                    raw_size);
+
+            Box::new(compressed)
+        }
+        None => index,
+    };
+
+    if matches.opt_present("w") {
+        Ok(Box::new(WildcardIndex::new(index)))
+    } else {
+        Ok(index)
+    }
+}
+
+/// Get the fields and the normalization pipeline that rewrite query
+/// terms to index terms, see `Fields::index_term`.
+pub fn index_term_options(matches: &Matches) -> Result<(Fields, Pipeline), FrontendError> {
+    let fields = parse_option(matches, "fields")?.unwrap_or_default();
+    let pipeline = match matches.opt_str("normalize") {
+        Some(spec) => Pipeline::from_spec(&spec)?,
+        None => Pipeline::new(),
+    };
+
+    Ok((fields, pipeline))
+}
+
+/// Get the intersection strategy of conjunctions. Returns `None` if all
+/// postings lists should be intersected at once.
+pub fn intersect_strategy(matches: &Matches)
+                          -> Result<Option<IntersectStrategy>, FrontendError> {
+    parse_option(matches, "intersect")
+}
+
+/// Read the document lengths, if they were given.
+pub fn read_lengths(matches: &Matches) -> Result<Option<DocLengths<u64>>, FrontendError> {
+    match matches.opt_str("lengths") {
+        Some(filename) => {
+            let reader = BufReader::new(File::open(filename)?);
+            Ok(Some(DocLengths::from_buf_read(reader)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Construct the scorer of a ranking model, `bm25` or `tfidf`. Ranked
+/// retrieval requires an index with term frequencies, BM25 also
+/// requires document lengths.
+pub fn scorer<'a>(model: &str,
+                  index: &dyn InvertedIndex<u64>,
+                  lengths: Option<&'a DocLengths<u64>>,
+                  matches: &Matches)
+                  -> Result<Box<dyn Scorer<u64> + 'a>, FrontendError> {
+    let unsupported = |err: &str| FrontendError::Unsupported(err.to_owned());
+
+    let frequencies = index.frequencies()
+        .ok_or_else(|| unsupported("Ranked retrieval requires an index with term frequencies"))?;
+
+    match model {
+        "bm25" => {
+            let lengths = lengths.ok_or_else(|| unsupported("BM25 requires document lengths"))?;
+            let k1 = parse_option(matches, "bm25-k1")?.unwrap_or(1.2);
+            let b = parse_option(matches, "bm25-b")?.unwrap_or(0.75);
+            Ok(Box::new(Bm25::new(lengths, k1, b)))
+        }
+        "tfidf" => Ok(Box::new(TfIdf::new(frequencies))),
+        _ => Err(FrontendError::Unsupported(format!("Unknown ranking model: {}", model))),
+    }
+}
+
+/// Describe a result document as JSON, with its title, its metadata
+/// fields, and its score in ranked retrieval.
+pub fn result_json(metadata: &dyn Metadata, doc: u64, score: Option<f64>) -> Value {
+    let fields: Map<_, _> = metadata.fields()
+        .iter()
+        .filter_map(|field| metadata.get(doc, field).map(|value| (field.clone(), json!(value))))
+        .collect();
+
+    let mut result = json!({
+        "doc": doc,
+        "title": metadata.title(doc),
+        "metadata": fields,
+    });
+    if let Some(score) = score {
+        result["score"] = json!(score);
+    }
+
+    result
+}
+
+/// Parse the value of an option, if it was given.
+fn parse_option<T>(matches: &Matches, name: &str) -> Result<Option<T>, FrontendError>
+    where T: FromStr,
+          T::Err: fmt::Display
+{
+    match matches.opt_str(name) {
+        Some(value) => {
+            let value = value.parse()
+                .map_err(|err: T::Err| {
+                    FrontendError::InvalidOption(name.to_owned(), err.to_string())
+                })?;
+            Ok(Some(value))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use getopts::Options;
+
+    use super::{FrontendError, add_index_options, add_ranking_options, index_term_options,
+                intersect_strategy, open_index, result_json};
+    use super::super::{IntersectStrategy, MemoryMetadata};
+
+    #[test]
+    fn frontend_options() {
+        let mut opts = Options::new();
+        add_index_options(&mut opts);
+        add_ranking_options(&mut opts);
+
+        let matches = opts.parse(&["-F", "form,lemma", "-N", "lowercase", "-I", "galloping"])
+            .unwrap();
+        let (fields, pipeline) = index_term_options(&matches).unwrap();
+        assert_eq!(fields.index_term("Run", &pipeline), Some("form:run".to_owned()));
+        assert_eq!(intersect_strategy(&matches).unwrap(), Some(IntersectStrategy::Galloping));
+
+        let matches = opts.parse(&["-I", "quadratic"]).unwrap();
+        assert!(intersect_strategy(&matches).is_err());
+
+        let matches = opts.parse(&["-c", "vbyte", "-f"]).unwrap();
+        assert!(open_index("index.txt", &matches).is_err());
+        for args in &[["-b", "-p"], ["-m", "-f"], ["-s", "-b"]] {
+            let matches = opts.parse(args).unwrap();
+            assert!(matches!(open_index("index.txt", &matches),
+                             Err(FrontendError::Unsupported(_))));
+        }
+
+        let mut metadata = MemoryMetadata::new();
+        metadata.set(1, "title", "New York");
+        metadata.set(1, "year", "2016");
+        assert_eq!(result_json(&metadata, 1, Some(0.5)),
+                   json!({
+                       "doc": 1,
+                       "title": "New York",
+                       "metadata": {"title": "New York", "year": "2016"},
+                       "score": 0.5,
+                   }));
+        assert_eq!(result_json(&metadata, 2, None),
+                   json!({"doc": 2, "title": null, "metadata": {}}));
+    }
+}
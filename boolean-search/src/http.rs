@@ -0,0 +1,168 @@
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+
+/// Errors for reading HTTP requests.
+#[derive(Debug)]
+pub enum HttpError {
+    Io(io::Error),
+    Malformed(String),
+}
+
+impl From<io::Error> for HttpError {
+    fn from(err: io::Error) -> HttpError {
+        HttpError::Io(err)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpError::Io(ref err) => write!(f, "{}", err),
+            HttpError::Malformed(ref request) => write!(f, "Malformed request: {}", request),
+        }
+    }
+}
+
+/// A minimal HTTP/1.x request, consisting of the method, the path, and the
+/// parameters of the query string. Headers and bodies are not used.
+#[derive(Debug)]
+pub struct Request {
+    method: String,
+    path: String,
+    params: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Read a request from a buffered reader. The headers are consumed,
+    /// a request body is not.
+    pub fn from_buf_read<R>(reader: &mut R) -> Result<Self, HttpError>
+        where R: BufRead
+    {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        let parts: Vec<_> = line.split_whitespace().collect();
+        if parts.len() != 3 || !parts[2].starts_with("HTTP/") {
+            return Err(HttpError::Malformed(line.trim().to_owned()));
+        }
+
+        // Skip the headers, which end with an empty line.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? != 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+
+        let (path, query) = parts[1].split_once('?').unwrap_or((parts[1], ""));
+        let mut params = Vec::new();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            params.push((percent_decode(key)?, percent_decode(value)?));
+        }
+
+        Ok(Request {
+            method: parts[0].to_owned(),
+            path: percent_decode(path)?,
+            params,
+        })
+    }
+
+    /// Get the request method, such as `GET`.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Get the decoded path, without the query string.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the first value of a query string parameter.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Get all values of a query string parameter, in order.
+    pub fn params(&self, name: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+/// Write a response with a JSON body. The connection is closed after
+/// the response.
+pub fn write_json_response<W>(writer: &mut W, status: u16, body: &str) -> io::Result<()>
+    where W: Write
+{
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+
+    write!(writer,
+           "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+            Connection: close\r\n\r\n{}",
+           status,
+           reason,
+           body.len(),
+           body)?;
+    writer.flush()
+}
+
+/// Decode a percent-encoded URL component, where `+` encodes a space.
+fn percent_decode(s: &str) -> Result<String, HttpError> {
+    let malformed = || HttpError::Malformed(format!("invalid percent-encoding: {}", s));
+
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<_> = iter.by_ref().take(2).collect();
+                let hex = String::from_utf8(hex).map_err(|_| malformed())?;
+                if hex.len() != 2 {
+                    return Err(malformed());
+                }
+                bytes.push(u8::from_str_radix(&hex, 16).map_err(|_| malformed())?);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| malformed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{Request, write_json_response};
+
+    #[test]
+    fn http_request() {
+        let mut reader = Cursor::new("GET /search?q=new+york%20%26%20city&where=year%3E2000\
+                                      &where=lang=en HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let request = Request::from_buf_read(&mut reader).unwrap();
+        assert_eq!(request.method(), "GET");
+        assert_eq!(request.path(), "/search");
+        assert_eq!(request.param("q"), Some("new york & city"));
+        assert_eq!(request.params("where"), vec!["year>2000", "lang=en"]);
+        assert_eq!(request.param("limit"), None);
+
+        assert!(Request::from_buf_read(&mut Cursor::new("GET /search\r\n\r\n")).is_err());
+        assert!(Request::from_buf_read(&mut Cursor::new("GET /?q=%e HTTP/1.1\r\n\r\n")).is_err());
+
+        let mut response = Vec::new();
+        write_json_response(&mut response, 404, "{}").unwrap();
+        assert_eq!(String::from_utf8(response).unwrap(),
+                   "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\n\
+                    Content-Length: 2\r\nConnection: close\r\n\r\n{}");
+    }
+}
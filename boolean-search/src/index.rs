@@ -1,10 +1,20 @@
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::btree_map;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io;
 use std::io::{BufRead, Write};
+use std::ops::Bound;
 use std::slice;
+use std::str::FromStr;
 use std::vec;
 
+use binary_heap::BinaryHeap;
+
+use super::{FrequencyIndex, PositionalIndex};
+use super::wildcard::wildcard_match;
+
 /// An InvertedIndexMut is an inverted index that can be mutated.
 pub trait InvertedIndexMut<N: Ord> {
     /// Add a term-docid pair to the inverted index.
@@ -18,13 +28,90 @@ pub trait InvertedIndexMut<N: Ord> {
 /// An inverted index stores a term <-> postings list mapping.
 pub trait InvertedIndex<N: Clone + Ord> {
     /// Iterate over all term, postings list pairs in the inverted index.
-    fn iter<'a>(&'a self) -> Box<Iterator<Item = (&str, Posting<N>)> + 'a>;
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a>;
+
+    /// Iterate over the term, postings list pairs of the terms within a
+    /// range, in lexicographic order.
+    ///
+    /// The default implementation filters and sorts all terms, indexes
+    /// with a sorted term dictionary should override this method.
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        let mut terms: Vec<_> = self.iter()
+            .filter(|&(term, _)| term_in_range(term, from, to))
+            .collect();
+        terms.sort_by(|a, b| a.0.cmp(b.0));
+        Box::new(terms.into_iter())
+    }
+
+    /// Iterate over the term, postings list pairs of the terms that start
+    /// with `prefix`, in lexicographic order.
+    fn prefix<'a>(&'a self,
+                  prefix: &str)
+                  -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        let prefix = prefix.to_owned();
+        Box::new(self.range(Bound::Included(&prefix), Bound::Unbounded)
+            .take_while(move |&(term, _)| term.starts_with(prefix.as_str())))
+    }
+
+    /// Get the terms that match a wildcard pattern, where `*` matches any
+    /// sequence of characters. The terms are returned in lexicographic
+    /// order.
+    ///
+    /// The default implementation filters the terms that start with the
+    /// literal prefix of the pattern.
+    fn expand_wildcard(&self, pattern: &str) -> Vec<String> {
+        let prefix = pattern.split('*').next().unwrap_or("");
+        self.prefix(prefix)
+            .map(|(term, _)| term)
+            .filter(|term| wildcard_match(pattern, term))
+            .map(ToOwned::to_owned)
+            .collect()
+    }
 
     /// Get the number of terms in the index.
     fn len(&self) -> usize;
 
+    /// Returns `true` if the index does not contain any terms.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Retrieve the postings list for a term.
-    fn posting(&self, term: &str) -> Option<Posting<N>>;
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>>;
+
+    /// Get the document frequency of a term, which is 0 for terms that
+    /// are not in the index.
+    ///
+    /// The default implementation retrieves the postings list. Indexes
+    /// that decode or merge postings lists should override this method.
+    fn doc_freq(&self, term: &str) -> usize {
+        self.posting(term).map(|posting| posting.len()).unwrap_or(0)
+    }
+
+    /// Intersect the postings lists of terms. Returns `None` if the index
+    /// cannot do better than retrieving and intersecting the postings
+    /// lists, which is the default.
+    ///
+    /// Indexes with compressed postings lists should override this
+    /// method, so that parts of the larger lists are not decoded.
+    fn intersect_terms(&self, _terms: &[&str]) -> Option<Posting<'_, N>> {
+        None
+    }
+
+    /// Get the index as a positional index. Returns `None` if the index
+    /// does not store term positions.
+    fn positional(&self) -> Option<&dyn PositionalIndex<N>> {
+        None
+    }
+
+    /// Get the index as an index with term frequencies. Returns `None` if
+    /// the index does not store term frequencies.
+    fn frequencies(&self) -> Option<&dyn FrequencyIndex<N>> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -43,13 +130,13 @@ impl From<io::Error> for TextReadError {
 
 impl fmt::Display for TextReadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &TextReadError::Io(ref err) => write!(f, "{}", err),
-            &TextReadError::NoTerm => write!(f, "No term found"),
-            &TextReadError::NotSortedOrUnique(ref line) => {
+        match *self {
+            TextReadError::Io(ref err) => write!(f, "{}", err),
+            TextReadError::NoTerm => write!(f, "No term found"),
+            TextReadError::NotSortedOrUnique(ref line) => {
                 write!(f, "Postings list not sorted or unique: {}", line)
             }
-            &TextReadError::Parse => write!(f, "Could not parse document identifier"),
+            TextReadError::Parse => write!(f, "Could not parse document identifier"),
         }
     }
 }
@@ -70,6 +157,79 @@ pub trait InvertedIndexToText<N> {
     fn to_text<W>(&self, writer: &mut W) -> io::Result<()> where W: Write;
 }
 
+/// Strategies for intersecting postings lists.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IntersectStrategy {
+    /// Choose between `Linear` and `BinarySearch`, based on the lengths
+    /// of the postings lists.
+    #[default]
+    Adaptive,
+
+    /// Merge the postings lists.
+    Linear,
+
+    /// Look up every document of the smaller list in the larger list
+    /// using binary search.
+    BinarySearch,
+
+    /// Look up every document of the smaller list in the larger list
+    /// using exponential search, starting at the previous match.
+    Galloping,
+
+    /// Merge the postings lists, following skip pointers when possible.
+    /// The argument is the number of documents between skip pointers,
+    /// `None` places skip pointers every *sqrt(n)* documents.
+    SkipPointers(Option<usize>),
+}
+
+impl IntersectStrategy {
+    /// Get the strategy that is used to intersect postings lists of the
+    /// given lengths. `Adaptive` resolves to `Linear` or `BinarySearch`,
+    /// other strategies are returned unchanged.
+    pub fn resolve(self, len1: usize, len2: usize) -> IntersectStrategy {
+        match self {
+            IntersectStrategy::Adaptive if prefer_binsearch(len1.min(len2), len1.max(len2)) => {
+                IntersectStrategy::BinarySearch
+            }
+            IntersectStrategy::Adaptive => IntersectStrategy::Linear,
+            strategy => strategy,
+        }
+    }
+}
+
+impl FromStr for IntersectStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adaptive" => Ok(IntersectStrategy::Adaptive),
+            "linear" => Ok(IntersectStrategy::Linear),
+            "binsearch" => Ok(IntersectStrategy::BinarySearch),
+            "galloping" => Ok(IntersectStrategy::Galloping),
+            "skip" => Ok(IntersectStrategy::SkipPointers(None)),
+            _ => {
+                match s.strip_prefix("skip=").map(str::parse) {
+                    Some(Ok(interval)) => Ok(IntersectStrategy::SkipPointers(Some(interval))),
+                    _ => Err(format!("Unknown intersection strategy: {}", s)),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for IntersectStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IntersectStrategy::Adaptive => write!(f, "adaptive"),
+            IntersectStrategy::Linear => write!(f, "linear"),
+            IntersectStrategy::BinarySearch => write!(f, "binsearch"),
+            IntersectStrategy::Galloping => write!(f, "galloping"),
+            IntersectStrategy::SkipPointers(None) => write!(f, "skip"),
+            IntersectStrategy::SkipPointers(Some(interval)) => write!(f, "skip={}", interval),
+        }
+    }
+}
+
 /// A Posting is a sorted list of unique document identifiers.
 #[derive(Clone, Debug)]
 pub struct Posting<'a, N>
@@ -88,15 +248,28 @@ impl<'a, N> Posting<'a, N>
     pub fn intersect(&self, other: &Posting<N>) -> Posting<'static, N> {
         let (smaller, larger) = min_max_posting(self, other);
 
-        let larger_f = larger.len() as f64;
-
-        if (smaller.len() as f64) < (larger_f / larger_f.log(2.)) {
+        if prefer_binsearch(smaller.len(), larger.len()) {
             self.intersect_binsearch(other)
         } else {
             self.intersect_linear(other)
         }
     }
 
+    /// Intersect the postings list with another postings list, using the
+    /// given strategy. All strategies give the same result.
+    pub fn intersect_with(&self,
+                          other: &Posting<N>,
+                          strategy: IntersectStrategy)
+                          -> Posting<'static, N> {
+        match strategy {
+            IntersectStrategy::Adaptive => self.intersect(other),
+            IntersectStrategy::Linear => self.intersect_linear(other),
+            IntersectStrategy::BinarySearch => self.intersect_binsearch(other),
+            IntersectStrategy::Galloping => self.intersect_galloping(other),
+            IntersectStrategy::SkipPointers(interval) => self.intersect_skip(other, interval),
+        }
+    }
+
     fn intersect_binsearch(&self, other: &Posting<N>) -> Posting<'static, N> {
         let mut inter = Vec::new();
 
@@ -104,12 +277,12 @@ impl<'a, N> Posting<'a, N>
 
         let mut offset = 0;
         for doc in smaller.docs.as_ref() {
-            offset = match larger.docs[offset..].binary_search(doc) {
+            match larger.docs[offset..].binary_search(doc) {
                 Ok(idx) => {
                     inter.push(doc.clone());
-                    idx
+                    offset += idx + 1;
                 }
-                Err(idx) => idx,
+                Err(idx) => offset += idx,
             }
         }
 
@@ -140,14 +313,272 @@ impl<'a, N> Posting<'a, N>
         posting_from_vec(inter)
     }
 
+    fn intersect_galloping(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut inter = Vec::new();
+
+        let (smaller, larger) = min_max_posting(self, other);
+        let larger = larger.docs.as_ref();
+
+        let mut offset = 0;
+        for doc in smaller.docs.as_ref() {
+            match gallop(larger, offset, doc) {
+                Ok(idx) => {
+                    inter.push(doc.clone());
+                    offset = idx + 1;
+                }
+                Err(idx) if idx == larger.len() => break,
+                Err(idx) => offset = idx,
+            }
+        }
+
+        posting_from_vec(inter)
+    }
+
+    fn intersect_skip(&self, other: &Posting<N>, interval: Option<usize>) -> Posting<'static, N> {
+        let mut inter = Vec::new();
+
+        let docs1 = self.docs.as_ref();
+        let docs2 = other.docs.as_ref();
+
+        let skip1 = interval.unwrap_or_else(|| default_skip_interval(docs1.len())).max(1);
+        let skip2 = interval.unwrap_or_else(|| default_skip_interval(docs2.len())).max(1);
+
+        let mut p1i = 0;
+        let mut p2i = 0;
+
+        while p1i != docs1.len() && p2i != docs2.len() {
+            let doc1 = &docs1[p1i];
+            let doc2 = &docs2[p2i];
+
+            if doc1 == doc2 {
+                inter.push(doc1.clone());
+                p1i += 1;
+                p2i += 1;
+            } else if doc1 < doc2 {
+                p1i = follow_skip(docs1, p1i, skip1, doc2);
+            } else {
+                p2i = follow_skip(docs2, p2i, skip2, doc1);
+            }
+        }
+
+        posting_from_vec(inter)
+    }
+
+    /// Compute the union of the postings list with another postings list.
+    /// The union is in O(n log m + m) iff n < m / log m and O(n + m)
+    /// otherwise. The first variant saves comparisons, since spans of the
+    /// larger list are copied without comparing their elements.
+    pub fn union(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let (smaller, larger) = min_max_posting(self, other);
+
+        if prefer_binsearch(smaller.len(), larger.len()) {
+            self.union_binsearch(other)
+        } else {
+            self.union_linear(other)
+        }
+    }
+
+    fn union_binsearch(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut result = Vec::with_capacity(self.docs.len() + other.docs.len());
+
+        let (smaller, larger) = min_max_posting(self, other);
+
+        let mut offset = 0;
+        for doc in smaller.docs.as_ref() {
+            match larger.docs[offset..].binary_search(doc) {
+                Ok(idx) => {
+                    result.extend_from_slice(&larger.docs[offset..offset + idx + 1]);
+                    offset += idx + 1;
+                }
+                Err(idx) => {
+                    result.extend_from_slice(&larger.docs[offset..offset + idx]);
+                    result.push(doc.clone());
+                    offset += idx;
+                }
+            }
+        }
+
+        result.extend_from_slice(&larger.docs[offset..]);
+
+        posting_from_vec(result)
+    }
+
+    fn union_linear(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut result = Vec::with_capacity(self.docs.len() + other.docs.len());
+
+        let mut p1i = 0;
+        let mut p2i = 0;
+
+        while p1i != self.docs.len() && p2i != other.docs.len() {
+            let doc1 = &self.docs.as_ref()[p1i];
+            let doc2 = &other.docs.as_ref()[p2i];
+
+            if doc1 == doc2 {
+                result.push(doc1.clone());
+                p1i += 1;
+                p2i += 1;
+            } else if doc1 < doc2 {
+                result.push(doc1.clone());
+                p1i += 1;
+            } else {
+                result.push(doc2.clone());
+                p2i += 1;
+            }
+        }
+
+        result.extend_from_slice(&self.docs[p1i..]);
+        result.extend_from_slice(&other.docs[p2i..]);
+
+        posting_from_vec(result)
+    }
+
+    /// Compute the difference of the postings list and another postings
+    /// list, i.e. the documents that occur in this list, but not in
+    /// `other`. If n is the length of this list and m the length of
+    /// `other`, the difference is in O(n log m) iff n < m / log m and
+    /// O(n + m) otherwise.
+    pub fn difference(&self, other: &Posting<N>) -> Posting<'static, N> {
+        if prefer_binsearch(self.len(), other.len()) {
+            self.difference_binsearch(other)
+        } else {
+            self.difference_linear(other)
+        }
+    }
+
+    fn difference_binsearch(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut diff = Vec::new();
+
+        let mut offset = 0;
+        for doc in self.docs.as_ref() {
+            match other.docs[offset..].binary_search(doc) {
+                Ok(idx) => offset += idx + 1,
+                Err(idx) => {
+                    diff.push(doc.clone());
+                    offset += idx;
+                }
+            }
+        }
+
+        posting_from_vec(diff)
+    }
+
+    fn difference_linear(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut diff = Vec::new();
+
+        let mut p1i = 0;
+        let mut p2i = 0;
+
+        while p1i != self.docs.len() && p2i != other.docs.len() {
+            let doc1 = &self.docs.as_ref()[p1i];
+            let doc2 = &other.docs.as_ref()[p2i];
+
+            if doc1 == doc2 {
+                p1i += 1;
+                p2i += 1;
+            } else if doc1 < doc2 {
+                diff.push(doc1.clone());
+                p1i += 1;
+            } else {
+                p2i += 1;
+            }
+        }
+
+        diff.extend_from_slice(&self.docs[p1i..]);
+
+        posting_from_vec(diff)
+    }
+
+    /// Compute the symmetric difference of the postings list and another
+    /// postings list, i.e. the documents that occur in exactly one of the
+    /// lists. The algorithm is selected as in `union`.
+    pub fn symmetric_difference(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let (smaller, larger) = min_max_posting(self, other);
+
+        if prefer_binsearch(smaller.len(), larger.len()) {
+            self.symmetric_difference_binsearch(other)
+        } else {
+            self.symmetric_difference_linear(other)
+        }
+    }
+
+    fn symmetric_difference_binsearch(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut result = Vec::with_capacity(self.docs.len() + other.docs.len());
+
+        let (smaller, larger) = min_max_posting(self, other);
+
+        let mut offset = 0;
+        for doc in smaller.docs.as_ref() {
+            match larger.docs[offset..].binary_search(doc) {
+                Ok(idx) => {
+                    result.extend_from_slice(&larger.docs[offset..offset + idx]);
+                    offset += idx + 1;
+                }
+                Err(idx) => {
+                    result.extend_from_slice(&larger.docs[offset..offset + idx]);
+                    result.push(doc.clone());
+                    offset += idx;
+                }
+            }
+        }
+
+        result.extend_from_slice(&larger.docs[offset..]);
+
+        posting_from_vec(result)
+    }
+
+    fn symmetric_difference_linear(&self, other: &Posting<N>) -> Posting<'static, N> {
+        let mut result = Vec::with_capacity(self.docs.len() + other.docs.len());
+
+        let mut p1i = 0;
+        let mut p2i = 0;
+
+        while p1i != self.docs.len() && p2i != other.docs.len() {
+            let doc1 = &self.docs.as_ref()[p1i];
+            let doc2 = &other.docs.as_ref()[p2i];
+
+            if doc1 == doc2 {
+                p1i += 1;
+                p2i += 1;
+            } else if doc1 < doc2 {
+                result.push(doc1.clone());
+                p1i += 1;
+            } else {
+                result.push(doc2.clone());
+                p2i += 1;
+            }
+        }
+
+        result.extend_from_slice(&self.docs[p1i..]);
+        result.extend_from_slice(&other.docs[p2i..]);
+
+        posting_from_vec(result)
+    }
+
+    /// Compute the complement of the postings list with respect to a
+    /// universe, i.e. the documents of `universe` that are not in this
+    /// postings list.
+    pub fn complement(&self, universe: &Posting<N>) -> Posting<'static, N> {
+        universe.difference(self)
+    }
+
+    /// Get the document IDs in the posting list as a slice.
+    pub fn as_slice(&self) -> &[N] {
+        self.docs.as_ref()
+    }
+
     /// Get an iterator over the document IDs in the posting list.
-    pub fn iter(&self) -> slice::Iter<N> {
-        return self.docs.iter();
+    pub fn iter(&self) -> slice::Iter<'_, N> {
+        self.docs.iter()
+    }
+
+    /// Returns `true` if the postings list does not contain any documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
     }
 
     /// Get the size of the postings list.
     pub fn len(&self) -> usize {
-        return self.docs.len();
+        self.docs.len()
     }
 }
 
@@ -171,7 +602,111 @@ impl<'a, N> IntoIterator for &'a Posting<'a, N>
     type IntoIter = slice::Iter<'a, N>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.docs.into_iter()
+        self.docs.iter()
+    }
+}
+
+/// Intersect any number of postings lists. The documents of the
+/// smallest list are looked up in the other lists using exponential
+/// search, so that no intermediate results are constructed. The
+/// intersection of zero postings lists is empty.
+pub fn intersect_all<N>(postings: &[Posting<N>]) -> Posting<'static, N>
+    where N: Clone + Ord
+{
+    let mut sorted: Vec<_> = postings.iter().map(Posting::as_slice).collect();
+    sorted.sort_by_key(|docs| docs.len());
+
+    let (smallest, rest) = match sorted.split_first() {
+        Some(split) => split,
+        None => return posting_from_vec(Vec::new()),
+    };
+
+    let mut inter = Vec::new();
+    let mut offsets = vec![0; rest.len()];
+
+    'candidates: for doc in smallest.iter() {
+        for (docs, offset) in rest.iter().zip(offsets.iter_mut()) {
+            match gallop(docs, *offset, doc) {
+                Ok(idx) => *offset = idx + 1,
+                Err(idx) if idx == docs.len() => break 'candidates,
+                Err(idx) => {
+                    *offset = idx;
+                    continue 'candidates;
+                }
+            }
+        }
+
+        inter.push(doc.clone());
+    }
+
+    posting_from_vec(inter)
+}
+
+/// Compute the union of any number of postings lists, using a k-way merge.
+/// A heap holds the next document of every postings list, so the union is
+/// in O(n log k), where n is the total length of the k lists.
+pub fn union_all<N>(postings: &[Posting<N>]) -> Posting<'static, N>
+    where N: Clone + Ord
+{
+    let mut result = Vec::with_capacity(postings.iter().map(Posting::len).max().unwrap_or(0));
+
+    // Heap of (next document, postings list, index in postings list). The
+    // ordering is reversed, so that the smallest document is on top.
+    let mut heap = BinaryHeap::new();
+    for (list, posting) in postings.iter().enumerate() {
+        if let Some(doc) = posting.docs.first() {
+            heap.insert(Reverse((doc, list, 0)));
+        }
+    }
+
+    loop {
+        let exhausted = match heap.peek_mut() {
+            Some(mut top) => {
+                let Reverse((doc, list, idx)) = *top;
+
+                if result.last() != Some(doc) {
+                    result.push(doc.clone());
+                }
+
+                match postings[list].docs.get(idx + 1) {
+                    Some(next) => {
+                        *top = Reverse((next, list, idx + 1));
+                        false
+                    }
+                    None => true,
+                }
+            }
+            None => break,
+        };
+
+        if exhausted {
+            heap.pop();
+        }
+    }
+
+    posting_from_vec(result)
+}
+
+/// Search `doc` in `docs[offset..]` using exponential search. Like
+/// `binary_search`, returns `Ok` with the index of the document if it was
+/// found and `Err` with the insertion index otherwise. Indices are
+/// relative to the start of `docs`.
+fn gallop<N>(docs: &[N], offset: usize, doc: &N) -> Result<usize, usize>
+    where N: Ord
+{
+    // Find a bound such that the document is in
+    // docs[offset + bound / 2..offset + bound + 1].
+    let mut bound = 1;
+    while offset + bound < docs.len() && docs[offset + bound] < *doc {
+        bound *= 2;
+    }
+
+    let start = (offset + bound / 2).min(docs.len());
+    let end = (offset + bound + 1).min(docs.len());
+
+    match docs[start..end].binary_search(doc) {
+        Ok(idx) => Ok(start + idx),
+        Err(idx) => Err(start + idx),
     }
 }
 
@@ -187,6 +722,78 @@ fn min_max_posting<'a, N>(a: &'a Posting<'a, N>,
     }
 }
 
+/// Returns `true` when an operation on postings lists of lengths n and m,
+/// where n <= m, should look up the elements of the smaller list in the
+/// larger list using binary search, rather than merging the lists. This is
+/// the case when n < m / log m.
+pub fn prefer_binsearch(n: usize, m: usize) -> bool {
+    let m_f = m as f64;
+    (n as f64) < (m_f / m_f.log(2.))
+}
+
+/// Returns `true` if a term is within the given range.
+fn term_in_range(term: &str, from: Bound<&str>, to: Bound<&str>) -> bool {
+    let after_start = match from {
+        Bound::Included(from) => term >= from,
+        Bound::Excluded(from) => term > from,
+        Bound::Unbounded => true,
+    };
+
+    let before_end = match to {
+        Bound::Included(to) => term <= to,
+        Bound::Excluded(to) => term < to,
+        Bound::Unbounded => true,
+    };
+
+    after_start && before_end
+}
+
+/// Get the entries of a sorted term dictionary within a range of terms.
+/// Unlike `BTreeMap::range`, this function does not panic on empty ranges.
+pub fn dictionary_range<'a, V>(terms: &'a BTreeMap<String, V>,
+                               from: Bound<&str>,
+                               to: Bound<&str>)
+                               -> btree_map::Range<'a, String, V> {
+    let empty = match (from, to) {
+        (Bound::Included(from), Bound::Included(to)) => from > to,
+        (Bound::Included(from), Bound::Excluded(to)) |
+        (Bound::Excluded(from), Bound::Included(to)) |
+        (Bound::Excluded(from), Bound::Excluded(to)) => from >= to,
+        _ => false,
+    };
+
+    if empty {
+        terms.range::<str, _>((Bound::Excluded(""), Bound::Included("")))
+    } else {
+        terms.range::<str, _>((from, to))
+    }
+}
+
+/// Get the default number of documents between skip pointers for a
+/// postings list of length n: *sqrt(n)*.
+pub fn default_skip_interval(len: usize) -> usize {
+    ((len as f64).sqrt() as usize).max(1)
+}
+
+/// Advance from `idx` in a postings list with (implicit) skip pointers at
+/// every multiple of `interval`. The skip pointer is followed if its
+/// target does not exceed `doc`, otherwise the index is incremented.
+fn follow_skip<N>(docs: &[N], idx: usize, interval: usize, doc: &N) -> usize
+    where N: Ord
+{
+    if idx.is_multiple_of(interval) && idx + interval < docs.len() && docs[idx + interval] <= *doc {
+        idx + interval
+    } else {
+        idx + 1
+    }
+}
+
+pub fn posting_from_cow<'a, N>(docs: Cow<'a, [N]>) -> Posting<'a, N>
+    where N: Clone + Ord
+{
+    Posting { docs }
+}
+
 pub fn posting_from_ref<'a, N>(s: &'a [N]) -> Posting<'a, N>
     where N: Clone + Ord
 {
@@ -198,3 +805,145 @@ pub fn posting_from_vec<N>(v: Vec<N>) -> Posting<'static, N>
 {
     Posting { docs: Cow::Owned(v) }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::ops::Bound;
+
+    use super::super::{InvertedIndex, InvertedIndexMut, MemoryIndex};
+    use super::{IntersectStrategy, Posting, intersect_all, posting_from_vec, union_all};
+
+    fn set_posting(set: &BTreeSet<u32>) -> Posting<'static, u32> {
+        posting_from_vec(set.iter().cloned().collect())
+    }
+
+    fn set_vec<'a, I>(iter: I) -> Vec<u32>
+        where I: Iterator<Item = &'a u32>
+    {
+        iter.cloned().collect()
+    }
+
+    quickcheck! {
+        fn prop_intersect(xs: BTreeSet<u32>, ys: BTreeSet<u32>) -> bool {
+            let (p1, p2) = (set_posting(&xs), set_posting(&ys));
+            let check = set_vec(xs.intersection(&ys));
+
+            p1.intersect(&p2).docs.as_ref() == check.as_slice() &&
+            p1.intersect_linear(&p2).docs.as_ref() == check.as_slice() &&
+            p1.intersect_binsearch(&p2).docs.as_ref() == check.as_slice()
+        }
+    }
+
+    quickcheck! {
+        fn prop_intersect_strategies(xs: BTreeSet<u32>, ys: BTreeSet<u32>, interval: usize)
+                                     -> bool {
+            let (p1, p2) = (set_posting(&xs), set_posting(&ys));
+            let check = set_vec(xs.intersection(&ys));
+
+            let strategies = [IntersectStrategy::Adaptive,
+                              IntersectStrategy::Linear,
+                              IntersectStrategy::BinarySearch,
+                              IntersectStrategy::Galloping,
+                              IntersectStrategy::SkipPointers(None),
+                              IntersectStrategy::SkipPointers(Some(interval % 16))];
+
+            strategies.iter().all(|&strategy| {
+                p1.intersect_with(&p2, strategy).docs.as_ref() == check.as_slice() &&
+                p2.intersect_with(&p1, strategy).docs.as_ref() == check.as_slice()
+            })
+        }
+    }
+
+    quickcheck! {
+        fn prop_union(xs: BTreeSet<u32>, ys: BTreeSet<u32>) -> bool {
+            let (p1, p2) = (set_posting(&xs), set_posting(&ys));
+            let check = set_vec(xs.union(&ys));
+
+            p1.union(&p2).docs.as_ref() == check.as_slice() &&
+            p1.union_linear(&p2).docs.as_ref() == check.as_slice() &&
+            p1.union_binsearch(&p2).docs.as_ref() == check.as_slice()
+        }
+    }
+
+    quickcheck! {
+        fn prop_difference(xs: BTreeSet<u32>, ys: BTreeSet<u32>) -> bool {
+            let (p1, p2) = (set_posting(&xs), set_posting(&ys));
+            let check = set_vec(xs.difference(&ys));
+
+            p1.difference(&p2).docs.as_ref() == check.as_slice() &&
+            p1.difference_linear(&p2).docs.as_ref() == check.as_slice() &&
+            p1.difference_binsearch(&p2).docs.as_ref() == check.as_slice()
+        }
+    }
+
+    quickcheck! {
+        fn prop_symmetric_difference(xs: BTreeSet<u32>, ys: BTreeSet<u32>) -> bool {
+            let (p1, p2) = (set_posting(&xs), set_posting(&ys));
+            let check = set_vec(xs.symmetric_difference(&ys));
+
+            p1.symmetric_difference(&p2).docs.as_ref() == check.as_slice() &&
+            p1.symmetric_difference_linear(&p2).docs.as_ref() == check.as_slice() &&
+            p1.symmetric_difference_binsearch(&p2).docs.as_ref() == check.as_slice()
+        }
+    }
+
+    quickcheck! {
+        fn prop_complement(xs: BTreeSet<u32>, ys: BTreeSet<u32>) -> bool {
+            // The posting should be a subset of the universe.
+            let universe: BTreeSet<u32> = xs.union(&ys).cloned().collect();
+            let (p, u) = (set_posting(&xs), set_posting(&universe));
+            let check = set_vec(universe.difference(&xs));
+
+            p.complement(&u).docs.as_ref() == check.as_slice()
+        }
+    }
+
+    quickcheck! {
+        fn prop_intersect_all(sets: Vec<BTreeSet<u32>>) -> bool {
+            let postings: Vec<_> = sets.iter().map(set_posting).collect();
+            let check = match sets.split_first() {
+                Some((first, rest)) => {
+                    let mut check = first.clone();
+                    for set in rest {
+                        check = check.intersection(set).cloned().collect();
+                    }
+                    check.into_iter().collect()
+                }
+                None => Vec::new(),
+            };
+
+            intersect_all(&postings).docs.as_ref() == check.as_slice()
+        }
+    }
+
+    quickcheck! {
+        fn prop_union_all(sets: Vec<BTreeSet<u32>>) -> bool {
+            let postings: Vec<_> = sets.iter().map(set_posting).collect();
+            let check: BTreeSet<u32> = sets.iter().flat_map(|set| set.iter().cloned()).collect();
+
+            union_all(&postings).docs.as_ref() == set_vec(check.iter()).as_slice()
+        }
+    }
+
+    #[test]
+    fn sorted_range_and_prefix() {
+        let mut index = MemoryIndex::new();
+        for &term in &["york", "new", "newark", "news", "boston", "ne"] {
+            index.add_term(term, 1u32);
+        }
+
+        let terms: Vec<_> = index.iter().map(|(term, _)| term).collect();
+        assert_eq!(terms, vec!["boston", "ne", "new", "newark", "news", "york"]);
+
+        let prefixed: Vec<_> = index.prefix("new").map(|(term, _)| term).collect();
+        assert_eq!(prefixed, vec!["new", "newark", "news"]);
+
+        let ranged: Vec<_> = index.range(Bound::Excluded("ne"), Bound::Included("newark"))
+            .map(|(term, _)| term)
+            .collect();
+        assert_eq!(ranged, vec!["new", "newark"]);
+
+        assert_eq!(index.range(Bound::Included("z"), Bound::Excluded("a")).count(), 0);
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use conllx;
+use conllx::Sentence;
+
+use super::{DocIdExtractor, DocIdPolicy, DocLengths, Fields, IndexingError, IndexingReport,
+            InvertedIndexMut, MemoryIndex, Normalizer, Pipeline};
+
+/// The indexing reports of input files.
+type FileReports = Vec<(PathBuf, IndexingReport)>;
+
+/// Errors for indexing CoNLL-X files.
+#[derive(Debug)]
+pub enum IndexerError {
+    Conllx(conllx::Error),
+    Indexing(IndexingError),
+    Io(io::Error),
+}
+
+impl From<conllx::Error> for IndexerError {
+    fn from(err: conllx::Error) -> IndexerError {
+        IndexerError::Conllx(err)
+    }
+}
+
+impl From<IndexingError> for IndexerError {
+    fn from(err: IndexingError) -> IndexerError {
+        IndexerError::Indexing(err)
+    }
+}
+
+impl From<io::Error> for IndexerError {
+    fn from(err: io::Error) -> IndexerError {
+        IndexerError::Io(err)
+    }
+}
+
+impl fmt::Display for IndexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexerError::Conllx(ref err) => write!(f, "{}", err),
+            IndexerError::Indexing(ref err) => write!(f, "{}", err),
+            IndexerError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Add the terms of the indexed fields of sentences to an index, the terms
+/// are normalized using `normalizer`. Tokens without terms do not count
+/// towards the document length.
+pub fn add_sentences<I, S>(sentences: S,
+                           fields: &Fields,
+                           normalizer: &dyn Normalizer,
+                           index: &mut I,
+                           lengths: &mut DocLengths<u64>)
+    where I: InvertedIndexMut<u64>,
+          S: IntoIterator<Item = (u64, Sentence)>
+{
+    for (doc, sentence) in sentences {
+        for token in &sentence {
+            let terms = ok_or_continue!(fields.terms(token, normalizer));
+            for term in &terms {
+                index.add_term(term, doc);
+            }
+
+            if !terms.is_empty() {
+                lengths.add(doc, 1);
+            }
+        }
+    }
+}
+
+/// Index CoNLL-X files using a pool of `n_threads` worker threads. Every
+/// worker takes files from a shared queue and builds its own index, using
+/// its own worker of the pipeline. The indexes and document lengths of the
+/// workers are merged afterwards, so the result is the same as indexing
+/// the files one after another.
+///
+/// Every input file is processed with its own document identifier
+/// extractor, so the returned reports are per file, sorted by path.
+pub fn index_parallel(inputs: Vec<PathBuf>,
+                      fields: &Fields,
+                      pipeline: Arc<Pipeline>,
+                      doc_key: Option<String>,
+                      policy: DocIdPolicy,
+                      n_threads: usize,
+                      lengths: &mut DocLengths<u64>)
+                      -> Result<(MemoryIndex<u64>, FileReports), IndexerError> {
+    let queue = Arc::new(Mutex::new(inputs));
+
+    let workers: Vec<_> = (0..n_threads.max(1))
+        .map(|_| {
+            let queue = queue.clone();
+            let fields = fields.clone();
+            let pipeline = pipeline.clone();
+            let doc_key = doc_key.clone();
+            thread::spawn(move || {
+                let normalizer = pipeline.worker();
+                let mut index = MemoryIndex::new();
+                let mut lengths = DocLengths::new();
+                let mut reports = Vec::new();
+
+                loop {
+                    let path = ok_or_break!(queue.lock().unwrap().pop());
+                    let mut extractor = DocIdExtractor::new(doc_key.clone(), policy);
+                    let result = index_file(&path,
+                                            &fields,
+                                            &*normalizer,
+                                            &mut extractor,
+                                            &mut index,
+                                            &mut lengths);
+
+                    // Other workers do not need to start on new files
+                    // after an error.
+                    if let Err(err) = result {
+                        queue.lock().unwrap().clear();
+                        return Err(err);
+                    }
+
+                    reports.push((path, extractor.report().clone()));
+                }
+
+                Ok((index, lengths, reports))
+            })
+        })
+        .collect();
+
+    let mut index = MemoryIndex::new();
+    let mut reports = Vec::new();
+    let mut result = Ok(());
+    for worker in workers {
+        match worker.join().expect("Indexing thread panicked") {
+            Ok((worker_index, worker_lengths, worker_reports)) => {
+                index.merge(worker_index);
+                lengths.merge(worker_lengths);
+                reports.extend(worker_reports);
+            }
+            Err(err) => result = Err(err),
+        }
+    }
+    result?;
+
+    reports.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok((index, reports))
+}
+
+/// Add the sentences of a CoNLL-X file to an index. Sentences without a
+/// document identifier are skipped, unless the policy is to fail.
+fn index_file(path: &Path,
+              fields: &Fields,
+              normalizer: &dyn Normalizer,
+              extractor: &mut DocIdExtractor,
+              index: &mut MemoryIndex<u64>,
+              lengths: &mut DocLengths<u64>)
+              -> Result<(), IndexerError> {
+    let reader = conllx::Reader::new(BufReader::new(File::open(path)?));
+    for sentence in reader {
+        let sentence = sentence?;
+        if let Some(doc) = extractor.doc_id(&sentence)? {
+            add_sentences(Some((doc, sentence)), fields, normalizer, index, lengths);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use conllx;
+
+    use super::{add_sentences, index_parallel};
+    use super::super::{DocIdExtractor, DocIdPolicy, DocLengths, Fields, InvertedIndexToText,
+                       Lowercase, MemoryIndex, Pipeline};
+    use super::super::tempfile::TempDir;
+
+    static FILES: [&str; 3] = ["1\tNew\tnew\t_\t_\t1\t0\tROOT\t_\t_\n\
+                                2\tYork\tyork\t_\t_\t1\t0\tROOT\t_\t_\n\n\
+                                1\tis\tbe\t_\t_\t2\t0\tROOT\t_\t_\n",
+                               "1\tA\ta\t_\t_\t3\t0\tROOT\t_\t_\n\
+                                2\tcity\tcity\t_\t_\t3\t0\tROOT\t_\t_\n\n\
+                                1\tnew\tnew\t_\t_\t4\t0\tROOT\t_\t_\n",
+                               "1\tYork\tyork\t_\t_\t5\t0\tROOT\t_\t_\n\n\
+                                1\tskipped\tskipped\t_\t_\t_\t0\tROOT\t_\t_\n"];
+
+    fn to_text(index: &MemoryIndex<u64>, lengths: &DocLengths<u64>) -> (String, String) {
+        let (mut index_text, mut lengths_text) = (Vec::new(), Vec::new());
+        index.to_text(&mut index_text).unwrap();
+        lengths.to_text(&mut lengths_text).unwrap();
+        (String::from_utf8(index_text).unwrap(), String::from_utf8(lengths_text).unwrap())
+    }
+
+    #[test]
+    fn parallel_equals_sequential() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Lowercase);
+        let fields = Fields::default();
+
+        let mut index = MemoryIndex::new();
+        let mut lengths = DocLengths::new();
+        let mut extractor = DocIdExtractor::new(None, DocIdPolicy::Skip);
+        let sentences = conllx::Reader::new(Cursor::new(FILES.join("\n")))
+            .into_iter()
+            .map(Result::unwrap)
+            .filter_map(|sentence| {
+                extractor.doc_id(&sentence).unwrap().map(|doc| (doc, sentence))
+            });
+        add_sentences(sentences, &fields, &pipeline, &mut index, &mut lengths);
+
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let mut inputs = Vec::new();
+        for (idx, data) in FILES.iter().enumerate() {
+            let path = dir.path().join(format!("{}.conll", idx));
+            fs::write(&path, data).unwrap();
+            inputs.push(path);
+        }
+
+        let pipeline = Arc::new(pipeline);
+        for n_threads in 1..4 {
+            let mut parallel_lengths = DocLengths::new();
+            let (parallel_index, reports) = index_parallel(inputs.clone(),
+                                                           &fields,
+                                                           pipeline.clone(),
+                                                           None,
+                                                           DocIdPolicy::Skip,
+                                                           n_threads,
+                                                           &mut parallel_lengths)
+                .unwrap();
+
+            assert_eq!(to_text(&parallel_index, &parallel_lengths),
+                       to_text(&index, &lengths));
+            assert_eq!(reports.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+                       inputs.iter().collect::<Vec<_>>());
+            assert_eq!(reports[2].1.errors().len(), 1);
+        }
+
+        inputs.push(dir.path().join("missing.conll"));
+        assert!(index_parallel(inputs,
+                               &fields,
+                               pipeline,
+                               None,
+                               DocIdPolicy::Skip,
+                               2,
+                               &mut DocLengths::new())
+            .is_err());
+    }
+}
@@ -1,17 +1,113 @@
+extern crate binary_heap;
+extern crate byteorder;
+extern crate classify;
+extern crate conllx;
+extern crate getopts;
 extern crate itertools;
+extern crate memmap;
+extern crate num_traits;
+extern crate rust_stemmers;
+#[macro_use]
+extern crate serde_json;
+extern crate trie;
+extern crate unicode_normalization;
+
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 
 #[macro_use]
 mod macros;
 
-mod docid;
-pub use docid::DocIdentifiers;
+mod binary;
+pub use binary::{BinaryIndexWriter, BinaryReadError, InvertedIndexFromBinary,
+                 InvertedIndexToBinary};
+
+mod codec;
+pub use codec::{Codec, CompressedPosting, DecodeIter};
+
+mod collection;
+pub use collection::{CollectionError, DocIdAssigner, Document, DocumentFormat, JsonlReader,
+                     TextReader, TrecReader, tokenize};
+
+mod compressed;
+pub use compressed::CompressedMemoryIndex;
+
+mod doclen;
+pub use doclen::{DocLengthError, DocLengths};
+
+mod evaluation;
+pub use evaluation::{EvaluationError, Metric, Qrels, Run, evaluate, trec_doc_id};
+
+mod extract;
+pub use extract::{DocIdExtractor, DocIdPolicy, IndexingError, IndexingReport};
+
+mod field;
+pub use field::{Field, Fields};
+
+mod frequency;
+pub use frequency::{FrequencyIndex, FrequencyMemoryIndex, FrequencyPosting};
+
+mod frontend;
+pub use frontend::{FrontendError, add_index_options, add_ranking_options, index_term_options,
+                   intersect_strategy, open_index, read_lengths, result_json, scorer};
+
+mod http;
+pub use http::{HttpError, Request, write_json_response};
+
+mod indexer;
+pub use indexer::{IndexerError, add_sentences, index_parallel};
 
 mod index;
-pub use index::{InvertedIndex, InvertedIndexFromText, InvertedIndexToText, InvertedIndexMut,
-                Posting, TextReadError};
+pub use index::{IntersectStrategy, InvertedIndex, InvertedIndexFromText, InvertedIndexToText,
+                InvertedIndexMut, Posting, TextReadError, intersect_all, union_all};
 
 mod memory;
 pub use memory::MemoryIndex;
 
+mod metadata;
+pub use metadata::{Comparison, Filter, MemoryMetadata, Metadata, MetadataError, MmapMetadata,
+                   open_metadata, split_filters};
+
+mod mmap;
+pub use mmap::{MmapIndex, MmapIndexWriter, write_mmap_index};
+
+mod normalize;
+pub use normalize::{Lowercase, NormalizeError, Normalizer, Pipeline, Stem, Stopwords,
+                    TagStopwords, UnicodeNormalize};
+
+mod plan;
+pub use plan::{Explain, QueryPlan};
+
+mod positional;
+pub use positional::{PositionalIndex, PositionalIndexMut, PositionalMemoryIndex,
+                     PositionalPosting};
+
+mod query;
+pub use query::{EvalError, Query, QueryError};
+
+mod rank;
+pub use rank::{Bm25, Scorer, TfIdf, top_k};
+
+mod segment;
+pub use segment::{SegmentError, SegmentedIndex};
+
+mod snippet;
+pub use snippet::{DocumentStore, DocumentStoreError, Snippet};
+
+mod server;
+pub use server::{MAX_WILDCARD_TERMS, READ_TIMEOUT, Server};
+
+mod spelling;
+pub use spelling::{SpellingCorrector, Suggestion, levenshtein};
+
+mod spimi;
+pub use spimi::SpimiIndexer;
+
+mod tempfile;
+
 mod util;
 pub use util::{is_sorted_uniq, or_exit};
+
+mod wildcard;
+pub use wildcard::WildcardIndex;
@@ -1,18 +1,22 @@
-use std::collections::hash_map;
-use std::collections::hash_map::HashMap;
+use std::collections::btree_map;
+use std::collections::btree_map::BTreeMap;
 use std::fmt;
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, Read, Write};
+use std::ops::Bound;
 use std::str::FromStr;
 
 use itertools::Itertools;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use super::*;
-use super::index::{posting_from_ref, posting_from_vec};
+use super::binary::{read_binary, write_binary};
+use super::index::{dictionary_range, posting_from_ref, posting_from_vec};
 
-/// In-memory inverted index.
+/// In-memory inverted index. The term dictionary is sorted, so terms are
+/// iterated and written in lexicographic order.
 pub struct MemoryIndex<N> {
-    terms: HashMap<String, Vec<N>>,
+    terms: BTreeMap<String, Vec<N>>,
 }
 
 impl<N> MemoryIndex<N>
@@ -20,7 +24,35 @@ impl<N> MemoryIndex<N>
 {
     /// Construct an empty in-memory inverted index.
     pub fn new() -> MemoryIndex<N> {
-        MemoryIndex { terms: HashMap::new() }
+        MemoryIndex { terms: BTreeMap::new() }
+    }
+}
+
+impl<N> MemoryIndex<N>
+    where N: 'static + Clone + Ord
+{
+    /// Merge another index into this index. The postings lists of terms
+    /// that occur in both indexes are combined.
+    pub fn merge(&mut self, other: MemoryIndex<N>) {
+        for (term, docs) in other.terms {
+            match self.terms.entry(term) {
+                btree_map::Entry::Occupied(mut entry) => {
+                    let merged = posting_from_ref(entry.get()).union(&posting_from_ref(&docs));
+                    *entry.get_mut() = merged.as_slice().to_vec();
+                }
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(docs);
+                }
+            }
+        }
+    }
+}
+
+impl<N> Default for MemoryIndex<N>
+    where N: Ord
+{
+    fn default() -> Self {
+        MemoryIndex::new()
     }
 }
 
@@ -39,14 +71,14 @@ impl<N> InvertedIndexFromText<N> for MemoryIndex<N>
         let mut index = MemoryIndex::new();
 
         for line in reader.lines() {
-            let line = try!(line);
+            let line = line?;
             let mut iter = line.split_whitespace();
 
-            let term = try!(iter.next().ok_or(TextReadError::NoTerm));
+            let term = iter.next().ok_or(TextReadError::NoTerm)?;
 
             let mut docs: Vec<N> = Vec::new();
             for doc_str in iter {
-                docs.push(try!(doc_str.parse().map_err(|_| TextReadError::Parse)));
+                docs.push(doc_str.parse().map_err(|_| TextReadError::Parse)?);
             }
 
             if !is_sorted_uniq(&docs) {
@@ -68,16 +100,39 @@ impl<N> InvertedIndexToText<N> for MemoryIndex<N>
         where W: Write
     {
         for (term, posting) in &self.terms {
-            try!(write!(writer, "{}\t", term));
+            write!(writer, "{}\t", term)?;
             let docs_str = posting.iter().map(ToString::to_string).join(" ");
-            try!(writer.write(docs_str.as_bytes()));
-            try!(writer.write(&['\n' as u8]));
+            writer.write_all(docs_str.as_bytes())?;
+            writer.write_all(b"\n")?;
         }
 
         Ok(())
     }
 }
 
+impl<N> InvertedIndexFromBinary<N> for MemoryIndex<N>
+    where N: FromPrimitive + Ord
+{
+    fn from_binary<R>(reader: R) -> Result<Self, BinaryReadError>
+        where R: Read
+    {
+        let mut index = MemoryIndex::new();
+        read_binary(reader, |term, docs| index.add_postings_list(term, docs))?;
+        Ok(index)
+    }
+}
+
+impl<N> InvertedIndexToBinary<N> for MemoryIndex<N>
+    where N: ToPrimitive
+{
+    fn to_binary<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        let terms = self.terms.iter().map(|(term, docs)| (term.as_str(), docs.as_slice())).collect();
+        write_binary(terms, writer)
+    }
+}
+
 impl<N> IntoIterator for MemoryIndex<N>
     where N: 'static + Clone + Ord
 {
@@ -98,12 +153,12 @@ impl<'a, N> IntoIterator for &'a MemoryIndex<N>
     type IntoIter = Iter<'a, N>;
 
     fn into_iter(self) -> Self::IntoIter {
-        Iter { term_docs_iter: self.terms.iter() }
+        Iter { term_docs_iter: self.terms.range::<str, _>(..) }
     }
 }
 
 pub struct Iter<'a, N: 'a> {
-    term_docs_iter: hash_map::Iter<'a, String, Vec<N>>,
+    term_docs_iter: btree_map::Range<'a, String, Vec<N>>,
 }
 
 impl<'a, N> Iterator for Iter<'a, N>
@@ -112,12 +167,12 @@ impl<'a, N> Iterator for Iter<'a, N>
     type Item = (&'a str, Posting<'a, N>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.term_docs_iter.next().map(|(k, v)| (k.as_ref(), posting_from_ref(&v)))
+        self.term_docs_iter.next().map(|(k, v)| (k.as_ref(), posting_from_ref(v)))
     }
 }
 
 pub struct IntoIter<N> {
-    term_docs_iter: hash_map::IntoIter<String, Vec<N>>,
+    term_docs_iter: btree_map::IntoIter<String, Vec<N>>,
 }
 
 impl<N> Iterator for IntoIter<N>
@@ -132,14 +187,13 @@ impl<N> Iterator for IntoIter<N>
 
 impl<N: Ord> InvertedIndexMut<N> for MemoryIndex<N> {
     fn add_term(&mut self, term: &str, doc: N) {
-        let mut posting = self.terms.entry(term.to_owned()).or_insert(Vec::new());
+        let posting = self.terms.entry(term.to_owned()).or_default();
 
         if let Err(idx) = posting.binary_search(&doc) {
             posting.insert(idx, doc)
         }
     }
 
-
     fn add_postings_list<D>(&mut self, term: &str, docs: D)
         where D: Into<Vec<N>>
     {
@@ -150,12 +204,18 @@ impl<N: Ord> InvertedIndexMut<N> for MemoryIndex<N> {
 impl<N> InvertedIndex<N> for MemoryIndex<N>
     where N: Clone + Ord
 {
-    fn iter<'a>(&'a self) -> Box<Iterator<Item = (&str, Posting<N>)> + 'a> {
-        Box::new(Iter { term_docs_iter: self.terms.iter() })
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(Iter { term_docs_iter: self.terms.range::<str, _>(..) })
+    }
 
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(Iter { term_docs_iter: dictionary_range(&self.terms, from, to) })
     }
 
-    fn posting(&self, term: &str) -> Option<Posting<N>> {
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
         self.terms.get(term).map(|docs| posting_from_ref(docs))
     }
 
@@ -0,0 +1,642 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str;
+use std::str::FromStr;
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use memmap::{Mmap, Protection};
+
+/// Magic number at the start of a binary metadata file.
+pub const METADATA_MAGIC: [u8; 4] = *b"BSMD";
+
+/// The version of the binary metadata format that is written.
+pub const METADATA_VERSION: u32 = 1;
+
+/// Length that marks a missing value in the binary format.
+const MISSING: u32 = u32::MAX;
+
+const ENTRY_SIZE: usize = 16;
+
+/// Errors for reading document metadata.
+#[derive(Debug)]
+pub enum MetadataError {
+    Io(io::Error),
+    Format(String),
+    NoId(usize),
+    Parse(usize, String),
+}
+
+impl From<io::Error> for MetadataError {
+    fn from(err: io::Error) -> MetadataError {
+        MetadataError::Io(err)
+    }
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MetadataError::Io(ref err) => write!(f, "{}", err),
+            MetadataError::Format(ref err) => write!(f, "Invalid metadata file: {}", err),
+            MetadataError::NoId(line) => write!(f, "Line {}: no identifier found", line),
+            MetadataError::Parse(line, ref id) => {
+                write!(f, "Line {}: could not parse document identifier: {}", line, id)
+            }
+        }
+    }
+}
+
+/// Metadata fields of documents, such as their titles, paths, or dates.
+pub trait Metadata {
+    /// Get the names of the metadata fields.
+    fn fields(&self) -> &[String];
+
+    /// Get the value of a field of a document.
+    fn get(&self, doc: u64, field: &str) -> Option<&str>;
+
+    /// Get the title of a document.
+    fn title(&self, doc: u64) -> Option<&str> {
+        self.get(doc, "title")
+    }
+}
+
+/// Document metadata that is stored in memory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemoryMetadata {
+    fields: Vec<String>,
+    docs: BTreeMap<u64, Vec<Option<String>>>,
+}
+
+impl MemoryMetadata {
+    /// Construct an empty metadata table.
+    pub fn new() -> Self {
+        MemoryMetadata::default()
+    }
+
+    /// Read metadata in the text format. Each line contains a document
+    /// identifier followed by tab-separated field values. The field names
+    /// can be given on a tab-separated first line that starts with `#`,
+    /// such as `#id title path date`. Without such a line, the only field
+    /// is `title`. Missing and empty values are allowed.
+    pub fn from_buf_read<R>(reader: R) -> Result<Self, MetadataError>
+        where R: BufRead
+    {
+        let mut metadata = MemoryMetadata::new();
+        metadata.fields.push("title".to_owned());
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+
+            if idx == 0 && line.starts_with('#') {
+                metadata.fields = line.split('\t').skip(1).map(str::to_owned).collect();
+                continue;
+            }
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut iter = line.split('\t');
+
+            let id = iter.next().map(str::trim).filter(|id| !id.is_empty());
+            let id = id.ok_or(MetadataError::NoId(idx + 1))?;
+            let doc = id.parse().map_err(|_| MetadataError::Parse(idx + 1, id.to_owned()))?;
+
+            let mut values: Vec<_> = iter.take(metadata.fields.len())
+                .map(|value| if value.is_empty() {
+                    None
+                } else {
+                    Some(value.to_owned())
+                })
+                .collect();
+            values.resize(metadata.fields.len(), None);
+
+            metadata.docs.insert(doc, values);
+        }
+
+        Ok(metadata)
+    }
+
+    /// Set the value of a field of a document. The field is added if it
+    /// does not exist yet.
+    pub fn set(&mut self, doc: u64, field: &str, value: &str) {
+        let field_idx = match self.fields.iter().position(|f| f == field) {
+            Some(idx) => idx,
+            None => {
+                self.fields.push(field.to_owned());
+                self.fields.len() - 1
+            }
+        };
+
+        let values = self.docs.entry(doc).or_default();
+        values.resize(self.fields.len(), None);
+        values[field_idx] = Some(value.to_owned());
+    }
+
+    /// Get the identifiers of the documents, in ascending order.
+    pub fn docs(&self) -> Vec<u64> {
+        self.docs.keys().cloned().collect()
+    }
+
+    /// Get the number of documents.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Returns `true` if there are no documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Write the metadata in the text format, with a line with the field
+    /// names. Tabs and line breaks in values are replaced by spaces.
+    pub fn write_text<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writeln!(writer, "#id\t{}", self.fields.join("\t"))?;
+
+        for (doc, values) in &self.docs {
+            write!(writer, "{}", doc)?;
+            for idx in 0..self.fields.len() {
+                let value = values.get(idx).and_then(Option::as_deref).unwrap_or("");
+                write!(writer, "\t{}", value.replace(['\t', '\r', '\n'], " "))?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the metadata in the binary format. The format is:
+    ///
+    /// * Header: the magic number `BSMD`, the format version (u32), the
+    ///   number of fields (u32), and the field names. Each field name is
+    ///   stored as its length (u32) followed by its UTF-8 bytes.
+    /// * The number of documents (u64).
+    /// * Document table, sorted by document identifier. Each entry has the
+    ///   document identifier (u64) and the offset (u64) of its record in
+    ///   the record data.
+    /// * Record data. A record contains the values of all fields, each
+    ///   stored as its length (u32) followed by its UTF-8 bytes. Missing
+    ///   values have the length `u32::MAX`.
+    ///
+    /// All integers are stored in little endian byte order.
+    pub fn write_binary<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        let mut table = Vec::with_capacity(self.docs.len() * ENTRY_SIZE);
+        let mut records = Vec::new();
+
+        for (&doc, values) in &self.docs {
+            table.write_u64::<LittleEndian>(doc)?;
+            table.write_u64::<LittleEndian>(records.len() as u64)?;
+
+            for idx in 0..self.fields.len() {
+                match values.get(idx).and_then(Option::as_deref) {
+                    Some(value) => {
+                        records.write_u32::<LittleEndian>(value.len() as u32)?;
+                        records.write_all(value.as_bytes())?;
+                    }
+                    None => records.write_u32::<LittleEndian>(MISSING)?,
+                }
+            }
+        }
+
+        writer.write_all(&METADATA_MAGIC)?;
+        writer.write_u32::<LittleEndian>(METADATA_VERSION)?;
+        writer.write_u32::<LittleEndian>(self.fields.len() as u32)?;
+        for field in &self.fields {
+            writer.write_u32::<LittleEndian>(field.len() as u32)?;
+            writer.write_all(field.as_bytes())?;
+        }
+        writer.write_u64::<LittleEndian>(self.docs.len() as u64)?;
+        writer.write_all(&table)?;
+        writer.write_all(&records)
+    }
+}
+
+impl Metadata for MemoryMetadata {
+    fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    fn get(&self, doc: u64, field: &str) -> Option<&str> {
+        let idx = self.fields.iter().position(|f| f == field)?;
+        self.docs.get(&doc)?.get(idx)?.as_deref()
+    }
+}
+
+/// Document metadata that is memory-mapped from a file in the binary
+/// format. Documents are found using binary search over the document
+/// table, so only the records that are used are paged in. The file is
+/// validated when it is opened.
+pub struct MmapMetadata {
+    mmap: Mmap,
+    fields: Vec<String>,
+    n_docs: usize,
+    table_offset: usize,
+    records_offset: usize,
+}
+
+impl MmapMetadata {
+    /// Open a metadata file in the binary format.
+    pub fn open<P>(path: P) -> Result<Self, MetadataError>
+        where P: AsRef<Path>
+    {
+        let mmap = Mmap::open_path(path, Protection::Read)?;
+
+        let (fields, n_docs, table_offset, records_offset) = {
+            let data = unsafe { mmap.as_slice() };
+            validate(data)?
+        };
+
+        Ok(MmapMetadata {
+            mmap,
+            fields,
+            n_docs,
+            table_offset,
+            records_offset,
+        })
+    }
+
+    fn data(&self) -> &[u8] {
+        unsafe { self.mmap.as_slice() }
+    }
+
+    /// Get the document identifier and record offset of table entry `idx`.
+    fn entry(&self, idx: usize) -> (u64, usize) {
+        let entry = &self.data()[self.table_offset + idx * ENTRY_SIZE..];
+        (LittleEndian::read_u64(entry), LittleEndian::read_u64(&entry[8..]) as usize)
+    }
+
+    /// Find the record offset of a document using binary search.
+    fn find(&self, doc: u64) -> Option<usize> {
+        let mut size = self.n_docs;
+        let mut base = 0;
+
+        while size > 0 {
+            let half = size / 2;
+            let mid = base + half;
+
+            match self.entry(mid).0.cmp(&doc) {
+                Ordering::Less => {
+                    base = mid + 1;
+                    size -= half + 1;
+                }
+                Ordering::Equal => return Some(self.entry(mid).1),
+                Ordering::Greater => size = half,
+            }
+        }
+
+        None
+    }
+}
+
+impl Metadata for MmapMetadata {
+    fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    fn get(&self, doc: u64, field: &str) -> Option<&str> {
+        let field_idx = self.fields.iter().position(|f| f == field)?;
+        let mut record = &self.data()[self.records_offset + self.find(doc)?..];
+
+        // Records were validated when the file was opened.
+        for idx in 0..=field_idx {
+            let len = LittleEndian::read_u32(record);
+            record = &record[4..];
+
+            if len == MISSING {
+                if idx == field_idx {
+                    return None;
+                }
+                continue;
+            }
+
+            let len = len as usize;
+            if idx == field_idx {
+                return Some(unsafe { str::from_utf8_unchecked(&record[..len]) });
+            }
+            record = &record[len..];
+        }
+
+        None
+    }
+}
+
+/// Validate a binary metadata file. Returns the field names, the number
+/// of documents, and the offsets of the document table and record data.
+fn validate(data: &[u8]) -> Result<(Vec<String>, usize, usize, usize), MetadataError> {
+    let format_err = |msg: &str| MetadataError::Format(msg.to_owned());
+
+    let mut reader = Cursor { data, pos: 0 };
+
+    if reader.take(4).ok_or_else(|| format_err("file too short"))? != METADATA_MAGIC {
+        return Err(format_err("no metadata magic number"));
+    }
+
+    let version = reader.u32().ok_or_else(|| format_err("file too short"))?;
+    if version != METADATA_VERSION {
+        return Err(MetadataError::Format(format!("unsupported version: {}", version)));
+    }
+
+    let n_fields = reader.u32().ok_or_else(|| format_err("file too short"))?;
+    let mut fields = Vec::new();
+    for _ in 0..n_fields {
+        let field = reader.string().ok_or_else(|| format_err("invalid field name"))?;
+        fields.push(field.to_owned());
+    }
+
+    let n_docs = reader.u64().ok_or_else(|| format_err("file too short"))? as usize;
+    let table_offset = reader.pos;
+    let table = n_docs.checked_mul(ENTRY_SIZE)
+        .and_then(|len| reader.take(len))
+        .ok_or_else(|| format_err("truncated document table"))?;
+    let records_offset = reader.pos;
+
+    let mut prev_doc = None;
+    for entry in table.chunks(ENTRY_SIZE) {
+        let doc = LittleEndian::read_u64(entry);
+        if prev_doc.is_some_and(|prev| prev >= doc) {
+            return Err(format_err("document table is not sorted"));
+        }
+        prev_doc = Some(doc);
+
+        let mut record = Cursor {
+            data: &data[records_offset..],
+            pos: LittleEndian::read_u64(&entry[8..]) as usize,
+        };
+        for _ in 0..n_fields {
+            record.value().ok_or_else(|| format_err("invalid record"))?;
+        }
+    }
+
+    Ok((fields, n_docs, table_offset, records_offset))
+}
+
+/// Bounds-checked reader over a byte slice.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(LittleEndian::read_u32)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(LittleEndian::read_u64)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        str::from_utf8(self.take(len)?).ok()
+    }
+
+    /// Read a value that may be missing. Returns `None` if the value is
+    /// invalid.
+    fn value(&mut self) -> Option<Option<&'a str>> {
+        let len = self.u32()?;
+        if len == MISSING {
+            Some(None)
+        } else {
+            self.pos -= 4;
+            self.string().map(Some)
+        }
+    }
+}
+
+/// Open a metadata file, which can be in the binary or the text format.
+pub fn open_metadata<P>(path: P) -> Result<Box<dyn Metadata>, MetadataError>
+    where P: AsRef<Path>
+{
+    let mut magic = [0u8; 4];
+    let is_binary = {
+        let mut file = File::open(&path)?;
+        file.read_exact(&mut magic).is_ok() && magic == METADATA_MAGIC
+    };
+
+    if is_binary {
+        Ok(Box::new(MmapMetadata::open(path)?))
+    } else {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(Box::new(MemoryMetadata::from_buf_read(reader)?))
+    }
+}
+
+/// Comparison operators of metadata filters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match *self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A filter over a metadata field, such as `date>2016-01-01`.
+///
+/// Values are compared as numbers when both the field value and the filter
+/// value are numbers. Otherwise, they are compared as strings, which also
+/// orders ISO 8601 dates correctly. Documents that do not have the field
+/// do not match the filter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filter {
+    field: String,
+    comparison: Comparison,
+    value: String,
+}
+
+impl Filter {
+    /// Get the field that the filter applies to.
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Get the comparison operator.
+    pub fn comparison(&self) -> Comparison {
+        self.comparison
+    }
+
+    /// Get the value that field values are compared with.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Check whether a document matches the filter.
+    pub fn matches(&self, metadata: &dyn Metadata, doc: u64) -> bool {
+        let value = match metadata.get(doc, &self.field) {
+            Some(value) => value,
+            None => return false,
+        };
+
+        let ordering = match (value.parse::<f64>(), self.value.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(value.cmp(&self.value)),
+        };
+
+        ordering.is_some_and(|ordering| self.comparison.holds(ordering))
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field_len = s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(s.len());
+        if field_len == 0 {
+            return Err(format!("Filter does not start with a field name: {}", s));
+        }
+
+        let (field, rest) = s.split_at(field_len);
+
+        // Two-character operators are tried first.
+        let comparison = [Comparison::Ne,
+                          Comparison::Le,
+                          Comparison::Ge,
+                          Comparison::Eq,
+                          Comparison::Lt,
+                          Comparison::Gt]
+            .iter()
+            .cloned()
+            .find(|comparison| rest.starts_with(comparison.as_str()))
+            .ok_or_else(|| format!("Filter has no comparison operator: {}", s))?;
+
+        Ok(Filter {
+            field: field.to_owned(),
+            comparison,
+            value: rest[comparison.as_str().len()..].to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.field, self.comparison.as_str(), self.value)
+    }
+}
+
+/// Split the metadata filters from a ranked query, which is a sequence of
+/// whitespace-separated words without operators. Words that parse as a
+/// filter, such as `date>2016-01-01`, are filters. The remaining words form
+/// the query. Use `Query::parse_with_filters` for Boolean queries.
+pub fn split_filters(query: &str) -> (String, Vec<Filter>) {
+    let mut words = Vec::new();
+    let mut filters = Vec::new();
+
+    for word in query.split_whitespace() {
+        match word.parse() {
+            Ok(filter) => filters.push(filter),
+            Err(_) => words.push(word),
+        }
+    }
+
+    (words.join(" "), filters)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    use super::{Comparison, Filter, MemoryMetadata, Metadata, MmapMetadata, split_filters};
+    use super::super::tempfile::TempDir;
+
+    static METADATA: &str = "#id\ttitle\tdate\tlength\n\
+                             1\tNew York\t2015-06-01\t120\n\
+                             2\t\t2016-03-15\t80\n\
+                             10\tAmsterdam\n";
+
+    #[test]
+    fn metadata_roundtrip() {
+        let mut metadata = MemoryMetadata::from_buf_read(Cursor::new(METADATA)).unwrap();
+        metadata.set(10, "category", "city");
+        assert_eq!(metadata.fields(), &["title", "date", "length", "category"]);
+        assert_eq!(metadata.title(1), Some("New York"));
+        assert_eq!(metadata.title(2), None);
+        assert_eq!(metadata.get(10, "date"), None);
+
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let path = dir.path().join("metadata");
+        metadata.write_binary(&mut File::create(&path).unwrap()).unwrap();
+        let mmap = MmapMetadata::open(&path).unwrap();
+
+        let mut text = Vec::new();
+        metadata.write_text(&mut text).unwrap();
+        let text = MemoryMetadata::from_buf_read(Cursor::new(text)).unwrap();
+        assert_eq!(text.fields(), metadata.fields());
+
+        assert_eq!(mmap.fields(), metadata.fields());
+        for doc in 0..12 {
+            for field in metadata.fields() {
+                assert_eq!(mmap.get(doc, field), metadata.get(doc, field));
+                assert_eq!(text.get(doc, field), metadata.get(doc, field));
+            }
+        }
+
+        let titles = MemoryMetadata::from_buf_read(Cursor::new("3\tRotterdam\n4\n")).unwrap();
+        assert_eq!(titles.title(3), Some("Rotterdam"));
+        assert_eq!(titles.title(4), None);
+        assert!(MemoryMetadata::from_buf_read(Cursor::new("x\ttitle\n")).is_err());
+    }
+
+    #[test]
+    fn metadata_filters() {
+        let metadata = MemoryMetadata::from_buf_read(Cursor::new(METADATA)).unwrap();
+
+        let (query, filters) = split_filters("new date>2016-01-01 york length<=100");
+        assert_eq!(query, "new york");
+        assert_eq!(filters[0].comparison(), Comparison::Gt);
+        assert_eq!(filters[1].to_string(), "length<=100");
+
+        let matching = |filter: &str| -> Vec<u64> {
+            let filter: Filter = filter.parse().unwrap();
+            metadata.docs().into_iter().filter(|&doc| filter.matches(&metadata, doc)).collect()
+        };
+        assert_eq!(matching("date>2016-01-01"), vec![2]);
+        assert_eq!(matching("length>=100"), vec![1]);
+        assert_eq!(matching("length>9"), vec![1, 2]);
+        assert_eq!(matching("title!=New York"), vec![10]);
+        assert!("date".parse::<Filter>().is_err());
+        assert!(">3".parse::<Filter>().is_err());
+    }
+}
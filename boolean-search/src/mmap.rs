@@ -0,0 +1,532 @@
+use std::io;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Bound;
+use std::path::Path;
+use std::slice;
+use std::str;
+
+use byteorder::{ByteOrder, NativeEndian, WriteBytesExt};
+use memmap::{Mmap, Protection};
+use num_traits::PrimInt;
+
+use super::{BinaryReadError, InvertedIndex, Posting};
+use super::binary::check_term_order;
+use super::index::posting_from_ref;
+use super::tempfile::TempFile;
+
+/// Magic number at the start of a memory-mappable index file.
+pub const MMAP_MAGIC: [u8; 4] = *b"BSMM";
+
+/// The version of the memory-mappable index format that is written.
+pub const MMAP_VERSION: u32 = 1;
+
+/// Value that is used to check that a file was written on a machine with
+/// the same byte order.
+const BYTE_ORDER_MARK: u32 = 0x0102_0304;
+
+const HEADER_SIZE: usize = 40;
+const ENTRY_SIZE: usize = 32;
+
+/// A read-only inverted index that is memory-mapped from a file.
+///
+/// Postings lists are not decoded or copied: the postings returned by the
+/// index borrow directly from the mapping. Consequently, the index can be
+/// used immediately after opening the file, and the operating system only
+/// pages in the parts of the index that are used.
+///
+/// Document identifiers are stored in native byte order, so an index file
+/// can only be used on machines with the same byte order and with the
+/// same document identifier type as the machine that wrote it. This is
+/// checked when the index is opened. The term dictionary is validated when
+/// the index is opened, the postings lists are not.
+pub struct MmapIndex<N> {
+    mmap: Mmap,
+    n_terms: usize,
+    terms_offset: usize,
+    postings_offset: usize,
+    n_postings: usize,
+    _phantom: PhantomData<N>,
+}
+
+impl<N> MmapIndex<N>
+    where N: PrimInt
+{
+    /// Open a memory-mappable index file.
+    pub fn open<P>(path: P) -> Result<Self, BinaryReadError>
+        where P: AsRef<Path>
+    {
+        let mmap = Mmap::open_path(path, Protection::Read)?;
+
+        let (n_terms, terms_offset, postings_offset, n_postings) = {
+            let data = unsafe { mmap.as_slice() };
+            validate(data, mem::size_of::<N>(), mem::align_of::<N>())?
+        };
+
+        Ok(MmapIndex {
+            mmap,
+            n_terms,
+            terms_offset,
+            postings_offset,
+            n_postings,
+            _phantom: PhantomData,
+        })
+    }
+
+    fn data(&self) -> &[u8] {
+        unsafe { self.mmap.as_slice() }
+    }
+
+    /// Get the term and postings list of the dictionary entry `idx`.
+    fn entry(&self, idx: usize) -> (&str, &[N]) {
+        let data = self.data();
+        let entry = read_entry(data, idx);
+
+        let term_start = self.terms_offset + entry.term_offset;
+        let term = &data[term_start..term_start + entry.term_len];
+
+        // The dictionary was validated when the index was opened.
+        let term = unsafe { str::from_utf8_unchecked(term) };
+
+        let docs = unsafe {
+            let ptr = data.as_ptr().add(self.postings_offset) as *const N;
+            &slice::from_raw_parts(ptr, self.n_postings)[entry.docs_offset..entry.docs_offset +
+                                                                             entry.n_docs]
+        };
+
+        (term, docs)
+    }
+
+    /// Find the dictionary entry of a term using binary search.
+    fn find(&self, term: &str) -> Option<usize> {
+        let idx = self.lower_bound(term);
+        if idx < self.n_terms && self.entry(idx).0 == term {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+
+    /// Find the index of the first term that is not smaller than `term`.
+    fn lower_bound(&self, term: &str) -> usize {
+        let mut size = self.n_terms;
+        let mut base = 0;
+
+        while size > 0 {
+            let half = size / 2;
+            let mid = base + half;
+
+            if self.entry(mid).0 < term {
+                base = mid + 1;
+                size -= half + 1;
+            } else {
+                size = half;
+            }
+        }
+
+        base
+    }
+
+    /// Find the index of the first term that is larger than `term`.
+    fn upper_bound(&self, term: &str) -> usize {
+        match self.find(term) {
+            Some(idx) => idx + 1,
+            None => self.lower_bound(term),
+        }
+    }
+}
+
+impl<N> InvertedIndex<N> for MmapIndex<N>
+    where N: PrimInt
+{
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new((0..self.n_terms).map(move |idx| {
+            let (term, docs) = self.entry(idx);
+            (term, posting_from_ref(docs))
+        }))
+    }
+
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        let start = match from {
+            Bound::Included(term) => self.lower_bound(term),
+            Bound::Excluded(term) => self.upper_bound(term),
+            Bound::Unbounded => 0,
+        };
+
+        let end = match to {
+            Bound::Included(term) => self.upper_bound(term),
+            Bound::Excluded(term) => self.lower_bound(term),
+            Bound::Unbounded => self.n_terms,
+        };
+
+        Box::new((start..end.max(start)).map(move |idx| {
+            let (term, docs) = self.entry(idx);
+            (term, posting_from_ref(docs))
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.n_terms
+    }
+
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
+        self.find(term).map(|idx| posting_from_ref(self.entry(idx).1))
+    }
+
+    fn doc_freq(&self, term: &str) -> usize {
+        self.find(term).map(|idx| read_entry(self.data(), idx).n_docs).unwrap_or(0)
+    }
+}
+
+/// Write an inverted index in the memory-mappable format. The format is:
+///
+/// * Header: the magic number `BSMM`, the format version (u32), the size
+///   of a document identifier in bytes (u32), a byte order mark (u32), the
+///   number of terms (u64), the offset of the term data (u64), and the
+///   offset of the postings data (u64).
+/// * Term dictionary, sorted by term. Each entry has the offset (u64) and
+///   length (u64) of the term in the term data, and the offset (u64) and
+///   length (u64) of the postings list in the postings data, counted in
+///   document identifiers.
+/// * Term data: UTF-8 encoded terms.
+/// * Postings data, aligned at 8 bytes: the document identifiers of all
+///   postings lists.
+///
+/// All integers, including document identifiers, are stored in native byte
+/// order.
+pub fn write_mmap_index<I, N, W>(index: &I, writer: &mut W) -> io::Result<()>
+    where I: InvertedIndex<N> + ?Sized,
+          N: PrimInt,
+          W: Write
+{
+    let mut terms: Vec<_> = index.iter().collect();
+    terms.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut dictionary = Vec::with_capacity(terms.len() * ENTRY_SIZE);
+    let mut term_data = Vec::new();
+    let mut n_postings = 0;
+
+    for &(term, ref posting) in &terms {
+        dictionary.write_u64::<NativeEndian>(term_data.len() as u64)?;
+        dictionary.write_u64::<NativeEndian>(term.len() as u64)?;
+        dictionary.write_u64::<NativeEndian>(n_postings as u64)?;
+        dictionary.write_u64::<NativeEndian>(posting.len() as u64)?;
+
+        term_data.extend_from_slice(term.as_bytes());
+        n_postings += posting.len();
+    }
+
+    write_header::<N, W>(writer, terms.len(), &dictionary, &term_data)?;
+
+    for (_, posting) in terms {
+        writer.write_all(doc_bytes(posting.as_slice()))?;
+    }
+
+    Ok(())
+}
+
+/// Writes an index in the memory-mappable format term by term, for
+/// indexes that do not fit in memory. Only the term dictionary is kept in
+/// memory, the postings lists are buffered in a temporary file.
+pub struct MmapIndexWriter<N> {
+    dictionary: Vec<u8>,
+    term_data: Vec<u8>,
+    n_terms: usize,
+    n_postings: usize,
+    last_term: Option<String>,
+    postings: TempFile,
+    _phantom: PhantomData<N>,
+}
+
+impl<N> MmapIndexWriter<N>
+    where N: PrimInt
+{
+    /// Construct a writer that buffers postings lists in `dir`.
+    pub fn new<P>(dir: P) -> io::Result<Self>
+        where P: AsRef<Path>
+    {
+        Ok(MmapIndexWriter {
+            dictionary: Vec::new(),
+            term_data: Vec::new(),
+            n_terms: 0,
+            n_postings: 0,
+            last_term: None,
+            postings: TempFile::create(dir)?,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Add the postings list of a term. Terms must be added in ascending
+    /// order.
+    pub fn add_postings_list(&mut self, term: &str, docs: &[N]) -> io::Result<()> {
+        check_term_order(&mut self.last_term, term)?;
+
+        self.dictionary.write_u64::<NativeEndian>(self.term_data.len() as u64)?;
+        self.dictionary.write_u64::<NativeEndian>(term.len() as u64)?;
+        self.dictionary.write_u64::<NativeEndian>(self.n_postings as u64)?;
+        self.dictionary.write_u64::<NativeEndian>(docs.len() as u64)?;
+
+        self.term_data.extend_from_slice(term.as_bytes());
+        self.postings.write_all(doc_bytes(docs))?;
+
+        self.n_terms += 1;
+        self.n_postings += docs.len();
+
+        Ok(())
+    }
+
+    /// Write the index.
+    pub fn finish<W>(mut self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        write_header::<N, W>(writer, self.n_terms, &self.dictionary, &self.term_data)?;
+        self.postings.copy_to(writer)?;
+        Ok(())
+    }
+}
+
+/// Write the header, the term dictionary, and the term data, up to the
+/// postings data.
+fn write_header<N, W>(writer: &mut W,
+                      n_terms: usize,
+                      dictionary: &[u8],
+                      term_data: &[u8])
+                      -> io::Result<()>
+    where W: Write
+{
+    let terms_offset = HEADER_SIZE + dictionary.len();
+    let postings_offset = align(terms_offset + term_data.len(), 8);
+
+    writer.write_all(&MMAP_MAGIC)?;
+    writer.write_u32::<NativeEndian>(MMAP_VERSION)?;
+    writer.write_u32::<NativeEndian>(mem::size_of::<N>() as u32)?;
+    writer.write_u32::<NativeEndian>(BYTE_ORDER_MARK)?;
+    writer.write_u64::<NativeEndian>(n_terms as u64)?;
+    writer.write_u64::<NativeEndian>(terms_offset as u64)?;
+    writer.write_u64::<NativeEndian>(postings_offset as u64)?;
+    writer.write_all(dictionary)?;
+    writer.write_all(term_data)?;
+
+    let padding = postings_offset - terms_offset - term_data.len();
+    writer.write_all(&[0u8; 8][..padding])
+}
+
+/// Get the document identifiers as bytes in native byte order.
+fn doc_bytes<N>(docs: &[N]) -> &[u8]
+    where N: PrimInt
+{
+    unsafe { slice::from_raw_parts(docs.as_ptr() as *const u8, mem::size_of_val(docs)) }
+}
+
+struct Entry {
+    term_offset: usize,
+    term_len: usize,
+    docs_offset: usize,
+    n_docs: usize,
+}
+
+fn read_entry(data: &[u8], idx: usize) -> Entry {
+    let start = HEADER_SIZE + idx * ENTRY_SIZE;
+    let entry = &data[start..start + ENTRY_SIZE];
+
+    Entry {
+        term_offset: NativeEndian::read_u64(&entry[0..]) as usize,
+        term_len: NativeEndian::read_u64(&entry[8..]) as usize,
+        docs_offset: NativeEndian::read_u64(&entry[16..]) as usize,
+        n_docs: NativeEndian::read_u64(&entry[24..]) as usize,
+    }
+}
+
+/// Validate the header and term dictionary. Returns the number of terms,
+/// the offset of the term data, the offset of the postings data, and the
+/// number of document identifiers in the postings data.
+fn validate(data: &[u8],
+            doc_size: usize,
+            doc_align: usize)
+            -> Result<(usize, usize, usize, usize), BinaryReadError> {
+    if data.len() < HEADER_SIZE {
+        return Err(if data.len() >= 4 && data[..4] != MMAP_MAGIC {
+            BinaryReadError::BadMagic
+        } else {
+            BinaryReadError::Truncated
+        });
+    }
+
+    if data[..4] != MMAP_MAGIC {
+        return Err(BinaryReadError::BadMagic);
+    }
+
+    let version = NativeEndian::read_u32(&data[4..]);
+    if version != MMAP_VERSION {
+        return Err(BinaryReadError::UnsupportedVersion(version));
+    }
+
+    if NativeEndian::read_u32(&data[12..]) != BYTE_ORDER_MARK {
+        return Err(BinaryReadError::Corrupt("index was written with a different byte order"
+            .to_owned()));
+    }
+
+    let file_doc_size = NativeEndian::read_u32(&data[8..]) as usize;
+    if file_doc_size != doc_size {
+        return Err(BinaryReadError::Corrupt(format!("document identifiers have size {}, \
+                                                     expected {}",
+                                                    file_doc_size,
+                                                    doc_size)));
+    }
+
+    let n_terms = NativeEndian::read_u64(&data[16..]) as usize;
+    let terms_offset = NativeEndian::read_u64(&data[24..]) as usize;
+    let postings_offset = NativeEndian::read_u64(&data[32..]) as usize;
+
+    let dictionary_end = n_terms.checked_mul(ENTRY_SIZE)
+        .and_then(|len| len.checked_add(HEADER_SIZE))
+        .ok_or(BinaryReadError::Truncated)?;
+    if dictionary_end > terms_offset || terms_offset > postings_offset ||
+       postings_offset > data.len() {
+        return Err(BinaryReadError::Truncated);
+    }
+
+    if !postings_offset.is_multiple_of(doc_align) {
+        return Err(BinaryReadError::Corrupt("postings data is not aligned".to_owned()));
+    }
+
+    let n_postings = (data.len() - postings_offset) / doc_size;
+
+    let mut prev_term: Option<&str> = None;
+    for idx in 0..n_terms {
+        let entry = read_entry(data, idx);
+
+        let term = entry.term_offset
+            .checked_add(entry.term_len)
+            .and_then(|end| data[terms_offset..postings_offset].get(entry.term_offset..end))
+            .ok_or(BinaryReadError::Truncated)?;
+        let term = str::from_utf8(term).map_err(|_| BinaryReadError::InvalidTerm)?;
+
+        if let Some(prev_term) = prev_term {
+            if prev_term >= term {
+                return Err(BinaryReadError::Corrupt("term dictionary is not sorted".to_owned()));
+            }
+        }
+        prev_term = Some(term);
+
+        match entry.docs_offset.checked_add(entry.n_docs) {
+            Some(end) if end <= n_postings => (),
+            _ => return Err(BinaryReadError::Truncated),
+        }
+    }
+
+    Ok((n_terms, terms_offset, postings_offset, n_postings))
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use std::ops::Bound;
+    use std::path::PathBuf;
+
+    use super::super::{BinaryReadError, InvertedIndex, InvertedIndexMut, MemoryIndex};
+    use super::super::tempfile::TempDir;
+    use super::{MmapIndex, MmapIndexWriter, write_mmap_index};
+
+    /// Write data to a file in a temporary directory.
+    fn write_temp(dir: &TempDir, name: &str, data: &[u8]) -> PathBuf {
+        let path = dir.path().join(name);
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+
+    fn test_data() -> Vec<u8> {
+        let mut index = MemoryIndex::new();
+        for &(term, doc) in &[("york", 3u64), ("new", 1), ("york", 1), ("city", 7), ("new", 3)] {
+            index.add_term(term, doc);
+        }
+
+        let mut data = Vec::new();
+        write_mmap_index(&index, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn mmap_lookup() {
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let path = write_temp(&dir, "lookup", &test_data());
+        let index: MmapIndex<u64> = MmapIndex::open(&path).unwrap();
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.posting("new").unwrap().as_slice(), &[1, 3]);
+        assert_eq!(index.posting("york").unwrap().as_slice(), &[1, 3]);
+        assert_eq!(index.posting("city").unwrap().as_slice(), &[7]);
+        assert!(index.posting("boston").is_none());
+        assert_eq!(index.doc_freq("city"), 1);
+        assert_eq!(index.doc_freq("boston"), 0);
+
+        let terms: Vec<_> = index.iter().map(|(term, _)| term.to_owned()).collect();
+        assert_eq!(terms, vec!["city", "new", "york"]);
+    }
+
+    #[test]
+    fn mmap_writer() {
+        let mut writer = MmapIndexWriter::new(env::temp_dir()).unwrap();
+        writer.add_postings_list("city", &[7u64]).unwrap();
+        writer.add_postings_list("new", &[1, 3]).unwrap();
+        writer.add_postings_list("york", &[1, 3]).unwrap();
+        assert!(writer.add_postings_list("york", &[4]).is_err());
+
+        let mut data = Vec::new();
+        writer.finish(&mut data).unwrap();
+        assert_eq!(data, test_data());
+    }
+
+    #[test]
+    fn mmap_range() {
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let path = write_temp(&dir, "range", &test_data());
+        let index: MmapIndex<u64> = MmapIndex::open(&path).unwrap();
+
+        let terms = |from, to| -> Vec<String> {
+            index.range(from, to).map(|(term, _)| term.to_owned()).collect()
+        };
+
+        assert_eq!(terms(Bound::Included("new"), Bound::Unbounded), vec!["new", "york"]);
+        assert_eq!(terms(Bound::Excluded("new"), Bound::Unbounded), vec!["york"]);
+        assert_eq!(terms(Bound::Unbounded, Bound::Excluded("new")), vec!["city"]);
+        assert_eq!(terms(Bound::Included("b"), Bound::Included("new")), vec!["city", "new"]);
+        assert!(terms(Bound::Included("york"), Bound::Excluded("city")).is_empty());
+
+        let prefixed: Vec<_> = index.prefix("ne").map(|(term, _)| term).collect();
+        assert_eq!(prefixed, vec!["new"]);
+    }
+
+    #[test]
+    fn mmap_truncated_is_error() {
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let data = test_data();
+
+        // Files cannot be empty, since empty files cannot be mapped.
+        for len in 1..data.len() {
+            let path = write_temp(&dir, "truncated", &data[..len]);
+            assert!(MmapIndex::<u64>::open(&path).is_err());
+        }
+    }
+
+    #[test]
+    fn mmap_wrong_doc_type_is_error() {
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let path = write_temp(&dir, "doctype", &test_data());
+        match MmapIndex::<u32>::open(&path) {
+            Err(BinaryReadError::Corrupt(_)) => (),
+            Err(err) => panic!("Expected corrupt index, got: {:?}", err),
+            Ok(_) => panic!("Expected corrupt index"),
+        }
+    }
+}
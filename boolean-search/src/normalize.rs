@@ -0,0 +1,594 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use classify::{PTBStopwordFilter, StopwordFilter};
+use rust_stemmers::{Algorithm, Stemmer};
+use unicode_normalization::{UnicodeNormalization, is_nfc, is_nfkc};
+
+/// Errors for constructing normalization pipelines.
+#[derive(Debug)]
+pub enum NormalizeError {
+    Io(io::Error),
+    MissingArgument(String),
+    UnknownLanguage(String),
+    UnknownStep(String),
+}
+
+impl From<io::Error> for NormalizeError {
+    fn from(err: io::Error) -> NormalizeError {
+        NormalizeError::Io(err)
+    }
+}
+
+impl fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NormalizeError::Io(ref err) => write!(f, "{}", err),
+            NormalizeError::MissingArgument(ref step) => {
+                write!(f, "Normalization step requires an argument: {}", step)
+            }
+            NormalizeError::UnknownLanguage(ref lang) => {
+                write!(f, "Unknown stemmer language: {}", lang)
+            }
+            NormalizeError::UnknownStep(ref step) => {
+                write!(f, "Unknown normalization step: {}", step)
+            }
+        }
+    }
+}
+
+/// A normalizer rewrites or removes terms before they are indexed or
+/// looked up.
+pub trait Normalizer: Send + Sync {
+    /// Normalize a term, the part-of-speech tag is available during
+    /// indexing. Returns `None` if the term should be removed.
+    fn normalize<'a>(&self, term: Cow<'a, str>, tag: Option<&str>) -> Option<Cow<'a, str>>;
+
+    /// Normalize a wildcard pattern. Only character-level normalizers,
+    /// such as lowercasing, apply to patterns; the default leaves the
+    /// pattern unchanged, which is appropriate for stemmers and stopword
+    /// filters.
+    fn normalize_pattern<'a>(&self, pattern: Cow<'a, str>) -> Cow<'a, str> {
+        pattern
+    }
+
+    /// Save the state that is needed to normalize queries like the
+    /// indexed terms. This should be called after indexing.
+    fn save(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Get a normalizer for an indexing thread, which normalizes terms
+    /// like this normalizer. State that is recorded during indexing, such
+    /// as the words that `TagStopwords` removed, is recorded by the worker
+    /// and added to this normalizer when the worker is dropped. The
+    /// default implementation uses this normalizer.
+    fn worker(&self) -> Box<dyn Normalizer + '_> {
+        Box::new(self)
+    }
+}
+
+impl<N> Normalizer for &N
+    where N: Normalizer + ?Sized
+{
+    fn normalize<'a>(&self, term: Cow<'a, str>, tag: Option<&str>) -> Option<Cow<'a, str>> {
+        (**self).normalize(term, tag)
+    }
+
+    fn normalize_pattern<'a>(&self, pattern: Cow<'a, str>) -> Cow<'a, str> {
+        (**self).normalize_pattern(pattern)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        (**self).save()
+    }
+
+    fn worker(&self) -> Box<dyn Normalizer + '_> {
+        (**self).worker()
+    }
+}
+
+/// Lowercases terms.
+pub struct Lowercase;
+
+impl Normalizer for Lowercase {
+    fn normalize<'a>(&self, term: Cow<'a, str>, _tag: Option<&str>) -> Option<Cow<'a, str>> {
+        if term.chars().any(char::is_uppercase) {
+            Some(Cow::Owned(term.to_lowercase()))
+        } else {
+            Some(term)
+        }
+    }
+
+    fn normalize_pattern<'a>(&self, pattern: Cow<'a, str>) -> Cow<'a, str> {
+        self.normalize(pattern, None).expect("Lowercasing removed a term")
+    }
+}
+
+/// Applies Unicode normalization form NFC or NFKC to terms.
+pub struct UnicodeNormalize {
+    compatibility: bool,
+}
+
+impl UnicodeNormalize {
+    /// Canonical composition (NFC).
+    pub fn nfc() -> Self {
+        UnicodeNormalize { compatibility: false }
+    }
+
+    /// Compatibility composition (NFKC), which also folds characters such
+    /// as ligatures and full-width forms.
+    pub fn nfkc() -> Self {
+        UnicodeNormalize { compatibility: true }
+    }
+}
+
+impl Normalizer for UnicodeNormalize {
+    fn normalize<'a>(&self, term: Cow<'a, str>, _tag: Option<&str>) -> Option<Cow<'a, str>> {
+        if self.compatibility {
+            if is_nfkc(&term) {
+                Some(term)
+            } else {
+                Some(Cow::Owned(term.nfkc().collect()))
+            }
+        } else if is_nfc(&term) {
+            Some(term)
+        } else {
+            Some(Cow::Owned(term.nfc().collect()))
+        }
+    }
+
+    fn normalize_pattern<'a>(&self, pattern: Cow<'a, str>) -> Cow<'a, str> {
+        self.normalize(pattern, None).expect("Unicode normalization removed a term")
+    }
+}
+
+/// Removes stopwords using a tag-based stopword filter, such as
+/// `PTBStopwordFilter`.
+///
+/// Queries are not tagged, so the filter cannot be applied to them.
+/// Instead, the words that the filter removed at every occurrence during
+/// indexing are recorded, and these words are removed from untagged
+/// terms. Words that are only removed with some tags are kept.
+///
+/// Indexing threads should record the words using their own `worker`, so
+/// that they do not contend for the words of this normalizer.
+pub struct TagStopwords<F> {
+    filter: F,
+    path: Option<PathBuf>,
+    query_stopwords: HashSet<String>,
+
+    // For every word seen during indexing, whether all its occurrences
+    // were removed.
+    removed: Mutex<HashMap<String, bool>>,
+}
+
+impl<F> TagStopwords<F>
+    where F: StopwordFilter
+{
+    /// Construct a normalizer that removes the stopwords of `filter`.
+    pub fn new(filter: F) -> Self {
+        TagStopwords {
+            filter,
+            path: None,
+            query_stopwords: HashSet::new(),
+            removed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Construct a normalizer for queries that removes the stopwords of
+    /// `filter`, where the stopwords for queries are read from `path`.
+    /// The file is written during indexing, see `create`, so it is an
+    /// error if it does not exist.
+    pub fn with_file<P>(filter: F, path: P) -> io::Result<Self>
+        where P: AsRef<Path>
+    {
+        let mut stopwords = TagStopwords::new(filter);
+        stopwords.read_query_stopwords(BufReader::new(File::open(path)?))?;
+        Ok(stopwords)
+    }
+
+    /// Construct a normalizer for indexing that removes the stopwords of
+    /// `filter`. The stopwords for queries are written to `path` by
+    /// `save`.
+    pub fn create<P>(filter: F, path: P) -> Self
+        where P: AsRef<Path>
+    {
+        let mut stopwords = TagStopwords::new(filter);
+        stopwords.path = Some(path.as_ref().to_owned());
+        stopwords
+    }
+
+    /// Read the stopwords for queries, one word per line.
+    pub fn read_query_stopwords<R>(&mut self, reader: R) -> io::Result<()>
+        where R: BufRead
+    {
+        self.query_stopwords = Stopwords::from_buf_read(reader)?.words;
+        Ok(())
+    }
+
+    /// Write the words that were removed at every occurrence, one word
+    /// per line.
+    pub fn write_query_stopwords<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        let removed = self.removed.lock().unwrap();
+        let mut words: Vec<_> = removed.iter()
+            .filter(|&(_, &always_removed)| always_removed)
+            .map(|(word, _)| word)
+            .collect();
+        words.sort();
+
+        for word in words {
+            writeln!(writer, "{}", word)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check whether a term is a stopword. Tagged terms are recorded in
+    /// `removed`.
+    fn is_stopword(&self,
+                   term: &str,
+                   tag: Option<&str>,
+                   removed: &Mutex<HashMap<String, bool>>)
+                   -> bool {
+        match tag {
+            Some(tag) => {
+                let stopword = self.filter.is_stopword(tag, term);
+                record(&mut removed.lock().unwrap(), term, stopword);
+                stopword
+            }
+            None => self.query_stopwords.contains(term),
+        }
+    }
+}
+
+impl<F> Normalizer for TagStopwords<F>
+    where F: StopwordFilter + Send + Sync
+{
+    fn normalize<'a>(&self, term: Cow<'a, str>, tag: Option<&str>) -> Option<Cow<'a, str>> {
+        if self.is_stopword(&term, tag, &self.removed) {
+            None
+        } else {
+            Some(term)
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        match self.path {
+            Some(ref path) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                self.write_query_stopwords(&mut writer)?;
+                writer.flush()
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn worker(&self) -> Box<dyn Normalizer + '_> {
+        Box::new(TagStopwordsWorker {
+            stopwords: self,
+            removed: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+/// Worker of `TagStopwords`, which records the removed words in its own
+/// map. The map is only used by one thread, so its lock is uncontended.
+/// The words are added to the words of `stopwords` on drop.
+struct TagStopwordsWorker<'a, F: 'a> {
+    stopwords: &'a TagStopwords<F>,
+    removed: Mutex<HashMap<String, bool>>,
+}
+
+impl<'a, F> Normalizer for TagStopwordsWorker<'a, F>
+    where F: StopwordFilter + Send + Sync
+{
+    fn normalize<'b>(&self, term: Cow<'b, str>, tag: Option<&str>) -> Option<Cow<'b, str>> {
+        if self.stopwords.is_stopword(&term, tag, &self.removed) {
+            None
+        } else {
+            Some(term)
+        }
+    }
+}
+
+impl<'a, F> Drop for TagStopwordsWorker<'a, F> {
+    fn drop(&mut self) {
+        let mut removed = self.stopwords.removed.lock().unwrap();
+        for (term, stopword) in self.removed.get_mut().unwrap().drain() {
+            *removed.entry(term).or_insert(stopword) &= stopword;
+        }
+    }
+}
+
+/// Record whether an occurrence of a term was removed.
+fn record(removed: &mut HashMap<String, bool>, term: &str, stopword: bool) {
+    match removed.get_mut(term) {
+        Some(always_removed) => *always_removed &= stopword,
+        None => {
+            removed.insert(term.to_owned(), stopword);
+        }
+    }
+}
+
+/// Removes the stopwords in a list.
+pub struct Stopwords {
+    words: HashSet<String>,
+}
+
+impl Stopwords {
+    /// Read a stopword list with one word per line.
+    pub fn from_buf_read<R>(reader: R) -> io::Result<Self>
+        where R: BufRead
+    {
+        let mut words = HashSet::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                words.insert(line.trim().to_owned());
+            }
+        }
+
+        Ok(Stopwords { words })
+    }
+}
+
+impl Normalizer for Stopwords {
+    fn normalize<'a>(&self, term: Cow<'a, str>, _tag: Option<&str>) -> Option<Cow<'a, str>> {
+        if self.words.contains(term.as_ref()) {
+            None
+        } else {
+            Some(term)
+        }
+    }
+}
+
+/// Stems terms using a Snowball stemmer.
+pub struct Stem {
+    stemmer: Stemmer,
+}
+
+impl Stem {
+    /// Construct a stemmer for a language, such as `english` or `dutch`.
+    pub fn new(language: &str) -> Result<Self, NormalizeError> {
+        let algorithm = match language {
+            "danish" => Algorithm::Danish,
+            "dutch" => Algorithm::Dutch,
+            "english" => Algorithm::English,
+            "french" => Algorithm::French,
+            "german" => Algorithm::German,
+            "italian" => Algorithm::Italian,
+            "norwegian" => Algorithm::Norwegian,
+            "portuguese" => Algorithm::Portuguese,
+            "spanish" => Algorithm::Spanish,
+            "swedish" => Algorithm::Swedish,
+            _ => return Err(NormalizeError::UnknownLanguage(language.to_owned())),
+        };
+
+        Ok(Stem { stemmer: Stemmer::create(algorithm) })
+    }
+}
+
+impl Normalizer for Stem {
+    fn normalize<'a>(&self, term: Cow<'a, str>, _tag: Option<&str>) -> Option<Cow<'a, str>> {
+        let stem = self.stemmer.stem(&term).into_owned();
+        if stem == term {
+            Some(term)
+        } else {
+            Some(Cow::Owned(stem))
+        }
+    }
+}
+
+/// A sequence of normalizers that are applied in order. The empty
+/// pipeline leaves terms unchanged.
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn Normalizer>>,
+}
+
+impl Pipeline {
+    /// Construct an empty pipeline.
+    pub fn new() -> Self {
+        Pipeline { steps: Vec::new() }
+    }
+
+    /// Construct a pipeline for queries from a comma-separated list of
+    /// steps:
+    ///
+    /// * `lowercase`: lowercase terms.
+    /// * `nfc`, `nfkc`: Unicode normalization.
+    /// * `ptb-stopwords=FILE`: remove closed-class words and the verbs *be*
+    ///   and *have*, using Penn Treebank tags. The words that are removed
+    ///   during indexing are saved to FILE and removed from queries.
+    /// * `stopwords=FILE`: remove the words in FILE, one word per line.
+    /// * `stem=LANGUAGE`: apply a Snowball stemmer, such as `stem=english`.
+    ///
+    /// The files of `ptb-stopwords` are created during indexing, see
+    /// `from_indexing_spec`, and must exist.
+    pub fn from_spec(spec: &str) -> Result<Self, NormalizeError> {
+        Self::parse_spec(spec, false)
+    }
+
+    /// Construct a pipeline for indexing from a comma-separated list of
+    /// steps, see `from_spec`. The files of `ptb-stopwords` are written
+    /// by `save`.
+    pub fn from_indexing_spec(spec: &str) -> Result<Self, NormalizeError> {
+        Self::parse_spec(spec, true)
+    }
+
+    fn parse_spec(spec: &str, indexing: bool) -> Result<Self, NormalizeError> {
+        let mut pipeline = Pipeline::new();
+
+        for step in spec.split(',').map(str::trim).filter(|step| !step.is_empty()) {
+            let (name, arg) = match step.split_once('=') {
+                Some((name, arg)) => (name, Some(arg)),
+                None => (step, None),
+            };
+
+            let missing_arg = || NormalizeError::MissingArgument(step.to_owned());
+
+            match name {
+                "lowercase" => pipeline.push(Lowercase),
+                "nfc" => pipeline.push(UnicodeNormalize::nfc()),
+                "nfkc" => pipeline.push(UnicodeNormalize::nfkc()),
+                "ptb-stopwords" => {
+                    let path = arg.ok_or_else(missing_arg)?;
+                    if indexing {
+                        pipeline.push(TagStopwords::create(PTBStopwordFilter, path))
+                    } else {
+                        pipeline.push(TagStopwords::with_file(PTBStopwordFilter, path)?)
+                    }
+                }
+                "stopwords" => {
+                    let reader = BufReader::new(File::open(arg.ok_or_else(missing_arg)?)?);
+                    pipeline.push(Stopwords::from_buf_read(reader)?)
+                }
+                "stem" => pipeline.push(Stem::new(arg.ok_or_else(missing_arg)?)?),
+                _ => return Err(NormalizeError::UnknownStep(step.to_owned())),
+            }
+        }
+
+        Ok(pipeline)
+    }
+
+    /// Add a normalizer to the end of the pipeline.
+    pub fn push<N>(&mut self, normalizer: N)
+        where N: 'static + Normalizer
+    {
+        self.steps.push(Box::new(normalizer));
+    }
+}
+
+impl Normalizer for Pipeline {
+    fn normalize<'a>(&self, mut term: Cow<'a, str>, tag: Option<&str>) -> Option<Cow<'a, str>> {
+        for step in &self.steps {
+            term = step.normalize(term, tag)?;
+        }
+
+        Some(term)
+    }
+
+    fn normalize_pattern<'a>(&self, mut pattern: Cow<'a, str>) -> Cow<'a, str> {
+        for step in &self.steps {
+            pattern = step.normalize_pattern(pattern);
+        }
+
+        pattern
+    }
+
+    fn save(&self) -> io::Result<()> {
+        for step in &self.steps {
+            step.save()?;
+        }
+
+        Ok(())
+    }
+
+    fn worker(&self) -> Box<dyn Normalizer + '_> {
+        Box::new(PipelineWorker { steps: self.steps.iter().map(|step| step.worker()).collect() })
+    }
+}
+
+/// Worker of a `Pipeline`, which consists of the workers of its steps.
+struct PipelineWorker<'a> {
+    steps: Vec<Box<dyn Normalizer + 'a>>,
+}
+
+impl<'a> Normalizer for PipelineWorker<'a> {
+    fn normalize<'b>(&self, mut term: Cow<'b, str>, tag: Option<&str>) -> Option<Cow<'b, str>> {
+        for step in &self.steps {
+            term = step.normalize(term, tag)?;
+        }
+
+        Some(term)
+    }
+
+    fn normalize_pattern<'b>(&self, mut pattern: Cow<'b, str>) -> Cow<'b, str> {
+        for step in &self.steps {
+            pattern = step.normalize_pattern(pattern);
+        }
+
+        pattern
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::io::Cursor;
+    use std::thread;
+
+    use classify::PTBStopwordFilter;
+
+    use super::{Lowercase, Normalizer, Pipeline, Stem, Stopwords, TagStopwords,
+                UnicodeNormalize};
+
+    fn normalize(pipeline: &Pipeline, term: &str, tag: Option<&str>) -> Option<String> {
+        pipeline.normalize(Cow::Borrowed(term), tag).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn normalization_pipeline() {
+        let mut pipeline = Pipeline::from_spec("nfkc, lowercase").unwrap();
+        pipeline.push(TagStopwords::new(PTBStopwordFilter));
+        pipeline.push(Stem::new("english").unwrap());
+        pipeline.push(Stopwords::from_buf_read(Cursor::new("run\nthe\n")).unwrap());
+
+        assert_eq!(normalize(&pipeline, "Running", Some("VBG")), None);
+        assert_eq!(normalize(&pipeline, "Cities", Some("NNS")), Some("citi".to_owned()));
+        assert_eq!(normalize(&pipeline, "ﬁnance", None), Some("financ".to_owned()));
+        assert_eq!(normalize(&pipeline, "of", Some("IN")), None);
+        assert_eq!(normalize(&pipeline, "have", Some("VB")), None);
+
+        assert_eq!(normalize(&Pipeline::new(), "Of", Some("IN")), Some("Of".to_owned()));
+
+        assert_eq!(pipeline.normalize_pattern(Cow::Borrowed("ＲＵＮ*")), "run*");
+        assert_eq!(pipeline.normalize_pattern(Cow::Borrowed("Cities*")), "cities*");
+
+        assert!(Pipeline::from_spec("stem=klingon").is_err());
+        assert!(Pipeline::from_spec("stopwords").is_err());
+        assert!(Pipeline::from_spec("ptb-stopwords").is_err());
+        assert!(Pipeline::from_spec("ptb-stopwords=/nonexistent/stopwords").is_err());
+        assert!(Pipeline::from_indexing_spec("ptb-stopwords=/nonexistent/stopwords").is_ok());
+        assert!(Pipeline::from_spec("uppercase").is_err());
+    }
+
+    #[test]
+    fn tag_stopwords() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Lowercase);
+        pipeline.push(UnicodeNormalize::nfc());
+
+        // Words are also recorded by workers.
+        let stopwords = TagStopwords::new(PTBStopwordFilter);
+        stopwords.normalize(Cow::Borrowed("of"), Some("IN"));
+        thread::scope(|scope| {
+            for &(word, tag) in &[("round", "IN"), ("round", "NN"), ("cat", "NN")] {
+                let stopwords = &stopwords;
+                scope.spawn(move || stopwords.worker().normalize(Cow::Borrowed(word), Some(tag)));
+            }
+        });
+
+        // Only words that were removed at every occurrence are query stopwords.
+        let mut words = Vec::new();
+        stopwords.write_query_stopwords(&mut words).unwrap();
+        assert_eq!(String::from_utf8(words.clone()).unwrap(), "of\n");
+
+        let mut query_stopwords = TagStopwords::new(PTBStopwordFilter);
+        query_stopwords.read_query_stopwords(Cursor::new(words)).unwrap();
+        pipeline.push(query_stopwords);
+        assert_eq!(normalize(&pipeline, "Of", None), None);
+        assert_eq!(normalize(&pipeline, "round", None), Some("round".to_owned()));
+        assert_eq!(normalize(&pipeline, "the", None), Some("the".to_owned()));
+    }
+}
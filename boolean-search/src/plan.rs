@@ -0,0 +1,508 @@
+use std::fmt;
+
+use super::{EvalError, IntersectStrategy, InvertedIndex, Posting, Query, intersect_all, union_all};
+use super::index::{posting_from_vec, prefer_binsearch};
+
+/// A node of a query plan.
+enum Node {
+    /// Fetch the postings list of a term.
+    Term(String, usize),
+
+    /// Fetch and merge the postings lists of the expansions of a wildcard.
+    Wildcard(String, Vec<(String, usize)>),
+
+    /// Evaluate a phrase or proximity query using term positions. The
+    /// estimate is the smallest document frequency of its terms.
+    Positional(Query, usize),
+
+    /// Intersect the positive operands, then remove the documents of the
+    /// negative operands. Operands are ordered by their estimated size.
+    And(Vec<Node>, Vec<Node>),
+
+    /// Merge the operands.
+    Or(Vec<Node>),
+}
+
+impl Node {
+    fn new<I, N>(query: &Query, index: &I) -> Result<Node, EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: Clone + Ord
+    {
+        match *query {
+            Query::Term(ref term) => Ok(Node::Term(term.clone(), index.doc_freq(term))),
+            Query::Wildcard(ref pattern) => {
+                let terms = index.expand_wildcard(pattern)
+                    .into_iter()
+                    .map(|term| {
+                        let len = index.doc_freq(&term);
+                        (term, len)
+                    })
+                    .collect();
+                Ok(Node::Wildcard(pattern.clone(), terms))
+            }
+            Query::Phrase(_) |
+            Query::Near(..) => {
+                index.positional().ok_or(EvalError::NotPositional)?;
+                let estimate =
+                    query.terms().into_iter().map(|term| index.doc_freq(term)).min().unwrap_or(0);
+                Ok(Node::Positional(query.clone(), estimate))
+            }
+            Query::And(ref queries) => {
+                let mut positive = Vec::new();
+                let mut negative = Vec::new();
+
+                for query in queries {
+                    match *query {
+                        Query::Not(ref query) => negative.push(Node::new(query, index)?),
+                        ref query => positive.push(Node::new(query, index)?),
+                    }
+                }
+
+                if positive.is_empty() {
+                    return Err(EvalError::UnboundedNot);
+                }
+
+                positive.sort_by_key(Node::estimate);
+                negative.sort_by_key(Node::estimate);
+
+                Ok(Node::And(positive, negative))
+            }
+            Query::Or(ref queries) => {
+                let nodes = queries.iter()
+                    .map(|query| Node::new(query, index))
+                    .collect::<Result<_, _>>()?;
+                Ok(Node::Or(nodes))
+            }
+            Query::Not(_) => Err(EvalError::UnboundedNot),
+        }
+    }
+
+    /// Estimate the number of documents of the result. Intersections are
+    /// estimated by their smallest operand, unions by the sum of their
+    /// operands.
+    fn estimate(&self) -> usize {
+        match *self {
+            Node::Term(_, len) |
+            Node::Positional(_, len) => len,
+            Node::Wildcard(_, ref terms) => terms.iter().map(|&(_, len)| len).sum(),
+            Node::And(ref positive, _) => positive[0].estimate(),
+            Node::Or(ref nodes) => nodes.iter().map(Node::estimate).sum(),
+        }
+    }
+
+    /// Get the term of a term node.
+    fn term(&self) -> Option<&str> {
+        match *self {
+            Node::Term(ref term, _) => Some(term),
+            _ => None,
+        }
+    }
+
+    fn operation(&self) -> String {
+        match *self {
+            Node::Term(ref term, _) => format!("term {}", term),
+            Node::Wildcard(ref pattern, ref terms) => {
+                format!("wildcard {} ({} terms)", pattern, terms.len())
+            }
+            Node::Positional(ref query, _) => format!("positional {}", query),
+            Node::And(..) => "and".to_owned(),
+            Node::Or(..) => "union".to_owned(),
+        }
+    }
+
+    /// Describe a node that was not executed, because the result was
+    /// already known to be empty.
+    fn skipped(&self) -> Explain {
+        Explain {
+            operation: self.operation(),
+            algorithm: None,
+            estimate: self.estimate(),
+            size: None,
+            postings: 0,
+            children: Vec::new(),
+        }
+    }
+
+    /// Describe a term node whose postings list was not retrieved,
+    /// because the index intersected it. The document frequency of a
+    /// term is its size.
+    fn not_retrieved(&self) -> Explain {
+        Explain {
+            operation: self.operation(),
+            algorithm: None,
+            estimate: self.estimate(),
+            size: Some(self.estimate()),
+            postings: 0,
+            children: Vec::new(),
+        }
+    }
+
+    fn execute<'a, I, N>(&self,
+                         index: &'a I,
+                         strategy: Option<IntersectStrategy>)
+                         -> Result<(Posting<'a, N>, Explain), EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: 'static + Clone + Ord
+    {
+        // Only term and positional nodes read postings from the index.
+        let mut postings = 0;
+        let (result, algorithm, children) = match *self {
+            Node::Term(ref term, _) => {
+                let posting = index.posting(term).unwrap_or_else(|| posting_from_vec(Vec::new()));
+                postings = posting.len();
+                (posting, None, Vec::new())
+            }
+            Node::Wildcard(_, ref terms) => {
+                let mut postings = Vec::new();
+                let mut children = Vec::new();
+                for &(ref term, len) in terms {
+                    let node = Node::Term(term.clone(), len);
+                    let (posting, explain) = node.execute(index, strategy)?;
+                    postings.push(posting);
+                    children.push(explain);
+                }
+
+                (union_all(&postings), Some("merge".to_owned()), children)
+            }
+            Node::Positional(ref query, _) => {
+                postings = query.terms().into_iter().map(|term| index.doc_freq(term)).sum();
+                (query.evaluate(index)?, Some("positions".to_owned()), Vec::new())
+            }
+            Node::And(ref positive, ref negative) => {
+                return self.execute_and(positive, negative, index, strategy);
+            }
+            Node::Or(ref nodes) => {
+                let mut postings = Vec::new();
+                let mut children = Vec::new();
+                for node in nodes {
+                    let (posting, explain) = node.execute(index, strategy)?;
+                    postings.push(posting);
+                    children.push(explain);
+                }
+
+                (union_all(&postings), Some("merge".to_owned()), children)
+            }
+        };
+
+        let explain = Explain {
+            operation: self.operation(),
+            algorithm,
+            estimate: self.estimate(),
+            size: Some(result.len()),
+            postings,
+            children,
+        };
+
+        Ok((result, explain))
+    }
+
+    fn execute_and<'a, I, N>(&self,
+                             positive: &[Node],
+                             negative: &[Node],
+                             index: &'a I,
+                             strategy: Option<IntersectStrategy>)
+                             -> Result<(Posting<'a, N>, Explain), EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: 'static + Clone + Ord
+    {
+        let (mut result, mut explain) = match strategy {
+            // Intersect all lists at once.
+            None => {
+                let mut postings = Vec::new();
+                let mut children = Vec::new();
+                let mut nodes: Vec<_> = positive.iter().collect();
+
+                // Let the index intersect the terms if it can do so without
+                // retrieving their postings lists, e.g. compressed lists.
+                let terms: Vec<_> = positive.iter().filter_map(Node::term).collect();
+                if terms.len() > 1 {
+                    if let Some(posting) = index.intersect_terms(&terms) {
+                        nodes.retain(|node| node.term().is_none());
+                        children.push(Explain {
+                            operation: "intersect".to_owned(),
+                            algorithm: Some("skip pointers".to_owned()),
+                            estimate: positive.iter()
+                                .filter(|node| node.term().is_some())
+                                .map(Node::estimate)
+                                .min()
+                                .unwrap_or(0),
+                            size: Some(posting.len()),
+                            postings: 0,
+                            children: positive.iter()
+                                .filter(|node| node.term().is_some())
+                                .map(Node::not_retrieved)
+                                .collect(),
+                        });
+                        postings.push(posting);
+                    }
+                }
+
+                for node in nodes {
+                    if postings.last().is_some_and(Posting::is_empty) {
+                        children.push(node.skipped());
+                        continue;
+                    }
+
+                    let (posting, explain) = node.execute(index, strategy)?;
+                    postings.push(posting);
+                    children.push(explain);
+                }
+
+                let result = intersect_all(&postings);
+                let explain = Explain {
+                    operation: "intersect".to_owned(),
+                    algorithm: Some("galloping, all lists".to_owned()),
+                    estimate: self.estimate(),
+                    size: Some(result.len()),
+                    postings: 0,
+                    children,
+                };
+
+                (result, explain)
+            }
+
+            // Intersect pairwise, starting with the smallest lists.
+            Some(strategy) => {
+                let (mut result, mut explain) = positive[0].execute(index, Some(strategy))?;
+                for node in &positive[1..] {
+                    if result.is_empty() {
+                        explain = combine("intersect", None, explain, node.skipped(), 0);
+                        continue;
+                    }
+
+                    let (posting, node_explain) = node.execute(index, Some(strategy))?;
+                    let algorithm = strategy.resolve(result.len(), posting.len());
+                    result = result.intersect_with(&posting, algorithm);
+                    explain = combine("intersect",
+                                      Some(algorithm.to_string()),
+                                      explain,
+                                      node_explain,
+                                      result.len());
+                }
+
+                (result, explain)
+            }
+        };
+
+        for node in negative {
+            if result.is_empty() {
+                explain = combine("difference", None, explain, node.skipped(), 0);
+                continue;
+            }
+
+            let (posting, node_explain) = node.execute(index, strategy)?;
+            let algorithm = if prefer_binsearch(result.len(), posting.len()) {
+                "binsearch"
+            } else {
+                "linear"
+            };
+            result = result.difference(&posting);
+            explain = combine("difference",
+                              Some(algorithm.to_owned()),
+                              explain,
+                              node_explain,
+                              result.len());
+        }
+
+        Ok((result, explain))
+    }
+}
+
+/// Construct the description of an intersection or difference, which is
+/// estimated by its smallest operand or by its left operand respectively.
+fn combine(operation: &str,
+           algorithm: Option<String>,
+           left: Explain,
+           right: Explain,
+           size: usize)
+           -> Explain {
+    let estimate = if operation == "intersect" {
+        left.estimate.min(right.estimate)
+    } else {
+        left.estimate
+    };
+
+    Explain {
+        operation: operation.to_owned(),
+        estimate,
+        size: left.size.map(|_| size),
+        postings: 0,
+        algorithm,
+        children: vec![left, right],
+    }
+}
+
+/// A plan for evaluating a Boolean query. The operands of conjunctions
+/// are ordered by their estimated number of documents, so that the
+/// smallest postings lists are intersected first, and operands are not
+/// evaluated once the result of a conjunction is empty.
+pub struct QueryPlan {
+    root: Node,
+}
+
+impl QueryPlan {
+    /// Plan a query, using the document frequencies of its terms in the
+    /// index as estimates.
+    pub fn new<I, N>(query: &Query, index: &I) -> Result<Self, EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: Clone + Ord
+    {
+        Ok(QueryPlan { root: Node::new(query, index)? })
+    }
+
+    /// Get the estimated number of documents of the result.
+    pub fn estimate(&self) -> usize {
+        self.root.estimate()
+    }
+
+    /// Execute the plan. Conjunctions are intersected pairwise using
+    /// `strategy`, or all at once if `strategy` is `None`. Returns the
+    /// result and a description of the execution.
+    pub fn execute<'a, I, N>(&self,
+                             index: &'a I,
+                             strategy: Option<IntersectStrategy>)
+                             -> Result<(Posting<'a, N>, Explain), EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: 'static + Clone + Ord
+    {
+        self.root.execute(index, strategy)
+    }
+}
+
+/// Description of the execution of a query plan: the operations, the
+/// algorithms that were used, and the estimated and actual sizes of the
+/// intermediate results.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Explain {
+    operation: String,
+    algorithm: Option<String>,
+    estimate: usize,
+    size: Option<usize>,
+    postings: usize,
+    children: Vec<Explain>,
+}
+
+impl Explain {
+    /// Get the operation, such as `term city` or `intersect`.
+    pub fn operation(&self) -> &str {
+        &self.operation
+    }
+
+    /// Get the algorithm that was used for the operation.
+    pub fn algorithm(&self) -> Option<&str> {
+        self.algorithm.as_deref()
+    }
+
+    /// Get the estimated number of documents of the result.
+    pub fn estimate(&self) -> usize {
+        self.estimate
+    }
+
+    /// Get the number of documents of the result. Returns `None` if the
+    /// operation was skipped.
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// Get the number of postings that the operation and its operands
+    /// read from the index. Skipped operations do not read postings, nor
+    /// do terms whose postings lists were intersected by the index.
+    pub fn postings(&self) -> usize {
+        self.postings + self.children.iter().map(Explain::postings).sum::<usize>()
+    }
+
+    /// Get the operands of the operation.
+    pub fn children(&self) -> &[Explain] {
+        &self.children
+    }
+
+    fn fmt_indent(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{:width$}{}", "", self.operation, width = depth * 2)?;
+
+        if let Some(ref algorithm) = self.algorithm {
+            write!(f, " [{}]", algorithm)?;
+        }
+
+        match self.size {
+            Some(size) => write!(f, ": estimated {}, actual {}", self.estimate, size)?,
+            None => write!(f, ": estimated {}, skipped", self.estimate)?,
+        }
+
+        for child in &self.children {
+            writeln!(f)?;
+            child.fmt_indent(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryPlan;
+    use super::super::{Codec, CompressedMemoryIndex, IntersectStrategy, InvertedIndexMut,
+                       MemoryIndex, Query};
+
+    #[test]
+    fn plan_explain() {
+        let mut index = MemoryIndex::new();
+        index.add_postings_list("city", (0u64..100).collect::<Vec<_>>());
+        index.add_postings_list("new", (0u64..50).map(|doc| doc * 2).collect::<Vec<_>>());
+        index.add_postings_list("york", vec![2u64, 4, 7]);
+        index.add_postings_list("old", vec![4u64]);
+
+        let query = Query::parse("city new york NOT old").unwrap();
+        let plan = QueryPlan::new(&query, &index).unwrap();
+        assert_eq!(plan.estimate(), 3);
+
+        let (result, explain) = plan.execute(&index, Some(IntersectStrategy::Adaptive)).unwrap();
+        assert_eq!(result.as_slice(), &[2]);
+        assert_eq!(explain.to_string(),
+                   "difference [binsearch]: estimated 3, actual 1\n  \
+                    intersect [binsearch]: estimated 3, actual 2\n    \
+                    intersect [binsearch]: estimated 3, actual 2\n      \
+                    term york: estimated 3, actual 3\n      \
+                    term new: estimated 50, actual 50\n    \
+                    term city: estimated 100, actual 100\n  \
+                    term old: estimated 1, actual 1");
+        assert_eq!(explain.postings(), 154);
+
+        let query = Query::parse("boston city (new OR ne*)").unwrap();
+        let (result, explain) = QueryPlan::new(&query, &index)
+            .unwrap()
+            .execute(&index, None)
+            .unwrap();
+        assert!(result.is_empty());
+        assert_eq!(explain.children()[0].operation(), "term boston");
+        assert_eq!(explain.children()[1].size(), None);
+        assert_eq!(explain.children()[2].size(), None);
+        assert_eq!(explain.postings(), 0);
+
+        // Compressed lists are intersected by the index.
+        let compressed = CompressedMemoryIndex::from_index(&index, Codec::VByte);
+        let query = Query::parse("city new york NOT old").unwrap();
+        let (result, explain) = QueryPlan::new(&query, &compressed)
+            .unwrap()
+            .execute(&compressed, None)
+            .unwrap();
+        assert_eq!(result.as_slice(), &[2]);
+        assert_eq!(explain.to_string(),
+                   "difference [binsearch]: estimated 3, actual 1\n  \
+                    intersect [galloping, all lists]: estimated 3, actual 2\n    \
+                    intersect [skip pointers]: estimated 3, actual 2\n      \
+                    term york: estimated 3, actual 3\n      \
+                    term new: estimated 50, actual 50\n      \
+                    term city: estimated 100, actual 100\n  \
+                    term old: estimated 1, actual 1");
+        assert_eq!(explain.postings(), 1);
+
+        assert!(QueryPlan::new(&Query::parse("NOT old").unwrap(), &index).is_err());
+        assert!(QueryPlan::new(&Query::parse("\"new york\"").unwrap(), &index).is_err());
+    }
+}
@@ -0,0 +1,406 @@
+use std::borrow::Cow;
+use std::collections::btree_map;
+use std::collections::btree_map::BTreeMap;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+use std::ops::Bound;
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+use super::*;
+use super::frequency::frequency_posting_from_cows;
+use super::index::{dictionary_range, posting_from_cow, posting_from_ref};
+
+/// A PositionalIndexMut is a positional inverted index that can be mutated.
+pub trait PositionalIndexMut<N: Ord> {
+    /// Add an occurrence of a term in a document at the given (token)
+    /// position.
+    fn add_term_position(&mut self, term: &str, doc: N, position: u32);
+}
+
+/// A positional inverted index stores for each term the documents in
+/// which the term occurs, plus the positions of the term within each
+/// document.
+pub trait PositionalIndex<N: Clone + Ord>: InvertedIndex<N> {
+    /// Retrieve the positional postings list for a term.
+    fn positions(&self, term: &str) -> Option<PositionalPosting<'_, N>>;
+}
+
+/// A positional posting is a sorted list of unique document identifiers,
+/// where every document has a sorted list of unique positions.
+#[derive(Clone, Debug)]
+pub struct PositionalPosting<'a, N>
+    where N: Ord + Clone + 'a
+{
+    docs: Cow<'a, [N]>,
+    positions: Cow<'a, [Vec<u32>]>,
+}
+
+impl<'a, N> PositionalPosting<'a, N>
+    where N: Clone + Ord
+{
+    /// Find the documents where the other postings list follows this
+    /// postings list directly. The positions in the result are those of
+    /// `other`, so that phrases can be matched by repeated application.
+    pub fn phrase(&self, other: &PositionalPosting<N>) -> PositionalPosting<'static, N> {
+        self.positional_intersect(other, 1, 1)
+    }
+
+    /// Find the documents where the other postings list occurs within `k`
+    /// positions of this postings list. The positions in the result are
+    /// those of `other`.
+    pub fn near(&self, other: &PositionalPosting<N>, k: u32) -> PositionalPosting<'static, N> {
+        self.positional_intersect(other, -(k as i64), k as i64)
+    }
+
+    /// Intersect two positional postings lists, retaining a position p2
+    /// of `other` when this list has a position p1 in the same document
+    /// with `min_offset <= p2 - p1 <= max_offset`.
+    fn positional_intersect(&self,
+                            other: &PositionalPosting<N>,
+                            min_offset: i64,
+                            max_offset: i64)
+                            -> PositionalPosting<'static, N> {
+        let mut docs = Vec::new();
+        let mut positions = Vec::new();
+
+        let mut p1i = 0;
+        let mut p2i = 0;
+
+        while p1i != self.docs.len() && p2i != other.docs.len() {
+            let doc1 = &self.docs[p1i];
+            let doc2 = &other.docs[p2i];
+
+            if doc1 == doc2 {
+                let matches = window_matches(&self.positions[p1i],
+                                             &other.positions[p2i],
+                                             min_offset,
+                                             max_offset);
+                if !matches.is_empty() {
+                    docs.push(doc1.clone());
+                    positions.push(matches);
+                }
+
+                p1i += 1;
+                p2i += 1;
+            } else if doc1 < doc2 {
+                p1i += 1;
+            } else {
+                p2i += 1;
+            }
+        }
+
+        PositionalPosting {
+            docs: Cow::Owned(docs),
+            positions: Cow::Owned(positions),
+        }
+    }
+
+    /// Get an iterator over the documents and their term positions.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&N, &[u32])> + '_> {
+        Box::new(self.docs.iter().zip(self.positions.iter().map(Vec::as_slice)))
+    }
+
+    /// Returns `true` if the postings list does not contain any documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Get the number of documents in the postings list.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Convert to a (non-positional) postings list.
+    pub fn into_posting(self) -> Posting<'a, N> {
+        posting_from_cow(self.docs)
+    }
+}
+
+pub fn positional_posting_from_vecs<N>(docs: Vec<N>,
+                                      positions: Vec<Vec<u32>>)
+                                      -> PositionalPosting<'static, N>
+    where N: Clone + Ord
+{
+    assert_eq!(docs.len(), positions.len());
+
+    PositionalPosting {
+        docs: Cow::Owned(docs),
+        positions: Cow::Owned(positions),
+    }
+}
+
+/// Return the positions p2 in `positions2` for which there is a position
+/// p1 in `positions1` with `min_offset <= p2 - p1 <= max_offset`.
+fn window_matches(positions1: &[u32],
+                  positions2: &[u32],
+                  min_offset: i64,
+                  max_offset: i64)
+                  -> Vec<u32> {
+    let mut matches = Vec::new();
+
+    let mut p1i = 0;
+    for &p2 in positions2 {
+        // Skip positions that are too far before p2. Since p2 increases,
+        // these positions cannot be in the window of later positions.
+        while p1i != positions1.len() && (p2 as i64 - positions1[p1i] as i64) > max_offset {
+            p1i += 1;
+        }
+
+        if p1i != positions1.len() && (p2 as i64 - positions1[p1i] as i64) >= min_offset {
+            matches.push(p2);
+        }
+    }
+
+    matches
+}
+
+struct PositionalPostings<N> {
+    docs: Vec<N>,
+    positions: Vec<Vec<u32>>,
+}
+
+impl<N> PositionalPostings<N> {
+    fn new() -> Self {
+        PositionalPostings {
+            docs: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+}
+
+/// In-memory positional inverted index.
+pub struct PositionalMemoryIndex<N> {
+    terms: BTreeMap<String, PositionalPostings<N>>,
+}
+
+impl<N> PositionalMemoryIndex<N>
+    where N: Ord
+{
+    /// Construct an empty in-memory positional inverted index.
+    pub fn new() -> PositionalMemoryIndex<N> {
+        PositionalMemoryIndex { terms: BTreeMap::new() }
+    }
+}
+
+impl<N> Default for PositionalMemoryIndex<N>
+    where N: Ord
+{
+    fn default() -> Self {
+        PositionalMemoryIndex::new()
+    }
+}
+
+impl<N: Ord> PositionalIndexMut<N> for PositionalMemoryIndex<N> {
+    fn add_term_position(&mut self, term: &str, doc: N, position: u32) {
+        let postings = self.terms.entry(term.to_owned()).or_insert_with(PositionalPostings::new);
+
+        let idx = match postings.docs.binary_search(&doc) {
+            Ok(idx) => idx,
+            Err(idx) => {
+                postings.docs.insert(idx, doc);
+                postings.positions.insert(idx, Vec::new());
+                idx
+            }
+        };
+
+        let positions = &mut postings.positions[idx];
+        if let Err(idx) = positions.binary_search(&position) {
+            positions.insert(idx, position);
+        }
+    }
+}
+
+impl<N> InvertedIndexFromText<N> for PositionalMemoryIndex<N>
+    where N: FromStr + Ord
+{
+    /// Read a positional inverted index from a buffered reader. The
+    /// expected format is:
+    ///
+    /// * Each line contains a term and postings list.
+    /// * The term and postings list are separated by the tab character.
+    /// * The postings list consists of documents separated by the space
+    ///   character.
+    /// * A document is an unsigned integer, followed by a colon, followed
+    ///   by a list of positions separated by the comma character.
+    fn from_text<R>(reader: R) -> Result<Self, TextReadError>
+        where R: BufRead
+    {
+        let mut index = PositionalMemoryIndex::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut iter = line.split_whitespace();
+
+            let term = iter.next().ok_or(TextReadError::NoTerm)?;
+
+            let mut postings = PositionalPostings::new();
+            for doc_str in iter {
+                let mut doc_iter = doc_str.splitn(2, ':');
+                let doc = doc_iter.next().ok_or(TextReadError::Parse)?;
+                let positions = doc_iter.next().ok_or(TextReadError::Parse)?;
+
+                postings.docs.push(doc.parse().map_err(|_| TextReadError::Parse)?);
+
+                let positions = positions.split(',')
+                    .map(|pos| pos.parse().map_err(|_| TextReadError::Parse))
+                    .collect::<Result<Vec<u32>, _>>()?;
+                if !is_sorted_uniq(&positions) {
+                    return Err(TextReadError::NotSortedOrUnique(line.to_owned()));
+                }
+
+                postings.positions.push(positions);
+            }
+
+            if !is_sorted_uniq(&postings.docs) {
+                return Err(TextReadError::NotSortedOrUnique(line.to_owned()));
+            }
+
+            index.terms.insert(term.to_owned(), postings);
+        }
+
+        Ok(index)
+    }
+}
+
+impl<N> InvertedIndexToText<N> for PositionalMemoryIndex<N>
+    where N: fmt::Display
+{
+    fn to_text<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        for (term, postings) in &self.terms {
+            write!(writer, "{}\t", term)?;
+            let docs_str = postings.docs
+                .iter()
+                .zip(&postings.positions)
+                .map(|(doc, positions)| format!("{}:{}", doc, positions.iter().join(",")))
+                .join(" ");
+            writer.write_all(docs_str.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Iter<'a, N: 'a> {
+    term_postings_iter: btree_map::Range<'a, String, PositionalPostings<N>>,
+}
+
+impl<'a, N> Iterator for Iter<'a, N>
+    where N: Clone + Ord
+{
+    type Item = (&'a str, Posting<'a, N>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.term_postings_iter.next().map(|(k, v)| (k.as_ref(), posting_from_ref(&v.docs)))
+    }
+}
+
+impl<N> InvertedIndex<N> for PositionalMemoryIndex<N>
+    where N: Clone + Ord
+{
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(Iter { term_postings_iter: self.terms.range::<str, _>(..) })
+    }
+
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        Box::new(Iter { term_postings_iter: dictionary_range(&self.terms, from, to) })
+    }
+
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
+        self.terms.get(term).map(|postings| posting_from_ref(&postings.docs))
+    }
+
+    fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    fn positional(&self) -> Option<&dyn PositionalIndex<N>> {
+        Some(self)
+    }
+
+    fn frequencies(&self) -> Option<&dyn FrequencyIndex<N>> {
+        Some(self)
+    }
+}
+
+impl<N> FrequencyIndex<N> for PositionalMemoryIndex<N>
+    where N: Clone + Ord
+{
+    /// Retrieve the postings list for a term with term frequencies. The
+    /// frequencies are computed from the number of positions.
+    fn term_frequencies(&self, term: &str) -> Option<FrequencyPosting<'_, N>> {
+        self.terms.get(term).map(|postings| {
+            let freqs = postings.positions.iter().map(|positions| positions.len() as u32).collect();
+            frequency_posting_from_cows(Cow::Borrowed(&postings.docs), Cow::Owned(freqs))
+        })
+    }
+}
+
+impl<N> PositionalIndex<N> for PositionalMemoryIndex<N>
+    where N: Clone + Ord
+{
+    fn positions(&self, term: &str) -> Option<PositionalPosting<'_, N>> {
+        self.terms.get(term).map(|postings| {
+            PositionalPosting {
+                docs: Cow::Borrowed(&postings.docs),
+                positions: Cow::Borrowed(&postings.positions),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PositionalIndex, PositionalIndexMut, PositionalMemoryIndex};
+
+    fn test_index() -> PositionalMemoryIndex<u32> {
+        let mut index = PositionalMemoryIndex::new();
+
+        for (doc, text) in vec!["new york is a city", "york is not new", "a new house in york"]
+            .into_iter()
+            .enumerate() {
+            for (pos, term) in text.split_whitespace().enumerate() {
+                index.add_term_position(term, doc as u32, pos as u32);
+            }
+        }
+
+        index
+    }
+
+    fn docs(posting: super::PositionalPosting<u32>) -> Vec<u32> {
+        posting.into_posting().into_iter().collect()
+    }
+
+    #[test]
+    fn phrase() {
+        let index = test_index();
+        let new = index.positions("new").unwrap();
+        let york = index.positions("york").unwrap();
+        let is = index.positions("is").unwrap();
+
+        assert_eq!(docs(new.phrase(&york)), vec![0]);
+        assert_eq!(docs(new.phrase(&york).phrase(&is)), vec![0]);
+        assert_eq!(docs(york.phrase(&is)), vec![0, 1]);
+        assert_eq!(docs(york.phrase(&new)), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn near() {
+        let index = test_index();
+        let new = index.positions("new").unwrap();
+        let york = index.positions("york").unwrap();
+
+        assert_eq!(docs(new.near(&york, 1)), vec![0]);
+        assert_eq!(docs(new.near(&york, 2)), vec![0]);
+        assert_eq!(docs(new.near(&york, 3)), vec![0, 1, 2]);
+        assert_eq!(docs(york.near(&new, 3)), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,831 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use super::index::posting_from_vec;
+use super::positional::positional_posting_from_vecs;
+use super::{Filter, IntersectStrategy, InvertedIndex, PositionalIndex, PositionalPosting,
+            Posting, intersect_all, union_all};
+
+/// Errors that can occur while parsing a query.
+#[derive(Debug, Eq, PartialEq)]
+pub enum QueryError {
+    Empty,
+    InvalidNear(String),
+    InvalidNearOperand(String),
+    MisplacedFilter(String),
+    UnclosedParen,
+    UnclosedQuote,
+    UnexpectedEnd,
+    UnexpectedToken(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::Empty => write!(f, "Empty query"),
+            QueryError::InvalidNear(ref op) => write!(f, "Invalid proximity operator: {}", op),
+            QueryError::InvalidNearOperand(ref query) => {
+                write!(f, "Proximity operands must be terms or phrases: {}", query)
+            }
+            QueryError::MisplacedFilter(ref filter) => {
+                write!(f, "Filters can only be used as top-level conjuncts: {}", filter)
+            }
+            QueryError::UnclosedParen => write!(f, "Missing closing parenthesis"),
+            QueryError::UnclosedQuote => write!(f, "Missing closing quote"),
+            QueryError::UnexpectedEnd => write!(f, "Unexpected end of query"),
+            QueryError::UnexpectedToken(ref token) => write!(f, "Unexpected token: {}", token),
+        }
+    }
+}
+
+/// Errors that can occur while evaluating a query.
+#[derive(Debug, Eq, PartialEq)]
+pub enum EvalError {
+    NotPositional,
+    UnboundedNot,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::NotPositional => {
+                write!(f, "Phrase and proximity queries require a positional index")
+            }
+            EvalError::UnboundedNot => {
+                write!(f, "NOT can only be used in a conjunction with a positive term")
+            }
+        }
+    }
+}
+
+/// Abstract syntax tree of a Boolean query.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Query {
+    /// Documents containing the term.
+    Term(String),
+
+    /// Documents containing the terms as a consecutive sequence.
+    Phrase(Vec<String>),
+
+    /// Documents containing any term that matches the pattern, where `*`
+    /// matches any sequence of characters.
+    Wildcard(String),
+
+    /// Documents where the second subquery matches within the given
+    /// number of positions of the first subquery. The subqueries should
+    /// be terms, phrases, or proximity queries.
+    Near(Box<Query>, Box<Query>, u32),
+
+    /// Documents satisfying all subqueries.
+    And(Vec<Query>),
+
+    /// Documents satisfying at least one subquery.
+    Or(Vec<Query>),
+
+    /// Documents not satisfying the subquery.
+    Not(Box<Query>),
+}
+
+impl Query {
+    /// Parse a Boolean query. The query language supports:
+    ///
+    /// * Terms, which are separated by whitespace. Terms can be quoted to
+    ///   use operator names or parentheses as terms.
+    /// * Phrases: quoted sequences of multiple terms, such as `"new york"`.
+    /// * Wildcard terms, such as `comput*`, `*ization`, or `re*ing`, which
+    ///   match any term that matches the pattern. Quoted terms are never
+    ///   treated as wildcards.
+    /// * The binary operators `AND` and `OR`. Juxtaposition of subqueries
+    ///   is an implicit `AND`.
+    /// * The proximity operator `NEAR/k`, which matches when its operands
+    ///   occur within `k` positions of each other.
+    /// * The unary operator `NOT`.
+    /// * Grouping using parentheses.
+    ///
+    /// Terms can be qualified with a field, as in `lemma:run AND pos:VB`.
+    /// Use `filter_map_terms` with `Fields::index_term` to rewrite the terms
+    /// to the terms of an index with multiple fields.
+    ///
+    /// `NEAR/k` binds stronger than `NOT`, which binds stronger than `AND`,
+    /// which binds stronger than `OR`. Phrase and proximity queries can
+    /// only be evaluated on a positional index.
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        Self::parse_tokens(tokenize(query, false)?).map(|(query, _)| query)
+    }
+
+    /// Parse a Boolean query that can be restricted by metadata filters,
+    /// such as `new york date>2016-01-01`. Unquoted terms that parse as a
+    /// filter are filters. Filters must be top-level conjuncts of the
+    /// query, they cannot be used in disjunctions, negations, proximity
+    /// queries, or parentheses.
+    pub fn parse_with_filters(query: &str) -> Result<(Query, Vec<Filter>), QueryError> {
+        Self::parse_tokens(tokenize(query, true)?)
+    }
+
+    fn parse_tokens(tokens: Vec<Token>) -> Result<(Query, Vec<Filter>), QueryError> {
+        if tokens.is_empty() {
+            return Err(QueryError::Empty);
+        }
+
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+            filters: Vec::new(),
+        };
+
+        let query = parser.parse_or()?;
+
+        match parser.peek() {
+            None => Ok((query, parser.filters)),
+            Some(token) => Err(QueryError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    /// Evaluate the query against an inverted index. Terms that are not in
+    /// the index match no documents.
+    pub fn evaluate<'a, I, N>(&self, index: &'a I) -> Result<Posting<'a, N>, EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: 'static + Clone + Ord
+    {
+        self.evaluate_with(index, None)
+    }
+
+    /// Evaluate the query, intersecting the postings lists of conjunctions
+    /// pairwise using `strategy`, starting with the shortest lists. If
+    /// `strategy` is `None`, all postings lists of a conjunction are
+    /// intersected at once using `intersect_all`.
+    pub fn evaluate_with<'a, I, N>(&self,
+                                   index: &'a I,
+                                   strategy: Option<IntersectStrategy>)
+                                   -> Result<Posting<'a, N>, EvalError>
+        where I: InvertedIndex<N> + ?Sized,
+              N: 'static + Clone + Ord
+    {
+        match *self {
+            Query::Term(ref term) => {
+                Ok(index.posting(term).unwrap_or_else(|| posting_from_vec(Vec::new())))
+            }
+            Query::Wildcard(ref pattern) => {
+                let postings: Vec<_> = index.expand_wildcard(pattern)
+                    .iter()
+                    .filter_map(|term| index.posting(term))
+                    .collect();
+                Ok(union_all(&postings))
+            }
+            Query::And(ref queries) => evaluate_and(queries, index, strategy),
+            Query::Or(ref queries) => {
+                let postings = queries.iter()
+                    .map(|query| query.evaluate_with(index, strategy))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(union_all(&postings))
+            }
+            Query::Not(_) => Err(EvalError::UnboundedNot),
+            Query::Phrase(_) |
+            Query::Near(..) => {
+                let index = index.positional().ok_or(EvalError::NotPositional)?;
+                Ok(self.evaluate_positions(index)?.into_posting())
+            }
+        }
+    }
+
+    /// Evaluate a term, phrase, or proximity query against a positional
+    /// index.
+    fn evaluate_positions<'a, N>(&self,
+                                 index: &'a dyn PositionalIndex<N>)
+                                 -> Result<PositionalPosting<'a, N>, EvalError>
+        where N: 'static + Clone + Ord
+    {
+        let term_positions = |term: &str| {
+            index.positions(term)
+                .unwrap_or_else(|| positional_posting_from_vecs(Vec::new(), Vec::new()))
+        };
+
+        match *self {
+            Query::Term(ref term) => Ok(term_positions(term)),
+            Query::Phrase(ref terms) => {
+                let mut result = term_positions(&terms[0]);
+                for term in &terms[1..] {
+                    result = result.phrase(&term_positions(term));
+                }
+
+                Ok(result)
+            }
+            Query::Near(ref query1, ref query2, k) => {
+                let positions1 = query1.evaluate_positions(index)?;
+                let positions2 = query2.evaluate_positions(index)?;
+                Ok(positions1.near(&positions2, k))
+            }
+            _ => Err(EvalError::NotPositional),
+        }
+    }
+
+    /// Get the terms of the query, including the terms of phrases.
+    /// Wildcard patterns are not included.
+    pub fn terms(&self) -> Vec<&str> {
+        let mut terms = Vec::new();
+        self.collect_terms(&mut terms, &mut Vec::new());
+        terms
+    }
+
+    /// Get the wildcard patterns of the query.
+    pub fn wildcards(&self) -> Vec<&str> {
+        let mut wildcards = Vec::new();
+        self.collect_terms(&mut Vec::new(), &mut wildcards);
+        wildcards
+    }
+
+    fn collect_terms<'a>(&'a self, terms: &mut Vec<&'a str>, wildcards: &mut Vec<&'a str>) {
+        match *self {
+            Query::Term(ref term) => terms.push(term),
+            Query::Phrase(ref phrase) => terms.extend(phrase.iter().map(String::as_str)),
+            Query::Wildcard(ref pattern) => wildcards.push(pattern),
+            Query::Near(ref query1, ref query2, _) => {
+                query1.collect_terms(terms, wildcards);
+                query2.collect_terms(terms, wildcards);
+            }
+            Query::And(ref queries) |
+            Query::Or(ref queries) => {
+                for query in queries {
+                    query.collect_terms(terms, wildcards);
+                }
+            }
+            Query::Not(ref query) => query.collect_terms(terms, wildcards),
+        }
+    }
+
+    /// Rewrite the terms of a query, including the terms of phrases and
+    /// wildcard patterns, using the function `f`. Terms for which `f`
+    /// returns `None` are removed from the query, which is useful for
+    /// stopwords. Returns `None` if all terms are removed.
+    ///
+    /// This can be used to qualify and normalize terms, see
+    /// `Fields::index_term`.
+    pub fn filter_map_terms<F>(&self, f: &F) -> Option<Query>
+        where F: Fn(&str) -> Option<String>
+    {
+        match *self {
+            Query::Term(ref term) => f(term).map(Query::Term),
+            Query::Phrase(ref terms) => {
+                let mut terms: Vec<_> = terms.iter().filter_map(|term| f(term)).collect();
+                match terms.len() {
+                    0 => None,
+                    1 => terms.pop().map(Query::Term),
+                    _ => Some(Query::Phrase(terms)),
+                }
+            }
+            Query::Wildcard(ref pattern) => f(pattern).map(Query::Wildcard),
+            Query::Near(ref query1, ref query2, k) => {
+                match (query1.filter_map_terms(f), query2.filter_map_terms(f)) {
+                    (Some(query1), Some(query2)) => {
+                        Some(Query::Near(Box::new(query1), Box::new(query2), k))
+                    }
+                    (query1, query2) => query1.or(query2),
+                }
+            }
+            Query::And(ref queries) => filter_map_nary(queries, f, Query::And),
+            Query::Or(ref queries) => filter_map_nary(queries, f, Query::Or),
+            Query::Not(ref query) => {
+                query.filter_map_terms(f).map(|query| Query::Not(Box::new(query)))
+            }
+        }
+    }
+
+    fn is_positional(&self) -> bool {
+        matches!(*self, Query::Term(_) | Query::Phrase(_) | Query::Near(..))
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Query::Term(ref term) => write!(f, "\"{}\"", term),
+            Query::Phrase(ref terms) => write!(f, "\"{}\"", terms.join(" ")),
+            Query::Wildcard(ref pattern) => write!(f, "{}", pattern),
+            Query::Near(ref query1, ref query2, k) => {
+                write!(f, "({} NEAR/{} {})", query1, k, query2)
+            }
+            Query::And(ref queries) => write_nary(f, "AND", queries),
+            Query::Or(ref queries) => write_nary(f, "OR", queries),
+            Query::Not(ref query) => write!(f, "NOT {}", query),
+        }
+    }
+}
+
+fn write_nary(f: &mut fmt::Formatter, op: &str, queries: &[Query]) -> fmt::Result {
+    write!(f, "(")?;
+
+    for (idx, query) in queries.iter().enumerate() {
+        if idx != 0 {
+            write!(f, " {} ", op)?;
+        }
+
+        write!(f, "{}", query)?;
+    }
+
+    write!(f, ")")
+}
+
+/// Evaluate a conjunction. Negated subqueries are subtracted from the
+/// intersection of the positive subqueries.
+fn evaluate_and<'a, I, N>(queries: &[Query],
+                          index: &'a I,
+                          strategy: Option<IntersectStrategy>)
+                          -> Result<Posting<'a, N>, EvalError>
+    where I: InvertedIndex<N> + ?Sized,
+          N: 'static + Clone + Ord
+{
+    let mut positive = Vec::new();
+    let mut negative = Vec::new();
+    let mut terms = Vec::new();
+
+    for query in queries {
+        match *query {
+            Query::Not(ref query) => negative.push(query.evaluate_with(index, strategy)?),
+            Query::Term(ref term) if strategy.is_none() => terms.push(term.as_str()),
+            ref query => positive.push(query.evaluate_with(index, strategy)?),
+        }
+    }
+
+    // Let the index intersect the terms if it can do so without retrieving
+    // their postings lists, e.g. compressed lists.
+    let intersection = if terms.len() > 1 {
+        index.intersect_terms(&terms)
+    } else {
+        None
+    };
+
+    match intersection {
+        Some(posting) => positive.push(posting),
+        None => {
+            for term in terms {
+                positive.push(index.posting(term).unwrap_or_else(|| posting_from_vec(Vec::new())))
+            }
+        }
+    }
+
+    let mut result = match (positive.len(), strategy) {
+        (0, _) => return Err(EvalError::UnboundedNot),
+        (1, _) => positive.pop().unwrap(),
+        (_, None) => intersect_all(&positive),
+        (_, Some(strategy)) => {
+            positive.sort_by_key(Posting::len);
+            let mut iter = positive.into_iter();
+            let first = iter.next().unwrap();
+            iter.fold(first, |result, posting| result.intersect_with(&posting, strategy))
+        }
+    };
+
+    for posting in negative {
+        result = result.difference(&posting);
+    }
+
+    Ok(result)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    And,
+    Filter(Filter),
+    LParen,
+    Near(u32),
+    Not,
+    Or,
+    Phrase(Vec<String>),
+    RParen,
+    Term(String),
+    Wildcard(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::And => write!(f, "AND"),
+            Token::Filter(ref filter) => write!(f, "{}", filter),
+            Token::LParen => write!(f, "("),
+            Token::Near(k) => write!(f, "NEAR/{}", k),
+            Token::Not => write!(f, "NOT"),
+            Token::Or => write!(f, "OR"),
+            Token::Phrase(ref terms) => write!(f, "\"{}\"", terms.join(" ")),
+            Token::RParen => write!(f, ")"),
+            Token::Term(ref term) | Token::Wildcard(ref term) => write!(f, "{}", term),
+        }
+    }
+}
+
+/// Split a query into tokens. If `filters` is `true`, unquoted terms that
+/// parse as a metadata filter are filters.
+fn tokenize(query: &str, filters: bool) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::LParen);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::RParen);
+        } else if c == '"' {
+            chars.next();
+            let quoted = quoted_term(query, &mut chars)?;
+            let mut terms: Vec<_> = quoted.split_whitespace().map(ToOwned::to_owned).collect();
+            tokens.push(match terms.len() {
+                0 => return Err(QueryError::UnexpectedToken("\"\"".to_owned())),
+                1 => Token::Term(terms.pop().unwrap()),
+                _ => Token::Phrase(terms),
+            });
+        } else {
+            let end = bare_term_end(query, &mut chars);
+            tokens.push(match &query[start..end] {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                op if op.starts_with("NEAR/") => {
+                    Token::Near(op["NEAR/".len()..]
+                        .parse()
+                        .map_err(|_| QueryError::InvalidNear(op.to_owned()))?)
+                }
+                term if filters && term.parse::<Filter>().is_ok() => {
+                    Token::Filter(term.parse().unwrap())
+                }
+                term if term.contains('*') => Token::Wildcard(term.to_owned()),
+                term => Token::Term(term.to_owned()),
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Read a quoted term, the opening quote should already be consumed.
+fn quoted_term(query: &str, chars: &mut Peekable<CharIndices>) -> Result<String, QueryError> {
+    let start = match chars.peek() {
+        Some(&(idx, _)) => idx,
+        None => return Err(QueryError::UnclosedQuote),
+    };
+
+    for (idx, c) in chars {
+        if c == '"' {
+            return Ok(query[start..idx].to_owned());
+        }
+    }
+
+    Err(QueryError::UnclosedQuote)
+}
+
+/// Find the end of an unquoted term.
+fn bare_term_end(query: &str, chars: &mut Peekable<CharIndices>) -> usize {
+    while let Some(&(idx, c)) = chars.peek() {
+        if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+            return idx;
+        }
+
+        chars.next();
+    }
+
+    query.len()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+
+    /// Nesting depth of parentheses.
+    depth: usize,
+
+    /// Filters that are top-level conjuncts.
+    filters: Vec<Filter>,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let n_filters = self.filters.len();
+        let mut queries = vec![self.parse_and()?];
+
+        while let Some(&Token::Or) = self.peek() {
+            self.next();
+            queries.push(self.parse_and()?);
+        }
+
+        // Filters of an operand of a disjunction are not top-level
+        // conjuncts.
+        if queries.len() > 1 && self.filters.len() > n_filters {
+            return Err(QueryError::MisplacedFilter(self.filters[n_filters].to_string()));
+        }
+
+        let queries = queries.into_iter().collect::<Option<Vec<_>>>().ok_or(QueryError::Empty)?;
+
+        Ok(nary(queries, Query::Or))
+    }
+
+    /// Parse a conjunction. Returns `None` if the conjunction only consists
+    /// of filters.
+    fn parse_and(&mut self) -> Result<Option<Query>, QueryError> {
+        let mut queries = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(Token::Filter(filter)) if self.depth == 0 => {
+                    let filter = filter.clone();
+                    self.filters.push(filter);
+                    self.next();
+                }
+                _ => queries.push(self.parse_not()?),
+            }
+
+            match self.peek() {
+                Some(&Token::And) => {
+                    self.next();
+                }
+                Some(&Token::Filter(_)) |
+                Some(&Token::Not) |
+                Some(&Token::LParen) |
+                Some(&Token::Phrase(_)) |
+                Some(&Token::Term(_)) |
+                Some(&Token::Wildcard(_)) => (),
+                _ => break,
+            }
+        }
+
+        if queries.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(nary(queries, Query::And)))
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<Query, QueryError> {
+        if let Some(&Token::Not) = self.peek() {
+            self.next();
+            return Ok(Query::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_near()
+    }
+
+    fn parse_near(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.parse_primary()?;
+
+        while let Some(&Token::Near(k)) = self.peek() {
+            self.next();
+            let right = self.parse_primary()?;
+
+            for operand in &[&query, &right] {
+                if !operand.is_positional() {
+                    return Err(QueryError::InvalidNearOperand(operand.to_string()));
+                }
+            }
+
+            query = Query::Near(Box::new(query), Box::new(right), k);
+        }
+
+        Ok(query)
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, QueryError> {
+        match self.next() {
+            Some(Token::Term(term)) => Ok(Query::Term(term)),
+            Some(Token::Phrase(terms)) => Ok(Query::Phrase(terms)),
+            Some(Token::Wildcard(pattern)) => Ok(Query::Wildcard(pattern)),
+            Some(Token::LParen) => {
+                self.depth += 1;
+                let query = self.parse_or()?;
+                self.depth -= 1;
+                match self.next() {
+                    Some(Token::RParen) => Ok(query),
+                    Some(token) => Err(QueryError::UnexpectedToken(token.to_string())),
+                    None => Err(QueryError::UnclosedParen),
+                }
+            }
+            Some(Token::Filter(filter)) => Err(QueryError::MisplacedFilter(filter.to_string())),
+            Some(token) => Err(QueryError::UnexpectedToken(token.to_string())),
+            None => Err(QueryError::UnexpectedEnd),
+        }
+    }
+}
+
+fn filter_map_nary<F, G>(queries: &[Query], f: &F, g: G) -> Option<Query>
+    where F: Fn(&str) -> Option<String>,
+          G: Fn(Vec<Query>) -> Query
+{
+    let queries: Vec<_> = queries.iter().filter_map(|query| query.filter_map_terms(f)).collect();
+    if queries.is_empty() {
+        None
+    } else {
+        Some(nary(queries, g))
+    }
+}
+
+/// Construct an n-ary query, unless there is only one subquery.
+fn nary<F>(mut queries: Vec<Query>, f: F) -> Query
+    where F: Fn(Vec<Query>) -> Query
+{
+    if queries.len() == 1 {
+        queries.pop().unwrap()
+    } else {
+        f(queries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+
+    use conllx::TokenBuilder;
+
+    use super::{Query, QueryError};
+    use super::Query::*;
+    use super::super::{Fields, IntersectStrategy, InvertedIndexMut, MemoryIndex, Normalizer,
+                       Pipeline, PositionalIndexMut, PositionalMemoryIndex, Stopwords};
+    use super::super::tempfile::TempDir;
+
+    fn term(t: &str) -> Query {
+        Term(t.to_owned())
+    }
+
+    #[test]
+    fn parse_implicit_and() {
+        assert_eq!(Query::parse("new york city").unwrap(),
+                   And(vec![term("new"), term("york"), term("city")]));
+    }
+
+    #[test]
+    fn parse_precedence() {
+        assert_eq!(Query::parse("a OR b AND NOT c").unwrap(),
+                   Or(vec![term("a"), And(vec![term("b"), Not(Box::new(term("c")))])]));
+    }
+
+    #[test]
+    fn parse_parentheses() {
+        assert_eq!(Query::parse("(a OR b) c").unwrap(),
+                   And(vec![Or(vec![term("a"), term("b")]), term("c")]));
+    }
+
+    #[test]
+    fn parse_quoted() {
+        assert_eq!(Query::parse("\"AND\" \"(\"").unwrap(),
+                   And(vec![term("AND"), term("(")]));
+    }
+
+    #[test]
+    fn parse_wildcard() {
+        assert_eq!(Query::parse("comput* \"a*\" NOT *ization").unwrap(),
+                   And(vec![Wildcard("comput*".to_owned()),
+                            term("a*"),
+                            Not(Box::new(Wildcard("*ization".to_owned())))]));
+    }
+
+    #[test]
+    fn parse_filters() {
+        let (query, filters) = Query::parse_with_filters("new year>2000 AND york lang=en")
+            .unwrap();
+        assert_eq!(query, And(vec![term("new"), term("york")]));
+        assert_eq!(filters.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                   vec!["year>2000", "lang=en"]);
+
+        let (query, filters) = Query::parse_with_filters("\"year>2000\" (a OR b)").unwrap();
+        assert_eq!(query, And(vec![term("year>2000"), Or(vec![term("a"), term("b")])]));
+        assert!(filters.is_empty());
+
+        for query in &["city OR year>2000",
+                       "year>2000 city OR york",
+                       "city NOT lang=en york",
+                       "(city year>2000)",
+                       "city NEAR/2 year>2000"] {
+            assert!(matches!(Query::parse_with_filters(query),
+                             Err(QueryError::MisplacedFilter(_))),
+                    "{}",
+                    query);
+        }
+
+        assert_eq!(Query::parse_with_filters("year>2000"), Err(QueryError::Empty));
+        assert_eq!(Query::parse("year>2000").unwrap(), term("year>2000"));
+    }
+
+    #[test]
+    fn filter_map_terms_fields() {
+        let fields = "lemma,pos".parse::<Fields>().unwrap();
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Stopwords::from_buf_read(Cursor::new("the\nof")).unwrap());
+        let index_term = |term: &str| fields.index_term(term, &pipeline);
+
+        let query = Query::parse("run AND pos:VB NOT \"lemma:a the b\"").unwrap();
+        assert_eq!(query.filter_map_terms(&index_term),
+                   Some(And(vec![term("lemma:run"),
+                                 term("pos:VB"),
+                                 Not(Box::new(Phrase(vec!["lemma:a".to_owned(),
+                                                          "lemma:b".to_owned()])))])));
+
+        let query = Query::parse("(the OR of) a NEAR/2 the").unwrap();
+        assert_eq!(query.filter_map_terms(&index_term), Some(term("lemma:a")));
+
+        let query = Query::parse("the \"of the\"").unwrap();
+        assert_eq!(query.filter_map_terms(&index_term), None);
+    }
+
+    #[test]
+    fn parse_phrase_near() {
+        assert_eq!(Query::parse("\"new york\" NEAR/3 city OR boston").unwrap(),
+                   Or(vec![Near(Box::new(Phrase(vec!["new".to_owned(), "york".to_owned()])),
+                                Box::new(term("city")),
+                                3),
+                           term("boston")]));
+    }
+
+    #[test]
+    fn query_terms() {
+        let query = Query::parse("(a OR b*) \"c d\" NEAR/2 e NOT f").unwrap();
+        assert_eq!(query.terms(), vec!["a", "c", "d", "e", "f"]);
+        assert_eq!(query.wildcards(), vec!["b*"]);
+    }
+
+    #[test]
+    fn evaluate_strategies() {
+        let mut index = MemoryIndex::new();
+        index.add_postings_list("a", vec![1u64, 2, 3, 5, 8, 13]);
+        index.add_postings_list("b", vec![2u64, 3, 5, 7]);
+        index.add_postings_list("c", vec![1u64, 3, 5, 7, 9]);
+        index.add_postings_list("d", vec![5u64]);
+
+        let query = Query::parse("a b c NOT d").unwrap();
+        assert_eq!(query.evaluate(&index).unwrap().as_slice(), &[3]);
+
+        for strategy in &["adaptive", "linear", "binsearch", "galloping", "skip", "skip=2"] {
+            let strategy: IntersectStrategy = strategy.parse().unwrap();
+            let result = query.evaluate_with(&index, Some(strategy)).unwrap();
+            assert_eq!(result.as_slice(), &[3]);
+        }
+
+        assert!("quadratic".parse::<IntersectStrategy>().is_err());
+    }
+
+    #[test]
+    fn evaluate_tag_stopwords() {
+        let dir = TempDir::create(env::temp_dir()).unwrap();
+        let path = dir.path().join("stopwords");
+        let spec = format!("lowercase,ptb-stopwords={}", path.display());
+        let fields: Fields = "form".parse().unwrap();
+
+        let docs = [vec![("The", "DT"), ("cat", "NN"), ("sat", "VBD")],
+                    vec![("Bank", "NNP"), ("of", "IN"), ("America", "NNP")],
+                    vec![("a", "DT"), ("round", "NN"), ("table", "NN")],
+                    vec![("round", "IN"), ("the", "DT"), ("corner", "NN")]];
+
+        // Index like create-index, stopwords do not take a position.
+        let pipeline = Pipeline::from_indexing_spec(&spec).unwrap();
+        let mut index = PositionalMemoryIndex::new();
+        for (doc, tokens) in docs.iter().enumerate() {
+            let mut position = 0;
+            for &(form, tag) in tokens {
+                let token = TokenBuilder::new().form(form).pos(tag).token();
+                if let Some(terms) = fields.terms(&token, &pipeline) {
+                    for term in &terms {
+                        index.add_term_position(term, doc as u64, position);
+                    }
+                    position += 1;
+                }
+            }
+        }
+        pipeline.save().unwrap();
+
+        let pipeline = Pipeline::from_spec(&spec).unwrap();
+        let index_term = |term: &str| fields.index_term(term, &pipeline);
+        let evaluate = |query: &str| {
+            let query = Query::parse(query).unwrap().filter_map_terms(&index_term).unwrap();
+            query.evaluate(&index).unwrap().as_slice().to_vec()
+        };
+
+        assert_eq!(evaluate("the AND cat"), vec![0]);
+        assert_eq!(evaluate("\"Bank of America\""), vec![1]);
+        assert_eq!(evaluate("round"), vec![2]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Query::parse("  "), Err(QueryError::Empty));
+        assert_eq!(Query::parse("(a OR b"), Err(QueryError::UnclosedParen));
+        assert_eq!(Query::parse("a)"),
+                   Err(QueryError::UnexpectedToken(")".to_owned())));
+        assert_eq!(Query::parse("a AND"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("\"a"), Err(QueryError::UnclosedQuote));
+        assert_eq!(Query::parse("a NEAR/x b"),
+                   Err(QueryError::InvalidNear("NEAR/x".to_owned())));
+        assert_eq!(Query::parse("a NEAR/2 (b OR c)"),
+                   Err(QueryError::InvalidNearOperand("(\"b\" OR \"c\")".to_owned())));
+    }
+}
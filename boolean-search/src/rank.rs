@@ -0,0 +1,291 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::HashMap;
+use std::hash::Hash;
+
+use binary_heap::BinaryHeap;
+
+use super::{DocLengths, FrequencyIndex};
+
+/// A scorer assigns scores to the documents that contain at least one
+/// term of a (bag-of-words) query.
+pub trait Scorer<N> {
+    /// Score the documents for the given query terms. A term that occurs
+    /// more than once in the query contributes to the score once for every
+    /// occurrence.
+    fn score(&self, index: &dyn FrequencyIndex<N>, terms: &[&str]) -> HashMap<N, f64>;
+}
+
+/// Okapi BM25 scoring.
+pub struct Bm25<'a, N: 'a> {
+    lengths: &'a DocLengths<N>,
+    k1: f64,
+    b: f64,
+}
+
+impl<'a, N> Bm25<'a, N>
+    where N: Eq + Hash
+{
+    /// Construct a BM25 scorer. `k1` controls saturation of the term
+    /// frequency, `b` the strength of document length normalization.
+    /// Common values are `k1 = 1.2` and `b = 0.75`.
+    pub fn new(lengths: &'a DocLengths<N>, k1: f64, b: f64) -> Self {
+        Bm25 { lengths, k1, b }
+    }
+}
+
+impl<'a, N> Scorer<N> for Bm25<'a, N>
+    where N: Clone + Eq + Hash + Ord
+{
+    fn score(&self, index: &dyn FrequencyIndex<N>, terms: &[&str]) -> HashMap<N, f64> {
+        let mut scores = HashMap::new();
+
+        let n_docs = self.lengths.len() as f64;
+        let avg_len = self.lengths.avg_len();
+
+        for term in terms {
+            let posting = match index.term_frequencies(term) {
+                Some(posting) => posting,
+                None => continue,
+            };
+
+            let df = posting.len() as f64;
+            let idf = ((n_docs - df + 0.5) / (df + 0.5) + 1.).ln();
+
+            for (doc, tf) in posting.iter() {
+                let tf = tf as f64;
+
+                // Documents without a known length are treated as having
+                // the average length. Without any document lengths, the
+                // length is not normalized.
+                let len = self.lengths.get(doc).map(|len| len as f64).unwrap_or(avg_len);
+                let norm = if avg_len > 0. {
+                    1. - self.b + self.b * len / avg_len
+                } else {
+                    1.
+                };
+
+                *scores.entry(doc.clone()).or_insert(0.) += idf * tf * (self.k1 + 1.) /
+                                                              (tf + self.k1 * norm);
+            }
+        }
+
+        scores
+    }
+}
+
+/// TF-IDF weighting with cosine similarity. Document vectors are weighted
+/// using *tf * log(N / df)*, query vectors using the same idf.
+pub struct TfIdf<N> {
+    n_docs: usize,
+    norms: HashMap<N, f64>,
+}
+
+impl<N> TfIdf<N>
+    where N: Clone + Eq + Hash + Ord
+{
+    /// Construct a TF-IDF scorer. This precomputes the norms of all
+    /// document vectors in the index.
+    pub fn new(index: &dyn FrequencyIndex<N>) -> Self {
+        let mut norms = HashMap::new();
+
+        for (_, posting) in index.iter() {
+            for doc in posting.iter() {
+                norms.insert(doc.clone(), 0.);
+            }
+        }
+
+        let n_docs = norms.len();
+
+        for (term, _) in index.iter() {
+            let posting = index.term_frequencies(term)
+                .expect("Term from the index without postings list");
+            let idf = idf(n_docs, posting.len());
+
+            for (doc, tf) in posting.iter() {
+                let weight = tf as f64 * idf;
+                *norms.get_mut(doc).unwrap() += weight * weight;
+            }
+        }
+
+        for norm in norms.values_mut() {
+            *norm = norm.sqrt();
+        }
+
+        TfIdf { n_docs, norms }
+    }
+}
+
+impl<N> Scorer<N> for TfIdf<N>
+    where N: Clone + Eq + Hash + Ord
+{
+    fn score(&self, index: &dyn FrequencyIndex<N>, terms: &[&str]) -> HashMap<N, f64> {
+        let mut query_tfs: HashMap<&str, usize> = HashMap::new();
+        for term in terms {
+            *query_tfs.entry(term).or_insert(0) += 1;
+        }
+
+        let mut scores = HashMap::new();
+        let mut query_norm = 0.;
+
+        for (term, query_tf) in query_tfs {
+            let posting = match index.term_frequencies(term) {
+                Some(posting) => posting,
+                None => continue,
+            };
+
+            let idf = idf(self.n_docs, posting.len());
+            let query_weight = query_tf as f64 * idf;
+            query_norm += query_weight * query_weight;
+
+            for (doc, tf) in posting.iter() {
+                *scores.entry(doc.clone()).or_insert(0.) += tf as f64 * idf * query_weight;
+            }
+        }
+
+        let query_norm = query_norm.sqrt();
+
+        for (doc, score) in &mut scores {
+            let doc_norm = self.norms.get(doc).cloned().unwrap_or(0.);
+            if doc_norm == 0. || query_norm == 0. {
+                *score = 0.;
+            } else {
+                *score /= doc_norm * query_norm;
+            }
+        }
+
+        scores
+    }
+}
+
+fn idf(n_docs: usize, df: usize) -> f64 {
+    (n_docs as f64 / df as f64).ln()
+}
+
+/// Select the `k` highest-scoring documents. The documents are returned
+/// in order of decreasing score, ties are broken by document identifier.
+pub fn top_k<N>(scores: HashMap<N, f64>, k: usize) -> Vec<(N, f64)>
+    where N: Ord
+{
+    // The heap contains the best k documents seen so far. Since the
+    // ordering of ScoredDoc is reversed, the worst of these documents is
+    // at the top of the heap.
+    let mut heap = BinaryHeap::new();
+    let mut heap_len = 0;
+
+    for (doc, score) in scores {
+        let scored = ScoredDoc { doc, score };
+
+        if heap_len < k {
+            heap.insert(scored);
+            heap_len += 1;
+        } else if let Some(mut worst) = heap.peek_mut() {
+            if scored < *worst {
+                *worst = scored;
+            }
+        }
+    }
+
+    heap.into_sorted_vec().into_iter().map(|scored| (scored.doc, scored.score)).collect()
+}
+
+/// A document with its score. Documents are ordered from best to worst,
+/// i.e. a document is smaller when it has a higher score.
+struct ScoredDoc<N> {
+    doc: N,
+    score: f64,
+}
+
+impl<N> PartialEq for ScoredDoc<N>
+    where N: Ord
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<N> Eq for ScoredDoc<N> where N: Ord {}
+
+impl<N> PartialOrd for ScoredDoc<N>
+    where N: Ord
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N> Ord for ScoredDoc<N>
+    where N: Ord
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score
+            .partial_cmp(&self.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| self.doc.cmp(&other.doc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Bm25, Scorer, TfIdf, top_k};
+    use super::super::{DocLengths, FrequencyMemoryIndex, InvertedIndexMut};
+
+    fn test_index() -> (FrequencyMemoryIndex<u32>, DocLengths<u32>) {
+        let mut index = FrequencyMemoryIndex::new();
+        let mut lengths = DocLengths::new();
+
+        for (doc, text) in vec!["a b c", "a a a b", "c d", "a d d d e f g h"]
+            .into_iter()
+            .enumerate() {
+            for term in text.split_whitespace() {
+                index.add_term(term, doc as u32);
+                lengths.add(doc as u32, 1);
+            }
+        }
+
+        (index, lengths)
+    }
+
+    #[test]
+    fn top_k_selects_best() {
+        let scores: HashMap<u32, f64> =
+            vec![(1, 0.5), (2, 2.0), (3, 1.0), (4, 2.0), (5, 0.1)].into_iter().collect();
+
+        assert_eq!(top_k(scores.clone(), 3), vec![(2, 2.0), (4, 2.0), (3, 1.0)]);
+        assert_eq!(top_k(scores.clone(), 0), vec![]);
+        assert_eq!(top_k(scores, 10).len(), 5);
+    }
+
+    #[test]
+    fn bm25_ranking() {
+        let (index, lengths) = test_index();
+        let bm25 = Bm25::new(&lengths, 1.2, 0.75);
+
+        let ranked: Vec<_> = top_k(bm25.score(&index, &["a"]), 10)
+            .into_iter()
+            .map(|(doc, _)| doc)
+            .collect();
+        assert_eq!(ranked, vec![1, 0, 3]);
+
+        let ranked: Vec<_> = top_k(bm25.score(&index, &["d", "b"]), 1)
+            .into_iter()
+            .map(|(doc, _)| doc)
+            .collect();
+        assert_eq!(ranked, vec![3]);
+
+        let no_lengths = DocLengths::new();
+        let bm25 = Bm25::new(&no_lengths, 1.2, 0.75);
+        assert!(bm25.score(&index, &["a"]).values().all(|score| score.is_finite()));
+    }
+
+    #[test]
+    fn tfidf_identical_document() {
+        let (index, _) = test_index();
+        let tfidf = TfIdf::new(&index);
+
+        let ranked = top_k(tfidf.score(&index, &["c", "d"]), 10);
+        assert_eq!(ranked[0].0, 2);
+        assert!((ranked[0].1 - 1.0).abs() < 1e-9);
+    }
+}
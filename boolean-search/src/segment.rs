@@ -0,0 +1,379 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use super::{BinaryReadError, InvertedIndex, InvertedIndexFromBinary, InvertedIndexMut,
+            InvertedIndexToBinary, MemoryIndex, Posting, union_all};
+use super::index::posting_from_ref;
+
+/// The file that lists the segments of an index, one per line.
+const MANIFEST: &str = "segments";
+
+/// The file that lists deleted documents, one per line.
+const TOMBSTONES: &str = "deleted";
+
+/// Errors for reading and writing segmented indexes.
+#[derive(Debug)]
+pub enum SegmentError {
+    Io(io::Error),
+    Binary(String, BinaryReadError),
+    InvalidDocument(String),
+}
+
+impl From<io::Error> for SegmentError {
+    fn from(err: io::Error) -> SegmentError {
+        SegmentError::Io(err)
+    }
+}
+
+impl fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SegmentError::Io(ref err) => write!(f, "{}", err),
+            SegmentError::Binary(ref segment, ref err) => {
+                write!(f, "Cannot read segment {}: {}", segment, err)
+            }
+            SegmentError::InvalidDocument(ref doc) => {
+                write!(f, "Invalid deleted document identifier: {}", doc)
+            }
+        }
+    }
+}
+
+/// An inverted index that consists of multiple segments, stored in a
+/// directory. New documents are added as a new segment. Deleted documents
+/// are recorded in a tombstone set and are filtered from query results,
+/// until the segments are merged.
+///
+/// The directory contains the segments in the binary index format, the
+/// file `segments` that lists the segments, and the file `deleted` that
+/// lists the deleted documents.
+pub struct SegmentedIndex<N> {
+    dir: PathBuf,
+    segments: Vec<(String, MemoryIndex<N>)>,
+    deleted: Vec<N>,
+    next_segment: usize,
+}
+
+impl<N> SegmentedIndex<N>
+    where N: 'static + Clone + FromPrimitive + FromStr + Ord + ToPrimitive + fmt::Display
+{
+    /// Create an empty segmented index in a new directory.
+    pub fn create<P>(dir: P) -> Result<Self, SegmentError>
+        where P: AsRef<Path>
+    {
+        fs::create_dir(dir.as_ref())?;
+
+        let index = SegmentedIndex {
+            dir: dir.as_ref().to_owned(),
+            segments: Vec::new(),
+            deleted: Vec::new(),
+            next_segment: 0,
+        };
+
+        index.write_manifest()?;
+        index.write_tombstones()?;
+
+        Ok(index)
+    }
+
+    /// Open a segmented index.
+    pub fn open<P>(dir: P) -> Result<Self, SegmentError>
+        where P: AsRef<Path>
+    {
+        let dir = dir.as_ref();
+
+        let mut segments = Vec::new();
+        for name in read_lines(&dir.join(MANIFEST))? {
+            let reader = BufReader::new(File::open(dir.join(&name))?);
+            let segment = MemoryIndex::from_binary(reader)
+                .map_err(|err| SegmentError::Binary(name.clone(), err))?;
+            segments.push((name, segment));
+        }
+
+        let mut deleted = BTreeSet::new();
+        for doc in read_lines(&dir.join(TOMBSTONES))? {
+            deleted.insert(doc.parse().map_err(|_| SegmentError::InvalidDocument(doc))?);
+        }
+
+        let next_segment = segments.iter()
+            .filter_map(|(name, _)| segment_number(name))
+            .max()
+            .map(|n| n + 1)
+            .unwrap_or(0);
+
+        Ok(SegmentedIndex {
+            dir: dir.to_owned(),
+            segments,
+            deleted: deleted.into_iter().collect(),
+            next_segment,
+        })
+    }
+
+    /// Add an index as a new segment. The documents in the segment should
+    /// not occur in other segments, unless they were deleted. Since the new
+    /// segment may reuse identifiers of deleted documents, deleted
+    /// documents are merged into the existing segments first if needed.
+    pub fn add_segment(&mut self, segment: MemoryIndex<N>) -> Result<(), SegmentError> {
+        let reuses_deleted = !self.deleted.is_empty() &&
+                             segment.iter().any(|(_, posting)| {
+            !posting.intersect(&posting_from_ref(&self.deleted)).is_empty()
+        });
+        if reuses_deleted {
+            self.merge()?;
+        }
+
+        let name = self.write_segment(&segment)?;
+        self.segments.push((name, segment));
+        self.write_manifest()
+    }
+
+    /// Mark documents as deleted.
+    pub fn delete<I>(&mut self, docs: I) -> Result<(), SegmentError>
+        where I: IntoIterator<Item = N>
+    {
+        let mut deleted: BTreeSet<N> = self.deleted.drain(..).collect();
+        deleted.extend(docs);
+        self.deleted = deleted.into_iter().collect();
+
+        self.write_tombstones()
+    }
+
+    /// Get the deleted documents, in ascending order.
+    pub fn deleted(&self) -> &[N] {
+        &self.deleted
+    }
+
+    /// Merge all segments into a single segment, dropping deleted
+    /// documents.
+    pub fn merge(&mut self) -> Result<(), SegmentError> {
+        let mut merged = MemoryIndex::new();
+        for (term, posting) in self.iter() {
+            if !posting.is_empty() {
+                merged.add_postings_list(term, posting.as_slice().to_vec());
+            }
+        }
+
+        let name = self.write_segment(&merged)?;
+        let old_segments = ::std::mem::replace(&mut self.segments, vec![(name, merged)]);
+        self.deleted.clear();
+
+        // Write the manifest before removing old segments, so that the
+        // index is consistent if the merge is interrupted.
+        self.write_manifest()?;
+        self.write_tombstones()?;
+
+        for (name, _) in old_segments {
+            fs::remove_file(self.dir.join(name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of segments.
+    pub fn segments_len(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn write_segment(&mut self, segment: &MemoryIndex<N>) -> Result<String, SegmentError> {
+        let name = format!("segment-{}.bin", self.next_segment);
+        self.next_segment += 1;
+
+        write_atomic(&self.dir.join(&name), |writer| segment.to_binary(writer))?;
+
+        Ok(name)
+    }
+
+    fn write_manifest(&self) -> Result<(), SegmentError> {
+        write_atomic(&self.dir.join(MANIFEST), |writer| {
+            for (name, _) in &self.segments {
+                writeln!(writer, "{}", name)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn write_tombstones(&self) -> Result<(), SegmentError> {
+        write_atomic(&self.dir.join(TOMBSTONES), |writer| {
+            for doc in &self.deleted {
+                writeln!(writer, "{}", doc)?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl<N> InvertedIndex<N> for SegmentedIndex<N>
+    where N: 'static + Clone + Ord
+{
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    fn range<'a>(&'a self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'a str, Posting<'a, N>)> + 'a> {
+        // Terms of which all documents are deleted are skipped.
+        Box::new(segment_terms(&self.segments, from, to)
+            .into_iter()
+            .filter_map(move |term| self.posting(term).map(|posting| (term, posting))))
+    }
+
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
+        let postings: Vec<_> = self.segments
+            .iter()
+            .filter_map(|(_, segment)| segment.posting(term))
+            .collect();
+
+        if postings.is_empty() {
+            return None;
+        }
+
+        let posting = if postings.len() == 1 {
+            postings.into_iter().next().unwrap()
+        } else {
+            union_all(&postings)
+        };
+
+        if self.deleted.is_empty() {
+            Some(posting)
+        } else {
+            let posting = posting.difference(&posting_from_ref(&self.deleted));
+            if posting.is_empty() {
+                None
+            } else {
+                Some(posting)
+            }
+        }
+    }
+
+    fn doc_freq(&self, term: &str) -> usize {
+        // Segments do not share documents, but deleted documents can only
+        // be excluded by retrieving the postings lists.
+        if self.deleted.is_empty() {
+            self.segments.iter().map(|(_, segment)| segment.doc_freq(term)).sum()
+        } else {
+            self.posting(term).map(|posting| posting.len()).unwrap_or(0)
+        }
+    }
+
+    /// Get the number of terms. The term dictionaries of the segments are
+    /// merged for every call, and with deleted documents, the postings
+    /// lists of all terms are also retrieved. So, this method is expensive
+    /// for large indexes.
+    fn len(&self) -> usize {
+        if self.deleted.is_empty() {
+            segment_terms(&self.segments, Bound::Unbounded, Bound::Unbounded).len()
+        } else {
+            self.iter().count()
+        }
+    }
+}
+
+/// Get the terms of all segments within a range.
+fn segment_terms<'a, N>(segments: &'a [(String, MemoryIndex<N>)],
+                        from: Bound<&str>,
+                        to: Bound<&str>)
+                        -> BTreeSet<&'a str>
+    where N: Clone + Ord
+{
+    segments.iter()
+        .flat_map(|(_, segment)| segment.range(from, to).map(|(term, _)| term))
+        .collect()
+}
+
+fn read_lines(path: &Path) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line.trim().to_owned());
+        }
+    }
+
+    Ok(lines)
+}
+
+fn segment_number(name: &str) -> Option<usize> {
+    name.trim_start_matches("segment-").trim_end_matches(".bin").parse().ok()
+}
+
+/// Write a file by writing to a temporary file first and then renaming
+/// it, so that readers never see a partially written file.
+fn write_atomic<F>(path: &Path, write: F) -> Result<(), SegmentError>
+    where F: FnOnce(&mut BufWriter<File>) -> io::Result<()>
+{
+    let tmp_path = path.with_extension("tmp");
+
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        write(&mut writer)?;
+        writer.flush()?;
+    }
+
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::super::{InvertedIndex, InvertedIndexMut, MemoryIndex};
+    use super::super::tempfile::TempDir;
+    use super::SegmentedIndex;
+
+    fn segment(docs: &[(&str, u64)]) -> MemoryIndex<u64> {
+        let mut index = MemoryIndex::new();
+        for &(term, doc) in docs {
+            index.add_term(term, doc);
+        }
+        index
+    }
+
+    #[test]
+    fn segments_delete_merge() {
+        let tmp = TempDir::create(env::temp_dir()).unwrap();
+        let dir = tmp.path().join("segments");
+
+        let mut index = SegmentedIndex::create(&dir).unwrap();
+        index.add_segment(segment(&[("new", 1), ("york", 1), ("new", 2)])).unwrap();
+        index.add_segment(segment(&[("york", 3), ("city", 4)])).unwrap();
+        index.delete(vec![2]).unwrap();
+
+        let index: SegmentedIndex<u64> = SegmentedIndex::open(&dir).unwrap();
+        assert_eq!(index.segments_len(), 2);
+        assert_eq!(index.posting("new").unwrap().as_slice(), &[1]);
+        assert_eq!(index.posting("york").unwrap().as_slice(), &[1, 3]);
+        assert_eq!(index.doc_freq("new"), 1);
+        assert_eq!(index.len(), 3);
+
+        let mut index = index;
+        index.delete(vec![4]).unwrap();
+        assert!(index.posting("city").is_none());
+        assert_eq!(index.doc_freq("city"), 0);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.iter().count(), 2);
+        index.merge().unwrap();
+
+        let index: SegmentedIndex<u64> = SegmentedIndex::open(&dir).unwrap();
+        assert_eq!(index.segments_len(), 1);
+        assert!(index.deleted().is_empty());
+        assert_eq!(index.posting("york").unwrap().as_slice(), &[1, 3]);
+        assert!(index.posting("city").is_none());
+        assert_eq!(index.doc_freq("york"), 2);
+    }
+}
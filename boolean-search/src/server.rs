@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use super::{Explain, Filter, HttpError, IntersectStrategy, InvertedIndex, Metadata, Query,
+            QueryPlan, Request, Scorer, result_json, split_filters, top_k, write_json_response};
+
+/// The maximum length of a request line and its headers, in bytes.
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// The time after which a client that does not send its request is
+/// disconnected. Requests are handled one at a time, so a slow client
+/// blocks the other clients for up to this time.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of terms that a wildcard of a Boolean query can
+/// match. The postings lists of all matching terms are merged, so broad
+/// patterns such as `*` are rejected.
+pub const MAX_WILDCARD_TERMS: usize = 1000;
+
+/// Answers search requests over HTTP with JSON responses, using an index
+/// and document metadata that are loaded once. See `search-server` for
+/// the endpoints.
+pub struct Server<'a> {
+    index: &'a dyn InvertedIndex<u64>,
+    metadata: &'a dyn Metadata,
+    index_term: &'a dyn Fn(&str) -> Option<String>,
+    scorers: BTreeMap<&'a str, Box<dyn Scorer<u64> + 'a>>,
+    strategy: Option<IntersectStrategy>,
+    limit: usize,
+    n_postings: usize,
+}
+
+impl<'a> Server<'a> {
+    /// Construct a server. Query terms are rewritten to index terms with
+    /// `index_term`, see `Fields::index_term`. `scorers` are the available
+    /// ranking models by name. Conjunctions are intersected using
+    /// `strategy`. `limit` is the default number of results per response.
+    pub fn new(index: &'a dyn InvertedIndex<u64>,
+               metadata: &'a dyn Metadata,
+               index_term: &'a dyn Fn(&str) -> Option<String>,
+               scorers: BTreeMap<&'a str, Box<dyn Scorer<u64> + 'a>>,
+               strategy: Option<IntersectStrategy>,
+               limit: usize)
+               -> Self {
+        Server {
+            index,
+            metadata,
+            index_term,
+            scorers,
+            strategy,
+            limit,
+            n_postings: index.iter().map(|(_, posting)| posting.len()).sum(),
+        }
+    }
+
+    /// Read a request from a client and write the response.
+    pub fn serve(&self, stream: &TcpStream) -> Result<(), HttpError> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut reader = BufReader::new(stream.take(MAX_REQUEST_LEN));
+        let (status, body) = match Request::from_buf_read(&mut reader) {
+            Ok(request) => {
+                let start = Instant::now();
+                let (status, body) = self.handle(&request);
+                stderr!("{} {} {} ({:.3} ms)",
+                        request.method(),
+                        request.path(),
+                        status,
+                        start.elapsed().as_secs_f64() * 1000.);
+                (status, body)
+            }
+            Err(HttpError::Malformed(request)) => {
+                (400, json!({"error": format!("Malformed request: {}", request)}))
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut writer = stream;
+        write_json_response(&mut writer, status, &body.to_string())?;
+
+        Ok(())
+    }
+
+    /// Dispatch a request to its endpoint. Returns the status code and the
+    /// response body.
+    pub fn handle(&self, request: &Request) -> (u16, Value) {
+        if request.method() != "GET" {
+            return (405, json!({"error": format!("Unsupported method: {}", request.method())}));
+        }
+
+        let result = match request.path() {
+            "/search" => self.search(request),
+            "/rank" => self.rank(request),
+            "/terms" => self.terms(request),
+            "/stats" => Ok(self.stats()),
+            path => return (404, json!({"error": format!("Unknown endpoint: {}", path)})),
+        };
+
+        match result {
+            Ok(body) => (200, body),
+            Err(err) => (400, json!({"error": err})),
+        }
+    }
+
+    /// Boolean search.
+    fn search(&self, request: &Request) -> Result<Value, String> {
+        let start = Instant::now();
+
+        let (query, mut filters) = Query::parse_with_filters(self.query(request)?)
+            .map_err(|err| format!("Invalid query: {}", err))?;
+        filters.extend(self.filters(request)?);
+        let query = query.filter_map_terms(&self.index_term)
+            .ok_or_else(|| "Query only consists of stopwords".to_owned())?;
+
+        for pattern in query.wildcards() {
+            if self.index.expand_wildcard(pattern).len() > MAX_WILDCARD_TERMS {
+                return Err(format!("Wildcard matches more than {} terms: {}",
+                                   MAX_WILDCARD_TERMS,
+                                   pattern));
+            }
+        }
+
+        let (result, explain) = QueryPlan::new(&query, self.index)
+            .and_then(|plan| plan.execute(self.index, self.strategy))
+            .map_err(|err| format!("Cannot evaluate query: {}", err))?;
+
+        let result: Vec<_> = result.iter()
+            .filter(|&&doc| filters.iter().all(|filter| filter.matches(self.metadata, doc)))
+            .map(|&doc| (doc, None))
+            .collect();
+
+        let mut response = self.page(request, &result, result.len())?;
+        response["query"] = json!(query.to_string());
+        if request.param("explain").is_some() {
+            response["explain"] = explain_json(&explain);
+        }
+        response["ms"] = json!(start.elapsed().as_secs_f64() * 1000.);
+
+        Ok(response)
+    }
+
+    /// Ranked search.
+    fn rank(&self, request: &Request) -> Result<Value, String> {
+        let start = Instant::now();
+
+        let frequencies = self.index
+            .frequencies()
+            .ok_or_else(|| "Ranked retrieval requires an index with term frequencies".to_owned())?;
+
+        // Use BM25 by default, if document lengths are available.
+        let model = match request.param("model") {
+            Some(model) => model,
+            None if self.scorers.contains_key("bm25") => "bm25",
+            None => "tfidf",
+        };
+        let scorer = self.scorers
+            .get(model)
+            .ok_or_else(|| format!("Unavailable ranking model: {}", model))?;
+
+        let (query, mut filters) = split_filters(self.query(request)?);
+        filters.extend(self.filters(request)?);
+        let terms: Vec<_> = query.split_whitespace().filter_map(self.index_term).collect();
+        let terms: Vec<_> = terms.iter().map(String::as_str).collect();
+        if terms.is_empty() {
+            return Err("Query only consists of stopwords".to_owned());
+        }
+
+        let mut scores = scorer.score(frequencies, &terms);
+        scores.retain(|&doc, _| filters.iter().all(|filter| filter.matches(self.metadata, doc)));
+
+        // Only the documents up to the end of the page are ranked.
+        let total = scores.len();
+        let offset: usize = param(request, "offset", 0)?;
+        let end = offset.saturating_add(param(request, "limit", self.limit)?);
+        let result: Vec<_> = top_k(scores, end)
+            .into_iter()
+            .map(|(doc, score)| (doc, Some(score)))
+            .collect();
+
+        let mut response = self.page(request, &result, total)?;
+        response["query"] = json!(terms);
+        response["model"] = json!(model);
+        response["ms"] = json!(start.elapsed().as_secs_f64() * 1000.);
+
+        Ok(response)
+    }
+
+    /// Term statistics. With `term`, the statistics of a query term, which
+    /// is normalized as during indexing. Otherwise, the statistics of the
+    /// index terms that start with `prefix`.
+    fn terms(&self, request: &Request) -> Result<Value, String> {
+        if let Some(term) = request.param("term") {
+            let index_term = (self.index_term)(term)
+                .ok_or_else(|| format!("Term is a stopword: {}", term))?;
+            let mut stats = self.term_stats(&index_term);
+            stats["query_term"] = json!(term);
+            return Ok(json!({"terms": [stats]}));
+        }
+
+        let prefix = request.param("prefix").unwrap_or("");
+        let limit = param(request, "limit", self.limit)?;
+        let terms: Vec<_> = self.index
+            .prefix(prefix)
+            .take(limit)
+            .map(|(term, _)| self.term_stats(term))
+            .collect();
+
+        Ok(json!({"terms": terms}))
+    }
+
+    /// Index statistics and the available ranking models.
+    fn stats(&self) -> Value {
+        json!({
+            "terms": self.index.len(),
+            "postings": self.n_postings,
+            "frequencies": self.index.frequencies().is_some(),
+            "positions": self.index.positional().is_some(),
+            "fields": self.metadata.fields(),
+            "models": self.scorers.keys().collect::<Vec<_>>(),
+            "intersect": self.strategy.map(|strategy| strategy.to_string()),
+        })
+    }
+
+    /// Get the query from the `q` parameter.
+    fn query<'r>(&self, request: &'r Request) -> Result<&'r str, String> {
+        request.param("q").ok_or_else(|| "Missing parameter: q".to_owned())
+    }
+
+    /// Get the metadata filters from the `where` parameters.
+    fn filters(&self, request: &Request) -> Result<Vec<Filter>, String> {
+        request.params("where").into_iter().map(str::parse).collect()
+    }
+
+    /// Select the results in the page given by the `offset` and `limit`
+    /// parameters. `total` is the number of matching documents, ranked
+    /// results only include the documents up to the end of the page.
+    fn page(&self,
+            request: &Request,
+            result: &[(u64, Option<f64>)],
+            total: usize)
+            -> Result<Value, String> {
+        let offset = param(request, "offset", 0)?;
+        let limit = param(request, "limit", self.limit)?;
+
+        let results: Vec<_> = result.iter()
+            .skip(offset)
+            .take(limit)
+            .map(|&(doc, score)| result_json(self.metadata, doc, score))
+            .collect();
+
+        Ok(json!({"total": total, "offset": offset, "results": results}))
+    }
+
+    /// The document frequency of a term and, for indexes with term
+    /// frequencies, its collection frequency.
+    fn term_stats(&self, term: &str) -> Value {
+        let mut stats = json!({
+            "term": term,
+            "df": self.index.doc_freq(term),
+        });
+
+        if let Some(frequencies) = self.index.frequencies() {
+            let cf: u64 = frequencies.term_frequencies(term)
+                .map(|posting| posting.iter().map(|(_, freq)| freq as u64).sum())
+                .unwrap_or(0);
+            stats["cf"] = json!(cf);
+        }
+
+        stats
+    }
+}
+
+/// Parse an optional request parameter.
+fn param<T>(request: &Request, name: &str, default: T) -> Result<T, String>
+    where T: FromStr
+{
+    match request.param(name) {
+        Some(value) => value.parse().map_err(|_| format!("Invalid value of {}: {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+fn explain_json(explain: &Explain) -> Value {
+    let children: Vec<_> = explain.children().iter().map(explain_json).collect();
+    json!({
+        "operation": explain.operation(),
+        "algorithm": explain.algorithm(),
+        "estimate": explain.estimate(),
+        "size": explain.size(),
+        "children": children,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::{Cursor, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use serde_json::{self, Value};
+
+    use super::{MAX_WILDCARD_TERMS, Server};
+    use super::super::{FrequencyMemoryIndex, InvertedIndexFromText, InvertedIndexMut,
+                       MemoryIndex, MemoryMetadata, Request, Scorer, TfIdf};
+
+    #[test]
+    fn serve_localhost() {
+        let index = FrequencyMemoryIndex::from_text(Cursor::new("city\t1:2 3:1\nnew\t1:1 2:1 \
+                                                                 3:1\nyork\t1:1 2:1\n"))
+            .unwrap();
+        let mut metadata = MemoryMetadata::new();
+        metadata.set(1, "title", "New York City");
+        metadata.set(2, "title", "New York");
+        metadata.set(3, "title", "New City");
+        let index_term = |term: &str| Some(term.to_lowercase());
+        let mut scorers: BTreeMap<_, Box<dyn Scorer<u64>>> = BTreeMap::new();
+        scorers.insert("tfidf", Box::new(TfIdf::new(&index)));
+        let server = Server::new(&index, &metadata, &index_term, scorers, None, 10);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let paths = ["/search?q=New+AND+york&limit=1",
+                     "/rank?q=city+york&offset=1&limit=1",
+                     "/terms?prefix=ne",
+                     "/stats",
+                     "/unknown"];
+
+        // Requests are sent from another thread, the server answers them
+        // one at a time.
+        let client = thread::spawn(move || {
+            paths.iter()
+                .map(|path| {
+                    let mut stream = TcpStream::connect(address).unwrap();
+                    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+                    let mut response = String::new();
+                    stream.read_to_string(&mut response).unwrap();
+                    response
+                })
+                .collect::<Vec<_>>()
+        });
+
+        for _ in 0..paths.len() {
+            let (stream, _) = listener.accept().unwrap();
+            server.serve(&stream).unwrap();
+        }
+
+        let responses = client.join().unwrap();
+        let body = |response: &str| -> Value {
+            let (_, body) = response.split_once("\r\n\r\n").unwrap();
+            serde_json::from_str(body).unwrap()
+        };
+
+        assert!(responses[0].starts_with("HTTP/1.1 200 OK\r\n"));
+        let search = body(&responses[0]);
+        assert_eq!(search["total"], 2);
+        assert_eq!(search["results"],
+                   json!([{"doc": 1, "title": "New York City",
+                           "metadata": {"title": "New York City"}}]));
+
+        let rank = body(&responses[1]);
+        assert_eq!(rank["model"], "tfidf");
+        assert_eq!(rank["total"], 3);
+        assert_eq!(rank["results"].as_array().unwrap().len(), 1);
+
+        assert_eq!(body(&responses[2])["terms"],
+                   json!([{"term": "new", "df": 3, "cf": 3}]));
+
+        let stats = body(&responses[3]);
+        assert_eq!(stats["terms"], 3);
+        assert_eq!(stats["postings"], 7);
+        assert_eq!(stats["models"], json!(["tfidf"]));
+
+        assert!(responses[4].starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn wildcard_limit() {
+        let mut index = MemoryIndex::new();
+        for term in 0..MAX_WILDCARD_TERMS + 1 {
+            index.add_term(&format!("t{}", term), 1u64);
+        }
+        let metadata = MemoryMetadata::new();
+        let index_term = |term: &str| Some(term.to_owned());
+        let server = Server::new(&index, &metadata, &index_term, BTreeMap::new(), None, 10);
+
+        let search = |query: &str| {
+            let request = format!("GET /search?q={} HTTP/1.1\r\n\r\n", query);
+            server.handle(&Request::from_buf_read(&mut Cursor::new(request)).unwrap())
+        };
+        assert_eq!(search("t1*").0, 200);
+        assert_eq!(search("t*").0, 400);
+        assert_eq!(search("t1+OR+*").0, 400);
+    }
+}
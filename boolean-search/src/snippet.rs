@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use conllx;
+use conllx::{ReadSentence, Token};
+
+use super::{DocIdExtractor, Fields, IndexingError, Normalizer};
+
+/// Errors for reading source documents.
+#[derive(Debug)]
+pub enum DocumentStoreError {
+    Conllx(conllx::Error),
+    Indexing(IndexingError),
+    Io(io::Error),
+}
+
+impl From<conllx::Error> for DocumentStoreError {
+    fn from(err: conllx::Error) -> DocumentStoreError {
+        DocumentStoreError::Conllx(err)
+    }
+}
+
+impl From<IndexingError> for DocumentStoreError {
+    fn from(err: IndexingError) -> DocumentStoreError {
+        DocumentStoreError::Indexing(err)
+    }
+}
+
+impl From<io::Error> for DocumentStoreError {
+    fn from(err: io::Error) -> DocumentStoreError {
+        DocumentStoreError::Io(err)
+    }
+}
+
+impl fmt::Display for DocumentStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DocumentStoreError::Conllx(ref err) => write!(f, "{}", err),
+            DocumentStoreError::Indexing(ref err) => write!(f, "{}", err),
+            DocumentStoreError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A keyword in context (KWIC): a matched term with the tokens to its left
+/// and right.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snippet {
+    left: String,
+    keyword: String,
+    right: String,
+}
+
+impl Snippet {
+    /// Get the tokens before the keyword.
+    pub fn left(&self) -> &str {
+        &self.left
+    }
+
+    /// Get the keyword. Consecutive matched tokens, such as the tokens of
+    /// a phrase, form a single keyword.
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// Get the tokens after the keyword.
+    pub fn right(&self) -> &str {
+        &self.right
+    }
+}
+
+impl fmt::Display for Snippet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.left.is_empty() {
+            write!(f, "{} ", self.left)?;
+        }
+
+        write!(f, "[{}]", self.keyword)?;
+
+        if !self.right.is_empty() {
+            write!(f, " {}", self.right)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The source documents of an index, to show matched terms in context.
+/// Only the byte offsets of the sentences of each document are kept in
+/// memory, the tokens are read from the source corpus when they are
+/// needed.
+pub struct DocumentStore<R> {
+    reader: RefCell<R>,
+    docs: HashMap<u64, Vec<u64>>,
+}
+
+impl<R> DocumentStore<R>
+    where R: BufRead + Seek
+{
+    /// Read the sentence offsets of the documents in a CoNLL-X corpus. The
+    /// document identifiers should be extracted in the same way as when
+    /// the index was created.
+    pub fn from_conllx(mut reader: R,
+                       extractor: &mut DocIdExtractor)
+                       -> Result<Self, DocumentStoreError> {
+        let mut docs: HashMap<u64, Vec<u64>> = HashMap::new();
+
+        let start = reader.stream_position()?;
+        let mut offset_reader = OffsetReader::new(reader, start);
+        loop {
+            let offset = offset_reader.offset;
+            let sentence = match conllx::Reader::new(&mut offset_reader).read_sentence()? {
+                Some(sentence) => sentence,
+                None => break,
+            };
+
+            if let Some(doc) = extractor.doc_id(&sentence)? {
+                docs.entry(doc).or_default().push(offset);
+            }
+        }
+
+        Ok(DocumentStore {
+            reader: RefCell::new(offset_reader.inner),
+            docs,
+        })
+    }
+
+    /// Get the tokens of a document, or `None` if the corpus does not
+    /// contain the document.
+    pub fn tokens(&self, doc: u64) -> Result<Option<Vec<Token>>, DocumentStoreError> {
+        let offsets = match self.docs.get(&doc) {
+            Some(offsets) => offsets,
+            None => return Ok(None),
+        };
+
+        let mut reader = self.reader.borrow_mut();
+        let mut tokens = Vec::new();
+        for &offset in offsets {
+            reader.seek(SeekFrom::Start(offset))?;
+            if let Some(sentence) = conllx::Reader::new(&mut *reader).read_sentence()? {
+                tokens.extend(sentence);
+            }
+        }
+
+        Ok(Some(tokens))
+    }
+
+    /// Get snippets of the tokens of a document that have one of the
+    /// given index terms, with `window` tokens of context on both sides.
+    /// Token terms are obtained in the same way as during indexing, using
+    /// `fields` and `normalizer`. At most `max_snippets` snippets are
+    /// returned.
+    pub fn snippets(&self,
+                    doc: u64,
+                    terms: &HashSet<String>,
+                    fields: &Fields,
+                    normalizer: &dyn Normalizer,
+                    window: usize,
+                    max_snippets: usize)
+                    -> Result<Vec<Snippet>, DocumentStoreError> {
+        let tokens = match self.tokens(doc)? {
+            Some(tokens) => tokens,
+            None => return Ok(Vec::new()),
+        };
+
+        let matches: Vec<bool> = tokens.iter()
+            .map(|token| {
+                fields.terms(token, normalizer)
+                    .map(|token_terms| token_terms.iter().any(|term| terms.contains(term.as_ref())))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        let mut snippets = Vec::new();
+        let mut idx = 0;
+        while idx < tokens.len() && snippets.len() < max_snippets {
+            if !matches[idx] {
+                idx += 1;
+                continue;
+            }
+
+            let start = idx;
+            while idx < tokens.len() && matches[idx] {
+                idx += 1;
+            }
+
+            snippets.push(Snippet {
+                left: join_forms(&tokens[start.saturating_sub(window)..start]),
+                keyword: join_forms(&tokens[start..idx]),
+                right: join_forms(&tokens[idx..(idx + window).min(tokens.len())]),
+            });
+        }
+
+        Ok(snippets)
+    }
+}
+
+/// A buffered reader that tracks the byte offset of the data that was
+/// consumed, since `conllx::Reader` does not expose the offsets of
+/// sentences.
+struct OffsetReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R> OffsetReader<R> {
+    fn new(inner: R, offset: u64) -> Self {
+        OffsetReader { inner, offset }
+    }
+}
+
+impl<R> Read for OffsetReader<R>
+    where R: Read
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> BufRead for OffsetReader<R>
+    where R: BufRead
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.offset += amt as u64;
+        self.inner.consume(amt)
+    }
+}
+
+fn join_forms(tokens: &[Token]) -> String {
+    let forms: Vec<_> = tokens.iter().map(|token| token.form().unwrap_or("_")).collect();
+    forms.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    use super::DocumentStore;
+    use super::super::{DocIdExtractor, DocIdPolicy, Fields, Lowercase, Pipeline};
+
+    static CORPUS: &str = "1\tNew\tnew\t_\t_\t1\t0\tROOT\t_\t_\n\
+                           2\tYork\tyork\t_\t_\t1\t0\tROOT\t_\t_\n\
+                           3\tis\tbe\t_\t_\t1\t0\tROOT\t_\t_\n\n\
+                           1\ta\ta\t_\t_\t1\t0\tROOT\t_\t_\n\
+                           2\tcity\tcity\t_\t_\t1\t0\tROOT\t_\t_\n\n\
+                           1\tYork\tyork\t_\t_\t2\t0\tROOT\t_\t_\n";
+
+    #[test]
+    fn kwic_snippets() {
+        let mut extractor = DocIdExtractor::new(None, DocIdPolicy::Fail);
+        let store = DocumentStore::from_conllx(Cursor::new(CORPUS), &mut extractor).unwrap();
+        assert_eq!(store.tokens(1).unwrap().unwrap().len(), 5);
+        assert_eq!(store.tokens(2).unwrap().unwrap()[0].form(), Some("York"));
+        assert!(store.tokens(3).unwrap().is_none());
+
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Lowercase);
+        let terms: HashSet<_> = vec!["new".to_owned(), "york".to_owned(), "city".to_owned()]
+            .into_iter()
+            .collect();
+
+        let snippets = store.snippets(1, &terms, &Fields::default(), &pipeline, 1, 10).unwrap();
+        let snippets: Vec<_> = snippets.iter().map(ToString::to_string).collect();
+        assert_eq!(snippets, vec!["[New York] is", "a [city]"]);
+
+        assert_eq!(store.snippets(2, &terms, &Fields::default(), &pipeline, 1, 10).unwrap().len(),
+                   1);
+        assert!(store.snippets(3, &terms, &Fields::default(), &pipeline, 1, 10)
+            .unwrap()
+            .is_empty());
+    }
+}
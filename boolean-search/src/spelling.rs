@@ -0,0 +1,189 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use super::InvertedIndex;
+
+/// A spelling suggestion for an unknown term.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Suggestion {
+    term: String,
+    distance: usize,
+    doc_freq: usize,
+}
+
+impl Suggestion {
+    /// Get the suggested term.
+    pub fn term(&self) -> &str {
+        &self.term
+    }
+
+    /// Get the edit distance between the unknown term and the suggestion.
+    pub fn distance(&self) -> usize {
+        self.distance
+    }
+
+    /// Get the number of documents that contain the suggested term.
+    pub fn doc_freq(&self) -> usize {
+        self.doc_freq
+    }
+}
+
+/// Spelling corrector over the term dictionary of an index. Candidate
+/// corrections are retrieved using a bigram index of the terms and then
+/// ranked by their Levenshtein distance and document frequency.
+pub struct SpellingCorrector {
+    terms: Vec<(String, usize)>,
+    bigrams: HashMap<String, Vec<usize>>,
+}
+
+impl SpellingCorrector {
+    /// Construct a spelling corrector from the terms of an index.
+    pub fn from_index<N>(index: &dyn InvertedIndex<N>) -> Self
+        where N: Clone + Ord
+    {
+        let mut terms = Vec::with_capacity(index.len());
+        let mut bigrams: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (idx, (term, posting)) in index.iter().enumerate() {
+            for bigram in term_bigrams(term) {
+                bigrams.entry(bigram).or_default().push(idx);
+            }
+
+            terms.push((term.to_owned(), posting.len()));
+        }
+
+        SpellingCorrector { terms, bigrams }
+    }
+
+    /// Get the terms within edit distance `max_distance` of `term`. The
+    /// suggestions are sorted by increasing edit distance and decreasing
+    /// document frequency.
+    pub fn suggest(&self, term: &str, max_distance: usize) -> Vec<Suggestion> {
+        // Every edit operation removes at most two of the bigrams of the
+        // term, so terms that share fewer bigrams cannot be within the
+        // maximum distance. If no bigrams need to be shared, as for short
+        // terms, all terms are candidates: `ab` and `ba` are within
+        // distance 2 without sharing a bigram.
+        let bigrams = term_bigrams(term);
+        let min_overlap = bigrams.len().saturating_sub(2 * max_distance);
+
+        let candidates: Vec<_> = if min_overlap == 0 {
+            (0..self.terms.len()).collect()
+        } else {
+            let mut overlap = HashMap::new();
+            for bigram in &bigrams {
+                for &idx in self.bigrams.get(bigram).into_iter().flatten() {
+                    *overlap.entry(idx).or_insert(0) += 1;
+                }
+            }
+
+            overlap.into_iter()
+                .filter(|&(_, count)| count >= min_overlap)
+                .map(|(idx, _)| idx)
+                .collect()
+        };
+
+        let mut suggestions: Vec<_> = candidates.into_iter()
+            .filter_map(|idx| {
+                let (ref candidate, doc_freq) = self.terms[idx];
+                let distance = levenshtein(term, candidate);
+                if distance <= max_distance && candidate != term {
+                    Some(Suggestion {
+                        term: candidate.clone(),
+                        distance,
+                        doc_freq,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        suggestions.sort_by(|a, b| {
+            (a.distance, Reverse(a.doc_freq), &a.term)
+                .cmp(&(b.distance, Reverse(b.doc_freq), &b.term))
+        });
+
+        suggestions
+    }
+}
+
+/// Get the character bigrams of a term, with markers for the start and
+/// end of the term.
+fn term_bigrams(term: &str) -> HashSet<String> {
+    let chars: Vec<_> = Some('$').into_iter().chain(term.chars()).chain(Some('$')).collect();
+    chars.windows(2).map(|bigram| bigram.iter().collect()).collect()
+}
+
+/// Compute the Levenshtein distance between two strings in characters.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+
+    let mut prev: Vec<_> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == cb { 0 } else { 1 };
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+
+        ::std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpellingCorrector, levenshtein};
+    use super::super::{InvertedIndexMut, MemoryIndex};
+
+    #[test]
+    fn levenshtein_distance() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("flaw", "lawn"), 2);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut index = MemoryIndex::new();
+        index.add_postings_list("city", vec![1u32, 2, 3]);
+        index.add_postings_list("cite", vec![4u32]);
+        index.add_postings_list("pity", vec![5u32]);
+        index.add_postings_list("new", vec![1u32]);
+        index.add_postings_list("york", vec![1u32]);
+
+        let corrector = SpellingCorrector::from_index(&index);
+
+        let suggestions: Vec<_> = corrector.suggest("cty", 2)
+            .iter()
+            .map(|s| (s.term().to_owned(), s.distance(), s.doc_freq()))
+            .collect();
+        assert_eq!(suggestions,
+                   vec![("city".to_owned(), 1, 3),
+                        ("cite".to_owned(), 2, 1),
+                        ("pity".to_owned(), 2, 1)]);
+
+        assert_eq!(corrector.suggest("yrok", 2)[0].term(), "york");
+        assert!(corrector.suggest("city", 0).is_empty());
+        assert!(corrector.suggest("boston", 2).is_empty());
+
+        // Short terms can be corrected to terms without shared bigrams.
+        let mut index = MemoryIndex::new();
+        index.add_postings_list("ba", vec![1u32]);
+        index.add_postings_list("city", vec![1u32]);
+        let corrector = SpellingCorrector::from_index(&index);
+        let suggestions: Vec<_> = corrector.suggest("ab", 2)
+            .iter()
+            .map(|s| s.term().to_owned())
+            .collect();
+        assert_eq!(suggestions, vec!["ba"]);
+    }
+}
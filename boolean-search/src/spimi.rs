@@ -0,0 +1,340 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use binary_heap::BinaryHeap;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use super::codec::{read_vbyte, write_vbyte};
+use super::index::posting_from_ref;
+use super::union_all;
+
+/// Estimated memory use of a dictionary entry, excluding the term and its
+/// documents.
+const ENTRY_OVERHEAD: usize = 64;
+
+/// Counter to give the runs of every indexer in a process unique names.
+static N_INDEXERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Single-pass in-memory indexing (SPIMI). Postings lists are accumulated
+/// in memory until the (estimated) memory use exceeds a budget. The
+/// postings lists are then written as a sorted run to disk. When indexing
+/// is finished, the runs are merged into the final index.
+///
+/// Since only the runs are merged, the corpus can be much larger than the
+/// memory budget.
+pub struct SpimiIndexer<N> {
+    budget: usize,
+    dir: PathBuf,
+    id: usize,
+    terms: HashMap<String, Vec<N>>,
+    mem_used: usize,
+    runs: Vec<PathBuf>,
+}
+
+impl<N> SpimiIndexer<N>
+    where N: Clone + FromPrimitive + Ord + ToPrimitive
+{
+    /// Construct an indexer that uses at most approximately `budget` bytes
+    /// for postings lists. Runs are written to `dir`.
+    pub fn new<P>(dir: P, budget: usize) -> Self
+        where P: AsRef<Path>
+    {
+        SpimiIndexer {
+            budget,
+            dir: dir.as_ref().to_owned(),
+            id: N_INDEXERS.fetch_add(1, Ordering::SeqCst),
+            terms: HashMap::new(),
+            mem_used: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Add a term-docid pair. Documents are expected to be added in
+    /// ascending order, but this is not required.
+    pub fn add_term(&mut self, term: &str, doc: N) -> io::Result<()> {
+        if !self.terms.contains_key(term) {
+            self.terms.insert(term.to_owned(), Vec::new());
+            self.mem_used += term.len() + ENTRY_OVERHEAD;
+        }
+
+        let docs = self.terms.get_mut(term).unwrap();
+        if docs.last() != Some(&doc) {
+            docs.push(doc);
+            self.mem_used += mem::size_of::<N>();
+        }
+
+        if self.mem_used > self.budget {
+            self.write_run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of runs that were written to disk.
+    pub fn n_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Finish indexing. The function `add` is called for every term with
+    /// its postings list, in term order.
+    pub fn finish<F>(mut self, mut add: F) -> io::Result<()>
+        where F: FnMut(&str, Vec<N>) -> io::Result<()>
+    {
+        // Everything fits in memory, no need to merge runs.
+        if self.runs.is_empty() {
+            for (term, docs) in sorted_terms(mem::take(&mut self.terms)) {
+                add(&term, docs)?;
+            }
+
+            return Ok(());
+        }
+
+        self.write_run()?;
+
+        let mut readers = Vec::new();
+        for path in &self.runs {
+            readers.push(RunReader::new(BufReader::new(File::open(path)?)));
+        }
+
+        merge_runs(readers, |term, docs| {
+            let docs = docs.into_iter()
+                .map(|doc| {
+                    N::from_u64(doc).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData,
+                                       "Document identifier out of range")
+                    })
+                })
+                .collect::<io::Result<_>>()?;
+            add(term, docs)
+        })
+    }
+
+    fn write_run(&mut self) -> io::Result<()> {
+        let path = self.dir.join(format!("boolean-search-run-{}-{}-{}",
+                                         process::id(),
+                                         self.id,
+                                         self.runs.len()));
+        // Register the run first, so that it is removed on failure.
+        self.runs.push(path.clone());
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let mut buf = Vec::new();
+
+        for (term, docs) in sorted_terms(mem::take(&mut self.terms)) {
+            buf.clear();
+            let mut prev = 0;
+            for doc in &docs {
+                let doc = doc.to_u64().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData,
+                                   "Document identifier does not fit in u64")
+                })?;
+                write_vbyte(doc - prev, &mut buf);
+                prev = doc;
+            }
+
+            writer.write_u32::<LittleEndian>(term.len() as u32)?;
+            writer.write_all(term.as_bytes())?;
+            writer.write_u64::<LittleEndian>(docs.len() as u64)?;
+            writer.write_u64::<LittleEndian>(buf.len() as u64)?;
+            writer.write_all(&buf)?;
+        }
+
+        writer.flush()?;
+        self.mem_used = 0;
+
+        Ok(())
+    }
+}
+
+impl<N> Drop for SpimiIndexer<N> {
+    fn drop(&mut self) {
+        for path in &self.runs {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Sort the terms and their postings lists.
+fn sorted_terms<N>(terms: HashMap<String, Vec<N>>) -> Vec<(String, Vec<N>)>
+    where N: Ord
+{
+    let mut terms: Vec<_> = terms.into_iter().collect();
+    terms.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for &mut (_, ref mut docs) in &mut terms {
+        docs.sort();
+        docs.dedup();
+    }
+
+    terms
+}
+
+/// Merge sorted runs using a k-way merge on terms. Postings lists of a
+/// term that occurs in multiple runs are combined.
+fn merge_runs<R, F>(mut readers: Vec<RunReader<R>>, mut add: F) -> io::Result<()>
+    where R: Read,
+          F: FnMut(&str, Vec<u64>) -> io::Result<()>
+{
+    // Heap of the next term of every run, smallest term on top. The
+    // postings list of the next term is kept in `pending`.
+    let mut heap = BinaryHeap::new();
+    let mut pending = Vec::with_capacity(readers.len());
+    for (run, reader) in readers.iter_mut().enumerate() {
+        match reader.next_entry()? {
+            Some((term, docs)) => {
+                heap.insert(Reverse((term, run)));
+                pending.push(docs);
+            }
+            None => pending.push(Vec::new()),
+        }
+    }
+
+    while let Some(Reverse((term, run))) = heap.pop() {
+        let mut postings = vec![mem::take(&mut pending[run])];
+        advance_run(&mut readers[run], run, &mut heap, &mut pending)?;
+
+        // Collect the postings lists of the same term in other runs.
+        loop {
+            let run = match heap.peek() {
+                Some(&Reverse((ref next_term, run))) if *next_term == term => run,
+                _ => break,
+            };
+
+            heap.pop();
+            postings.push(mem::take(&mut pending[run]));
+            advance_run(&mut readers[run], run, &mut heap, &mut pending)?;
+        }
+
+        let docs = if postings.len() == 1 {
+            postings.pop().unwrap()
+        } else {
+            let postings: Vec<_> = postings.iter().map(|docs| posting_from_ref(docs)).collect();
+            union_all(&postings).as_slice().to_vec()
+        };
+
+        add(&term, docs)?;
+    }
+
+    Ok(())
+}
+
+fn advance_run<R>(reader: &mut RunReader<R>,
+                  run: usize,
+                  heap: &mut BinaryHeap<Reverse<(String, usize)>>,
+                  pending: &mut [Vec<u64>])
+                  -> io::Result<()>
+    where R: Read
+{
+    if let Some((term, docs)) = reader.next_entry()? {
+        heap.insert(Reverse((term, run)));
+        pending[run] = docs;
+    }
+
+    Ok(())
+}
+
+/// Sequential reader for runs.
+struct RunReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R> RunReader<R>
+    where R: Read
+{
+    fn new(reader: R) -> Self {
+        RunReader {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    fn next_entry(&mut self) -> io::Result<Option<(String, Vec<u64>)>> {
+        let term_len = match self.reader.read_u32::<LittleEndian>() {
+            Ok(len) => len as usize,
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut term = vec![0; term_len];
+        self.reader.read_exact(&mut term)?;
+        let term = String::from_utf8(term)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Term is not valid UTF-8"))?;
+
+        let n_docs = self.reader.read_u64::<LittleEndian>()? as usize;
+        let n_bytes = self.reader.read_u64::<LittleEndian>()? as usize;
+
+        self.buf.resize(n_bytes, 0);
+        self.reader.read_exact(&mut self.buf)?;
+
+        let mut bytes = self.buf.as_slice();
+        let mut docs = Vec::with_capacity(n_docs);
+        let mut prev = 0;
+        for _ in 0..n_docs {
+            prev += read_vbyte(&mut bytes).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid postings list in run")
+            })?;
+            docs.push(prev);
+        }
+
+        Ok(Some((term, docs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::collections::BTreeMap;
+
+    use super::SpimiIndexer;
+
+    fn index(budget: usize) -> (Vec<(String, Vec<u32>)>, usize) {
+        let mut indexer = SpimiIndexer::new(env::temp_dir(), budget);
+
+        let docs = ["a b c", "b c d", "a a e", "e f b", "c"];
+        for (doc, text) in docs.iter().enumerate() {
+            for term in text.split_whitespace() {
+                indexer.add_term(term, doc as u32).unwrap();
+            }
+        }
+
+        // Documents do not have to be added in order.
+        indexer.add_term("a", 1).unwrap();
+
+        let n_runs = indexer.n_runs();
+
+        let mut terms = Vec::new();
+        indexer.finish(|term, docs| {
+                terms.push((term.to_owned(), docs));
+                Ok(())
+            })
+            .unwrap();
+
+        (terms, n_runs)
+    }
+
+    #[test]
+    fn spimi_runs_merge() {
+        let (in_memory, n_runs) = index(1 << 20);
+        assert_eq!(n_runs, 0);
+
+        let (external, n_runs) = index(0);
+        assert!(n_runs > 1);
+        assert_eq!(in_memory, external);
+
+        let check: BTreeMap<_, _> = in_memory.into_iter().collect();
+        assert_eq!(check["a"], vec![0, 1, 2]);
+        assert_eq!(check["b"], vec![0, 1, 3]);
+        assert_eq!(check["f"], vec![3]);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counter to give the temporary files of a process unique names.
+static N_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A buffered temporary file that is removed when it is dropped.
+pub struct TempFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl TempFile {
+    /// Create a temporary file in `dir`.
+    pub fn create<P>(dir: P) -> io::Result<Self>
+        where P: AsRef<Path>
+    {
+        let path = dir.as_ref().join(format!("boolean-search-tmp-{}-{}",
+                                             process::id(),
+                                             N_FILES.fetch_add(1, Ordering::SeqCst)));
+        let writer = BufWriter::new(File::create(&path)?);
+
+        Ok(TempFile { path, writer })
+    }
+
+    /// Copy the data that was written to the file to `writer`.
+    pub fn copy_to<W>(&mut self, writer: &mut W) -> io::Result<u64>
+        where W: Write
+    {
+        self.writer.flush()?;
+        io::copy(&mut File::open(&self.path)?, writer)
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A temporary directory that is removed with its contents when it is
+/// dropped.
+#[cfg(test)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+#[cfg(test)]
+impl TempDir {
+    /// Create a temporary directory in `dir`.
+    pub fn create<P>(dir: P) -> io::Result<Self>
+        where P: AsRef<Path>
+    {
+        let path = dir.as_ref().join(format!("boolean-search-tmp-{}-{}",
+                                             process::id(),
+                                             N_FILES.fetch_add(1, Ordering::SeqCst)));
+        fs::create_dir(&path)?;
+
+        Ok(TempDir { path })
+    }
+
+    /// Get the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
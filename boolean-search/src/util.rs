@@ -17,5 +17,5 @@ pub fn is_sorted_uniq<E>(r: &[E]) -> bool
         }
     }
 
-    return true;
-}
\ No newline at end of file
+    true
+}
@@ -0,0 +1,175 @@
+use std::ops::Bound;
+
+use trie::{ArrayTrie, TrieInsert, TriePrefixIter};
+
+use super::{FrequencyIndex, InvertedIndex, PositionalIndex, Posting};
+
+/// Inverted index wrapper that stores the term dictionary in a trie and a
+/// trie of reversed terms, to expand wildcard terms efficiently. Patterns
+/// with a literal prefix are expanded using the trie, patterns with only
+/// a literal suffix using the reverse trie.
+pub struct WildcardIndex<'a, N> {
+    index: Box<dyn InvertedIndex<N> + 'a>,
+    trie: ArrayTrie,
+    reverse_trie: ArrayTrie,
+}
+
+impl<'a, N> WildcardIndex<'a, N>
+    where N: Clone + Ord
+{
+    /// Construct the term tries of an index.
+    pub fn new(index: Box<dyn InvertedIndex<N> + 'a>) -> Self {
+        let mut trie = ArrayTrie::new();
+        let mut reverse_trie = ArrayTrie::new();
+
+        for (term, _) in index.iter() {
+            // The tries cannot store the empty string.
+            if !term.is_empty() {
+                trie.insert(term);
+                reverse_trie.insert(&reverse(term));
+            }
+        }
+
+        WildcardIndex {
+            index,
+            trie,
+            reverse_trie,
+        }
+    }
+}
+
+impl<'a, N> InvertedIndex<N> for WildcardIndex<'a, N>
+    where N: Clone + Ord
+{
+    fn iter<'b>(&'b self) -> Box<dyn Iterator<Item = (&'b str, Posting<'b, N>)> + 'b> {
+        self.index.iter()
+    }
+
+    fn range<'b>(&'b self,
+                 from: Bound<&str>,
+                 to: Bound<&str>)
+                 -> Box<dyn Iterator<Item = (&'b str, Posting<'b, N>)> + 'b> {
+        self.index.range(from, to)
+    }
+
+    fn expand_wildcard(&self, pattern: &str) -> Vec<String> {
+        let prefix = pattern.split('*').next().unwrap_or("");
+        let suffix = pattern.rsplit('*').next().unwrap_or("");
+
+        let candidates: Box<dyn Iterator<Item = String>> = if prefix.is_empty() &&
+                                                              !suffix.is_empty() {
+            Box::new(self.reverse_trie.prefix_iter(&reverse(suffix)).map(|term| reverse(&term)))
+        } else {
+            self.trie.prefix_iter(prefix)
+        };
+
+        let mut terms: Vec<_> = candidates.filter(|term| wildcard_match(pattern, term)).collect();
+        terms.sort();
+
+        terms
+    }
+
+    fn posting(&self, term: &str) -> Option<Posting<'_, N>> {
+        self.index.posting(term)
+    }
+
+    fn doc_freq(&self, term: &str) -> usize {
+        self.index.doc_freq(term)
+    }
+
+    fn intersect_terms(&self, terms: &[&str]) -> Option<Posting<'_, N>> {
+        self.index.intersect_terms(terms)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn positional(&self) -> Option<&dyn PositionalIndex<N>> {
+        self.index.positional()
+    }
+
+    fn frequencies(&self) -> Option<&dyn FrequencyIndex<N>> {
+        self.index.frequencies()
+    }
+}
+
+/// Returns `true` if a term matches a wildcard pattern, where `*` matches
+/// any sequence of characters.
+pub fn wildcard_match(pattern: &str, term: &str) -> bool {
+    let parts: Vec<_> = pattern.split('*').collect();
+
+    // A pattern without wildcards only matches itself.
+    if parts.len() == 1 {
+        return pattern == term;
+    }
+
+    let (prefix, suffix) = (parts[0], parts[parts.len() - 1]);
+    if term.len() < prefix.len() + suffix.len() || !term.starts_with(prefix) ||
+       !term.ends_with(suffix) {
+        return false;
+    }
+
+    // Match the literal parts between wildcards from left to right, taking
+    // the leftmost occurrence of each part.
+    let mut rest = &term[prefix.len()..term.len() - suffix.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+fn reverse(s: &str) -> String {
+    s.chars().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WildcardIndex, wildcard_match};
+    use super::super::{InvertedIndex, InvertedIndexMut, MemoryIndex, Query};
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(wildcard_match("comput*", "computer"));
+        assert!(wildcard_match("comput*", "comput"));
+        assert!(wildcard_match("*ization", "normalization"));
+        assert!(wildcard_match("re*ing", "reading"));
+        assert!(!wildcard_match("re*ing", "reing_"));
+        assert!(!wildcard_match("a*a", "a"));
+        assert!(wildcard_match("a*b*c", "aXbYbc"));
+        assert!(!wildcard_match("a*b*c", "acb"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("new", "newer"));
+    }
+
+    #[test]
+    fn wildcard_queries() {
+        let mut index = MemoryIndex::new();
+        index.add_postings_list("computer", vec![1u32, 3]);
+        index.add_postings_list("computing", vec![2u32]);
+        index.add_postings_list("normalization", vec![3u32, 4]);
+        index.add_postings_list("reading", vec![4u32, 5]);
+        index.add_postings_list("ring", vec![6u32]);
+
+        let tries = WildcardIndex::new(Box::new(index));
+
+        for &(pattern, terms) in &[("comput*", &["computer", "computing"][..]),
+                                   ("*ization", &["normalization"][..]),
+                                   ("re*ing", &["reading"][..]),
+                                   ("*in*", &["computing", "reading", "ring"][..]),
+                                   ("x*", &[][..])] {
+            assert_eq!(tries.expand_wildcard(pattern), terms);
+            assert_eq!(tries.index.expand_wildcard(pattern), terms);
+        }
+
+        let query = Query::parse("comput* AND NOT *ization").unwrap();
+        assert_eq!(query.evaluate(&tries).unwrap().as_slice(), &[1, 2]);
+
+        let query = Query::parse("re*ing OR *ization").unwrap();
+        assert_eq!(query.evaluate(&tries).unwrap().as_slice(), &[3, 4, 5]);
+    }
+}